@@ -0,0 +1,255 @@
+-- the schema as it was before versioned migrations. Databases created by earlier versions
+-- already have parts of it, so every statement is safe to repeat
+
+CREATE TABLE IF NOT EXISTS listings (
+    account TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    seed BIGINT NOT NULL,
+    mint TEXT NOT NULL,
+    funding_goal BIGINT NOT NULL,
+    pool_mint_supply NUMERIC NOT NULL,
+    funding_raised BIGINT NOT NULL,
+    available_tokens NUMERIC NOT NULL,
+    base_price DOUBLE PRECISION NOT NULL,
+    tokens_sold NUMERIC NOT NULL,
+    bump SMALLINT NOT NULL,
+    vault_bump SMALLINT NOT NULL,
+    mint_bump SMALLINT NOT NULL,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+ALTER TABLE listings ADD COLUMN IF NOT EXISTS slot BIGINT;
+
+ALTER TABLE listings ADD COLUMN IF NOT EXISTS write_version BIGINT;
+
+CREATE TABLE IF NOT EXISTS slots (
+    slot BIGINT PRIMARY KEY,
+    parent BIGINT,
+    status TEXT NOT NULL,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS slots_status_idx ON slots (status, slot);
+
+-- slots we never saw a status for (e.g. snapshot accounts at startup) count as rooted
+-- once a later slot has been rooted
+CREATE OR REPLACE FUNCTION slot_commitment(s BIGINT) RETURNS TEXT AS $$
+    SELECT COALESCE(
+        (SELECT status FROM slots WHERE slot = s),
+        CASE WHEN s <= (SELECT max(slot) FROM slots WHERE status = 'rooted')
+            THEN 'rooted' ELSE 'processed' END
+    )
+$$ LANGUAGE sql STABLE;
+
+CREATE OR REPLACE VIEW confirmed_listings AS
+    SELECT * FROM listings WHERE slot_commitment(slot) IN ('confirmed', 'rooted');
+
+CREATE OR REPLACE VIEW finalized_listings AS
+    SELECT * FROM listings WHERE slot_commitment(slot) = 'rooted';
+
+-- tracked wallets with their current SOL balance in lamports
+CREATE TABLE IF NOT EXISTS wallets (
+    address TEXT PRIMARY KEY,
+    lamports BIGINT,
+    slot BIGINT,
+    write_version BIGINT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS sol_balance_history (
+    wallet TEXT NOT NULL REFERENCES wallets (address),
+    lamports BIGINT NOT NULL,
+    slot BIGINT NOT NULL,
+    write_version BIGINT NOT NULL,
+    recorded_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (wallet, slot, write_version)
+);
+
+-- amounts are u64, too large for BIGINT
+CREATE TABLE IF NOT EXISTS token_balances (
+    wallet TEXT NOT NULL REFERENCES wallets (address),
+    mint TEXT NOT NULL,
+    amount NUMERIC(20, 0) NOT NULL,
+    slot BIGINT NOT NULL,
+    write_version BIGINT NOT NULL,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (wallet, mint)
+);
+
+CREATE INDEX IF NOT EXISTS token_balances_mint_idx ON token_balances (mint) WHERE amount > 0;
+
+ALTER TABLE token_balances ADD COLUMN IF NOT EXISTS token_program TEXT NOT NULL DEFAULT 'spl-token';
+
+ALTER TABLE token_balances ADD COLUMN IF NOT EXISTS extensions JSONB NOT NULL DEFAULT '{}';
+
+-- mints of tokens held by tracked wallets or referenced by listings, extensions are
+-- only set for Token-2022
+CREATE TABLE IF NOT EXISTS mints (
+    mint TEXT PRIMARY KEY,
+    token_program TEXT NOT NULL,
+    extensions JSONB NOT NULL,
+    slot BIGINT NOT NULL,
+    write_version BIGINT NOT NULL,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+ALTER TABLE mints ADD COLUMN IF NOT EXISTS decimals SMALLINT;
+
+ALTER TABLE mints ADD COLUMN IF NOT EXISTS supply NUMERIC(20, 0);
+
+ALTER TABLE mints ADD COLUMN IF NOT EXISTS mint_authority TEXT;
+
+ALTER TABLE mints ADD COLUMN IF NOT EXISTS freeze_authority TEXT;
+
+-- raw token amount in whole tokens, e.g. 1500000 with 6 decimals is 1.5
+CREATE OR REPLACE FUNCTION ui_amount(amount NUMERIC, decimals SMALLINT) RETURNS NUMERIC AS $$
+    SELECT trim_scale(amount / 10::NUMERIC ^ decimals)
+$$ LANGUAGE sql IMMUTABLE;
+
+ALTER TABLE mints ADD COLUMN IF NOT EXISTS ui_supply NUMERIC
+    GENERATED ALWAYS AS (ui_amount(supply, decimals)) STORED;
+
+-- NULL until the mint has been seen, kept in sync by the mint and balance upserts
+ALTER TABLE token_balances ADD COLUMN IF NOT EXISTS ui_amount NUMERIC;
+
+ALTER TABLE token_balances ADD COLUMN IF NOT EXISTS delegate TEXT;
+
+ALTER TABLE token_balances ADD COLUMN IF NOT EXISTS delegated_amount NUMERIC(20, 0) NOT NULL DEFAULT 0;
+
+-- 'initialized' or 'frozen'
+ALTER TABLE token_balances ADD COLUMN IF NOT EXISTS state TEXT NOT NULL DEFAULT 'initialized';
+
+ALTER TABLE token_balances ADD COLUMN IF NOT EXISTS close_authority TEXT;
+
+CREATE TABLE IF NOT EXISTS token_account_history (
+    wallet TEXT NOT NULL REFERENCES wallets (address),
+    mint TEXT NOT NULL,
+    amount NUMERIC(20, 0) NOT NULL,
+    delegate TEXT,
+    delegated_amount NUMERIC(20, 0) NOT NULL,
+    state TEXT NOT NULL,
+    close_authority TEXT,
+    slot BIGINT NOT NULL,
+    write_version BIGINT NOT NULL,
+    recorded_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (wallet, mint, slot, write_version)
+);
+
+CREATE TABLE IF NOT EXISTS security_events (
+    id BIGSERIAL PRIMARY KEY,
+    wallet TEXT NOT NULL REFERENCES wallets (address),
+    mint TEXT NOT NULL,
+    event TEXT NOT NULL,
+    details JSONB NOT NULL,
+    slot BIGINT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS security_events_wallet_idx ON security_events (wallet, slot);
+
+-- Metaplex metadata and master editions of tracked mints
+CREATE TABLE IF NOT EXISTS nft_metadata (
+    mint TEXT PRIMARY KEY,
+    update_authority TEXT NOT NULL,
+    name TEXT NOT NULL,
+    symbol TEXT NOT NULL,
+    uri TEXT NOT NULL,
+    seller_fee_basis_points INTEGER NOT NULL,
+    creators JSONB NOT NULL,
+    primary_sale_happened BOOLEAN NOT NULL,
+    is_mutable BOOLEAN NOT NULL,
+    token_standard TEXT,
+    collection TEXT,
+    collection_verified BOOLEAN NOT NULL,
+    slot BIGINT NOT NULL,
+    write_version BIGINT NOT NULL,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS master_editions (
+    mint TEXT PRIMARY KEY,
+    supply NUMERIC(20, 0) NOT NULL,
+    max_supply NUMERIC(20, 0),
+    slot BIGINT NOT NULL,
+    write_version BIGINT NOT NULL,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+-- a holding is an NFT if its mint has 0 decimals and a supply of 1, or a master edition
+CREATE OR REPLACE VIEW nft_holdings AS
+    SELECT t.wallet, t.mint, t.amount, md.name, md.symbol, md.uri, md.token_standard,
+        md.collection, md.collection_verified, e.mint IS NOT NULL AS master_edition,
+        t.slot, t.updated_at, t.ui_amount
+    FROM token_balances t
+    LEFT JOIN mints m ON m.mint = t.mint
+    LEFT JOIN nft_metadata md ON md.mint = t.mint
+    LEFT JOIN master_editions e ON e.mint = t.mint
+    WHERE t.amount > 0
+        AND ((m.decimals = 0 AND m.supply = 1) OR e.mint IS NOT NULL);
+
+-- keyed by slot as well, a transaction can land in more than one fork
+CREATE TABLE IF NOT EXISTS transactions (
+    signature TEXT NOT NULL,
+    slot BIGINT NOT NULL,
+    transaction_index BIGINT,
+    fee BIGINT NOT NULL,
+    success BOOLEAN NOT NULL,
+    err TEXT,
+    signers TEXT[] NOT NULL,
+    account_keys TEXT[] NOT NULL,
+    log_messages TEXT[],
+    compute_units BIGINT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (signature, slot)
+);
+
+CREATE INDEX IF NOT EXISTS transactions_slot_idx ON transactions (slot);
+
+-- answers `WHERE account_keys @> ARRAY['<account>']`
+CREATE INDEX IF NOT EXISTS transactions_account_keys_idx ON transactions USING GIN (account_keys);
+
+CREATE TABLE IF NOT EXISTS blocks (
+    slot BIGINT PRIMARY KEY,
+    parent_slot BIGINT,
+    blockhash TEXT NOT NULL,
+    parent_blockhash TEXT,
+    block_time TIMESTAMPTZ,
+    block_height BIGINT,
+    rewards JSONB NOT NULL,
+    executed_transaction_count BIGINT,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS blocks_block_time_idx ON blocks (block_time);
+
+-- wall-clock time of a slot, skipped slots take the time of the closest earlier block
+CREATE OR REPLACE FUNCTION slot_time(s BIGINT) RETURNS TIMESTAMPTZ AS $$
+    SELECT block_time FROM blocks
+    WHERE slot <= s AND block_time IS NOT NULL
+    ORDER BY slot DESC LIMIT 1
+$$ LANGUAGE sql STABLE;
+
+-- users and programs tracked on top of the config, only read with `reload.tracked_entities`
+CREATE TABLE IF NOT EXISTS tracked_entities (
+    kind TEXT NOT NULL CHECK (kind IN ('user', 'program')),
+    address TEXT NOT NULL,
+    idl_path TEXT,
+    table_prefix TEXT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (kind, address)
+);
+
+-- same format as a program's `filters` in the config
+ALTER TABLE tracked_entities ADD COLUMN IF NOT EXISTS filters JSONB;
+
+CREATE OR REPLACE FUNCTION notify_tracked_entities() RETURNS trigger AS $$
+BEGIN
+    PERFORM pg_notify('tracked_entities', TG_OP);
+    RETURN NULL;
+END
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE TRIGGER tracked_entities_notify
+    AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON tracked_entities
+    FOR EACH STATEMENT EXECUTE FUNCTION notify_tracked_entities();
@@ -0,0 +1,84 @@
+-- stake, vote and nonce accounts controlled by tracked wallets. A row is removed once
+-- the account is closed or no tracked wallet is among its authorities anymore
+
+-- epochs the clock sysvar reported, with the first slot the plugin saw in each
+CREATE TABLE epochs (
+    epoch BIGINT PRIMARY KEY,
+    first_slot BIGINT NOT NULL,
+    recorded_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+-- the delegation columns are NULL until the stake is delegated, the epochs also when the
+-- stake was active since genesis or isn't deactivating
+CREATE TABLE stake_accounts (
+    account TEXT PRIMARY KEY,
+    staker TEXT NOT NULL,
+    withdrawer TEXT NOT NULL,
+    lamports BIGINT NOT NULL,
+    rent_exempt_reserve BIGINT NOT NULL,
+    lockup_unix_timestamp BIGINT NOT NULL,
+    lockup_epoch NUMERIC(20, 0) NOT NULL,
+    lockup_custodian TEXT,
+    voter TEXT,
+    delegated_stake BIGINT,
+    activation_epoch BIGINT,
+    deactivation_epoch BIGINT,
+    credits_observed BIGINT,
+    slot BIGINT NOT NULL,
+    write_version BIGINT NOT NULL,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX stake_accounts_staker_idx ON stake_accounts (staker);
+
+CREATE INDEX stake_accounts_withdrawer_idx ON stake_accounts (withdrawer);
+
+-- 'initialized', 'activating', 'active', 'deactivating' or 'inactive' as of the newest
+-- epoch in `epochs`, NULL while no epoch has been seen. Warmup and cooldown are rate
+-- limited across the cluster and can take more than one epoch, this only tells which
+-- side of its activation and deactivation epochs a delegation is on
+CREATE FUNCTION stake_activation(voter TEXT, activation_epoch BIGINT, deactivation_epoch BIGINT)
+RETURNS TEXT AS $$
+    SELECT CASE
+        WHEN voter IS NULL THEN 'initialized'
+        WHEN activation_epoch = deactivation_epoch THEN 'inactive'
+        WHEN current.epoch IS NULL THEN NULL
+        WHEN current.epoch > deactivation_epoch THEN 'inactive'
+        WHEN current.epoch = deactivation_epoch THEN 'deactivating'
+        WHEN current.epoch <= activation_epoch THEN 'activating'
+        ELSE 'active'
+    END
+    FROM (SELECT max(epoch) AS epoch FROM epochs) AS current
+$$ LANGUAGE sql STABLE;
+
+CREATE TABLE vote_accounts (
+    account TEXT PRIMARY KEY,
+    node_pubkey TEXT NOT NULL,
+    authorized_withdrawer TEXT NOT NULL,
+    authorized_voter TEXT,
+    commission SMALLINT NOT NULL,
+    credits BIGINT NOT NULL,
+    last_vote_slot BIGINT,
+    root_slot BIGINT,
+    lamports BIGINT NOT NULL,
+    slot BIGINT NOT NULL,
+    write_version BIGINT NOT NULL,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX vote_accounts_node_pubkey_idx ON vote_accounts (node_pubkey);
+
+CREATE INDEX vote_accounts_withdrawer_idx ON vote_accounts (authorized_withdrawer);
+
+CREATE TABLE nonce_accounts (
+    account TEXT PRIMARY KEY,
+    authority TEXT NOT NULL,
+    blockhash TEXT NOT NULL,
+    lamports_per_signature BIGINT NOT NULL,
+    lamports BIGINT NOT NULL,
+    slot BIGINT NOT NULL,
+    write_version BIGINT NOT NULL,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX nonce_accounts_authority_idx ON nonce_accounts (authority);
@@ -0,0 +1,72 @@
+-- every change to a listing's funding, oldest row first per account. `block_time` is
+-- filled in once the slot's block metadata has been stored
+CREATE TABLE listing_history (
+    account TEXT NOT NULL,
+    funding_goal BIGINT NOT NULL,
+    funding_raised BIGINT NOT NULL,
+    available_tokens NUMERIC NOT NULL,
+    tokens_sold NUMERIC NOT NULL,
+    base_price DOUBLE PRECISION NOT NULL,
+    slot BIGINT NOT NULL,
+    write_version BIGINT NOT NULL,
+    block_time TIMESTAMPTZ,
+    recorded_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (account, slot, write_version)
+);
+
+CREATE INDEX listing_history_pending_time_idx ON listing_history (slot) WHERE block_time IS NULL;
+
+-- the listings that existed before the history, as of their last update
+INSERT INTO listing_history (
+    account, funding_goal, funding_raised, available_tokens, tokens_sold, base_price, slot,
+    write_version, block_time
+)
+SELECT account, funding_goal, funding_raised, available_tokens, tokens_sold, base_price,
+    COALESCE(slot, 0), COALESCE(write_version, 0), slot_time(slot)
+FROM listings;
+
+-- a listing's fundraising curve, oldest first, optionally limited to [since, until).
+-- `progress` is the share of the funding goal raised, NULL for a goal of 0. Rows whose
+-- time isn't known yet are only left out when a bound is given
+CREATE FUNCTION listing_funding_history(
+    listing TEXT,
+    since TIMESTAMPTZ DEFAULT NULL,
+    until TIMESTAMPTZ DEFAULT NULL
+) RETURNS TABLE (
+    slot BIGINT,
+    block_time TIMESTAMPTZ,
+    funding_goal BIGINT,
+    funding_raised BIGINT,
+    progress DOUBLE PRECISION,
+    tokens_sold NUMERIC,
+    available_tokens NUMERIC
+) AS $$
+    SELECT h.slot, t.block_time, h.funding_goal, h.funding_raised,
+        h.funding_raised::DOUBLE PRECISION / NULLIF(h.funding_goal, 0), h.tokens_sold,
+        h.available_tokens
+    FROM listing_history h
+    CROSS JOIN LATERAL (SELECT COALESCE(h.block_time, slot_time(h.slot)) AS block_time) t
+    WHERE h.account = listing
+        AND (since IS NULL OR t.block_time >= since)
+        AND (until IS NULL OR t.block_time < until)
+    ORDER BY h.slot, h.write_version
+$$ LANGUAGE sql STABLE;
+
+-- lamports a listing raised per second over the `period` before now: the difference
+-- between its newest funding and the funding it had when the period started (its first
+-- row if it's younger than that), divided by the period. NULL without any history
+CREATE FUNCTION listing_funding_velocity(listing TEXT, period INTERVAL DEFAULT '1 hour')
+RETURNS DOUBLE PRECISION AS $$
+    WITH h AS (SELECT * FROM listing_funding_history(listing)),
+    latest AS (SELECT funding_raised FROM h ORDER BY slot DESC LIMIT 1),
+    start AS (
+        SELECT COALESCE(
+            (SELECT funding_raised FROM h WHERE block_time <= now() - period
+             ORDER BY slot DESC LIMIT 1),
+            (SELECT funding_raised FROM h ORDER BY slot LIMIT 1)
+        ) AS funding_raised
+    )
+    SELECT (latest.funding_raised - start.funding_raised)::DOUBLE PRECISION
+        / EXTRACT(EPOCH FROM period)::DOUBLE PRECISION
+    FROM latest, start
+$$ LANGUAGE sql STABLE;
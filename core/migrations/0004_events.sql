@@ -0,0 +1,18 @@
+-- Anchor events logged by tracked programs, in log order per transaction. Like
+-- `transactions`, a signature can show up in more than one slot across forks
+CREATE TABLE events (
+    signature TEXT NOT NULL,
+    slot BIGINT NOT NULL,
+    event_index INTEGER NOT NULL,
+    instruction_index INTEGER NOT NULL,
+    program_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    data JSONB NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (signature, slot, event_index)
+);
+
+-- answers "the latest <name> events of <program>"
+CREATE INDEX events_program_name_slot_idx ON events (program_id, name, slot);
+
+CREATE INDEX events_slot_idx ON events (slot);
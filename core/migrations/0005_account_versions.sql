@@ -0,0 +1,56 @@
+-- every version of the listing and IDL account rows since the newest rooted one, so a
+-- commitment level is served the newest state it has reached instead of nothing while
+-- the current row is still ahead of it. `data` is the row as JSON, NULL for a close.
+-- Versions superseded by a rooted one are pruned as slots are rooted
+CREATE TABLE account_versions (
+    table_name TEXT NOT NULL,
+    account TEXT NOT NULL,
+    slot BIGINT NOT NULL,
+    write_version BIGINT NOT NULL,
+    data JSONB,
+    recorded_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (table_name, account, slot, write_version)
+);
+
+CREATE INDEX account_versions_slot_idx ON account_versions (slot);
+
+-- the listings as they are now, IDL tables are filled in by the plugin when it creates them
+INSERT INTO account_versions (table_name, account, slot, write_version, data)
+SELECT 'listings', account, COALESCE(slot, 0), COALESCE(write_version, 0),
+    to_jsonb(l) - 'updated_at'
+FROM listings l;
+
+-- whether slot `s` has reached `commitment`: processed, confirmed or finalized
+CREATE FUNCTION slot_reaches(s BIGINT, commitment TEXT) RETURNS BOOLEAN AS $$
+    SELECT CASE slot_commitment(s)
+        WHEN 'rooted' THEN true
+        WHEN 'confirmed' THEN commitment IN ('processed', 'confirmed')
+        ELSE commitment = 'processed'
+    END
+$$ LANGUAGE sql STABLE;
+
+-- the newest version of every account of `tbl` that has reached `commitment`, closed
+-- accounts included with a NULL `data`
+CREATE FUNCTION committed_versions(tbl TEXT, commitment TEXT)
+RETURNS TABLE (account TEXT, slot BIGINT, data JSONB, recorded_at TIMESTAMP) AS $$
+    SELECT DISTINCT ON (v.account) v.account, v.slot, v.data, v.recorded_at
+    FROM account_versions v
+    WHERE v.table_name = tbl AND slot_reaches(v.slot, commitment)
+    ORDER BY v.account, v.slot DESC, v.write_version DESC
+$$ LANGUAGE sql STABLE;
+
+CREATE OR REPLACE VIEW confirmed_listings AS
+    SELECT l.*
+    FROM committed_versions('listings', 'confirmed') v
+    CROSS JOIN LATERAL jsonb_populate_record(
+        NULL::listings, v.data || jsonb_build_object('updated_at', v.recorded_at)
+    ) l
+    WHERE v.data IS NOT NULL;
+
+CREATE OR REPLACE VIEW finalized_listings AS
+    SELECT l.*
+    FROM committed_versions('listings', 'finalized') v
+    CROSS JOIN LATERAL jsonb_populate_record(
+        NULL::listings, v.data || jsonb_build_object('updated_at', v.recorded_at)
+    ) l
+    WHERE v.data IS NOT NULL;
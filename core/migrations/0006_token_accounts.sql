@@ -0,0 +1,34 @@
+-- a wallet can hold one mint in several token accounts, each of them is a holding of its
+-- own. The rows so far can't be told apart by account, the snapshot the validator loads
+-- at its next start writes them again
+ALTER TABLE token_balances ADD COLUMN token_account TEXT;
+
+DELETE FROM token_balances;
+
+ALTER TABLE token_balances ALTER COLUMN token_account SET NOT NULL;
+
+ALTER TABLE token_balances DROP CONSTRAINT token_balances_pkey;
+
+ALTER TABLE token_balances ADD PRIMARY KEY (wallet, token_account);
+
+-- NULL for changes recorded before holdings were kept per account
+ALTER TABLE token_account_history ADD COLUMN token_account TEXT;
+
+-- the amounts of all of a wallet's token accounts of the mint
+DROP VIEW nft_holdings;
+
+CREATE VIEW nft_holdings AS
+    SELECT t.wallet, t.mint, t.amount, md.name, md.symbol, md.uri, md.token_standard,
+        md.collection, md.collection_verified, e.mint IS NOT NULL AS master_edition,
+        t.slot, t.updated_at, ui_amount(t.amount, m.decimals) AS ui_amount
+    FROM (
+        SELECT wallet, mint, sum(amount) AS amount, max(slot) AS slot,
+            max(updated_at) AS updated_at
+        FROM token_balances
+        GROUP BY wallet, mint
+    ) t
+    LEFT JOIN mints m ON m.mint = t.mint
+    LEFT JOIN nft_metadata md ON md.mint = t.mint
+    LEFT JOIN master_editions e ON e.mint = t.mint
+    WHERE t.amount > 0
+        AND ((m.decimals = 0 AND m.supply = 1) OR e.mint IS NOT NULL);
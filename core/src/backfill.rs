@@ -0,0 +1,327 @@
+use solana_account_decoder::UiAccountEncoding;
+use solana_rpc_client::nonblocking::rpc_client::RpcClient;
+use solana_rpc_client_api::{client_error::Error as ClientError, config::RpcAccountInfoConfig};
+use solana_sdk::{account::Account, commitment_config::CommitmentConfig, pubkey::Pubkey};
+use std::{sync::Arc, time::Duration};
+use tokio::{runtime::Handle, sync::mpsc, task::JoinHandle};
+
+use crate::{
+    config::RpcConfig,
+    logging::{limited, sample},
+    metaplex,
+    metrics::metrics,
+    models::MasterEdition,
+    sink::SinkEvent,
+    token,
+};
+
+// accounts fetched per mint: the mint, its metadata and its master edition
+const ACCOUNTS_PER_MINT: usize = 3;
+
+// getMultipleAccounts takes at most 100 accounts
+const MINTS_PER_REQUEST: usize = 100 / ACCOUNTS_PER_MINT;
+
+// fetches the accounts of newly tracked mints through RPC. A mint's own account, its
+// metadata and master edition rarely change, so they'd never be stored if their last
+// update came before the mint was tracked, e.g. earlier in the startup snapshot than the first holding of it.
+// They're written at the slot the RPC read them at, so any later update still wins
+#[derive(Debug)]
+pub struct Backfill {
+    sender: mpsc::UnboundedSender<[u8; 32]>,
+    task: JoinHandle<()>,
+}
+
+impl Backfill {
+    pub fn start(
+        runtime: &Handle,
+        url: &str,
+        config: &RpcConfig,
+        emit: impl Fn(SinkEvent) + Send + Sync + 'static,
+    ) -> Self {
+        let client = RpcClient::new_with_commitment(url.to_string(), CommitmentConfig::processed());
+        let retry = Duration::from_millis(config.retry_ms.max(1));
+        Self::with_client(runtime, client, retry, emit)
+    }
+
+    fn with_client(
+        runtime: &Handle,
+        client: RpcClient,
+        retry: Duration,
+        emit: impl Fn(SinkEvent) + Send + Sync + 'static,
+    ) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let task = runtime.spawn(run(client, receiver, retry, emit));
+        Backfill { sender, task }
+    }
+
+    pub fn fetch_mint(&self, mint: [u8; 32]) {
+        // only fails once the task is stopped
+        let _ = self.sender.send(mint);
+    }
+
+    // mints still waiting to be fetched are dropped
+    pub async fn stop(self) {
+        self.task.abort();
+        let _ = self.task.await;
+    }
+}
+
+async fn run(
+    client: RpcClient,
+    mut receiver: mpsc::UnboundedReceiver<[u8; 32]>,
+    retry: Duration,
+    emit: impl Fn(SinkEvent) + Send + Sync + 'static,
+) {
+    let emit = Arc::new(emit);
+    let mut mints = Vec::with_capacity(MINTS_PER_REQUEST);
+    while receiver.recv_many(&mut mints, MINTS_PER_REQUEST).await > 0 {
+        // the RPC usually isn't up yet while the validator loads its snapshot
+        let events = loop {
+            match fetch_mints(&client, &mints).await {
+                Ok(events) => break events,
+                Err(e) => {
+                    metrics().backfill_failures.inc();
+                    limited!(
+                        warn,
+                        mints = sample(mints.iter().map(|mint| bs58::encode(mint).into_string()))
+                            .as_str();
+                        "Error fetching {} mints through RPC, retrying in {:?}: {}",
+                        mints.len(),
+                        retry,
+                        e
+                    );
+                    tokio::time::sleep(retry).await;
+                }
+            }
+        };
+        // a sink may wait for room, e.g. the write pipeline under the `block` policy,
+        // which can't be done on a runtime thread
+        let emit = emit.clone();
+        let sent =
+            tokio::task::spawn_blocking(move || events.into_iter().for_each(|event| emit(event)));
+        if let Err(e) = sent.await {
+            log::error!("Error sending fetched accounts to the sinks: {:?}", e);
+        }
+        mints.clear();
+    }
+}
+
+async fn fetch_mints(
+    client: &RpcClient,
+    mints: &[[u8; 32]],
+) -> Result<Vec<SinkEvent>, ClientError> {
+    let addresses: Vec<Pubkey> = mints
+        .iter()
+        .flat_map(|mint| {
+            [
+                *mint,
+                metaplex::metadata_address(mint),
+                metaplex::master_edition_address(mint),
+            ]
+        })
+        .map(Pubkey::new_from_array)
+        .collect();
+    let config = RpcAccountInfoConfig {
+        encoding: Some(UiAccountEncoding::Base64),
+        ..RpcAccountInfoConfig::default()
+    };
+    let response = client
+        .get_multiple_accounts_with_config(&addresses, config)
+        .await?;
+    let slot = response.context.slot;
+
+    let mut events = Vec::new();
+    for (mint, accounts) in mints.iter().zip(response.value.chunks(ACCOUNTS_PER_MINT)) {
+        let [mint_account, metadata, edition] = accounts else {
+            continue;
+        };
+        let address = bs58::encode(mint).into_string();
+        if let Some(info) = mint_account
+            .as_ref()
+            .and_then(|account| token::parse_mint(account.owner.as_ref(), &account.data))
+        {
+            events.push(SinkEvent::MintUpdated {
+                mint: address.clone(),
+                info: token::mint_info(info, slot, 0),
+            });
+        }
+        if let Some(metadata) = metadata.as_ref().and_then(metadata_account) {
+            events.push(SinkEvent::NftMetadataUpdated {
+                mint: address.clone(),
+                metadata: Box::new(metaplex::nft_metadata(&metadata, slot, 0)),
+            });
+        }
+        if let Some(edition) = edition.as_ref().and_then(master_edition_account) {
+            events.push(SinkEvent::MasterEditionUpdated {
+                mint: address,
+                edition: MasterEdition {
+                    supply: edition.supply,
+                    max_supply: edition.max_supply,
+                    slot,
+                    write_version: 0,
+                },
+            });
+        }
+    }
+    Ok(events)
+}
+
+fn metadata_account(account: &Account) -> Option<metaplex::Metadata> {
+    if !metaplex::is_metadata_program(account.owner.as_ref()) {
+        return None;
+    }
+    metaplex::parse_metadata(&account.data)
+}
+
+fn master_edition_account(account: &Account) -> Option<metaplex::MasterEdition> {
+    if !metaplex::is_metadata_program(account.owner.as_ref()) {
+        return None;
+    }
+    metaplex::parse_master_edition(&account.data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+    use serde_json::{json, Value};
+    use solana_rpc_client_api::request::RpcRequest;
+    use spl_token::solana_program::program_pack::Pack;
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use crate::{
+        config::QueueFullPolicy,
+        metaplex::tests::metadata_account as metadata_data,
+        pipeline::{WriteOp, WritePipeline},
+    };
+
+    fn ui_account(owner: &str, data: &[u8]) -> Value {
+        json!({
+            "lamports": 1_000_000,
+            "data": [BASE64.encode(data), "base64"],
+            "owner": owner,
+            "executable": false,
+            "rentEpoch": 0,
+            "space": data.len(),
+        })
+    }
+
+    #[tokio::test]
+    async fn fetches_mints_with_their_metadata_and_edition() {
+        let nft = Pubkey::new_unique();
+        let missing = Pubkey::new_unique();
+
+        let mut mint = vec![0; spl_token::state::Mint::LEN];
+        spl_token::state::Mint {
+            supply: 1,
+            decimals: 0,
+            is_initialized: true,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut mint);
+        // a V2 master edition with a supply of 0 and no max supply
+        let mut edition = vec![6];
+        edition.extend_from_slice(&[0; 9]);
+        edition.resize(282, 0);
+
+        let mocks = HashMap::from([(
+            RpcRequest::GetMultipleAccounts,
+            json!({
+                "context": {"slot": 42},
+                "value": [
+                    ui_account(&spl_token::ID.to_string(), &mint),
+                    ui_account(
+                        &metaplex::METADATA_PROGRAM_ID.to_string(),
+                        &metadata_data(&nft, "Token #1")
+                    ),
+                    ui_account(&metaplex::METADATA_PROGRAM_ID.to_string(), &edition),
+                    null,
+                    null,
+                    null,
+                ],
+            }),
+        )]);
+        let client = RpcClient::new_mock_with_mocks("succeeds".to_string(), mocks);
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let backfill = Backfill::with_client(
+            &Handle::current(),
+            client,
+            Duration::from_millis(1),
+            move |event| sink.lock().unwrap().push(event),
+        );
+        backfill.fetch_mint(nft.to_bytes());
+        backfill.fetch_mint(missing.to_bytes());
+        while events.lock().unwrap().len() < 3 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        backfill.stop().await;
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 3);
+        let SinkEvent::MintUpdated { mint, info } = &events[0] else {
+            panic!("expected a mint, got {:?}", events[0]);
+        };
+        assert_eq!(*mint, nft.to_string());
+        assert_eq!((info.supply, info.decimals, info.slot), (1, 0, 42));
+        let SinkEvent::NftMetadataUpdated { mint, metadata } = &events[1] else {
+            panic!("expected metadata, got {:?}", events[1]);
+        };
+        assert_eq!(*mint, nft.to_string());
+        assert_eq!((metadata.name.as_str(), metadata.slot), ("Token #1", 42));
+        let SinkEvent::MasterEditionUpdated { mint, edition } = &events[2] else {
+            panic!("expected a master edition, got {:?}", events[2]);
+        };
+        assert_eq!(*mint, nft.to_string());
+        assert_eq!((edition.max_supply, edition.slot), (None, 42));
+    }
+
+    #[tokio::test]
+    async fn waits_for_room_in_a_full_pipeline() {
+        let mint = Pubkey::new_unique();
+        let mut data = vec![0; spl_token::state::Mint::LEN];
+        spl_token::state::Mint {
+            supply: 1,
+            is_initialized: true,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        let mocks = HashMap::from([(
+            RpcRequest::GetMultipleAccounts,
+            json!({
+                "context": {"slot": 42},
+                "value": [ui_account(&spl_token::ID.to_string(), &data), null, null],
+            }),
+        )]);
+        let client = RpcClient::new_mock_with_mocks("succeeds".to_string(), mocks);
+
+        let (pipeline, mut queue) = WritePipeline::unstarted(QueueFullPolicy::Block, 1);
+        pipeline.send(WriteOp::Epoch { epoch: 1, slot: 1 });
+        let backfill = Backfill::with_client(
+            &Handle::current(),
+            client,
+            Duration::from_millis(1),
+            move |event| {
+                if let SinkEvent::MintUpdated { mint, info } = event {
+                    pipeline.send(WriteOp::Mint { mint, info });
+                }
+            },
+        );
+        backfill.fetch_mint(mint.to_bytes());
+
+        // the queue only gets room once the backfill is waiting for it
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(matches!(queue.recv().await, Some(WriteOp::Epoch { .. })));
+        let op = tokio::time::timeout(Duration::from_secs(5), queue.recv())
+            .await
+            .expect("the backfill stopped sending");
+        assert!(
+            matches!(op, Some(WriteOp::Mint { mint: address, .. }) if address == mint.to_string())
+        );
+        backfill.stop().await;
+    }
+}
@@ -0,0 +1,48 @@
+use heimdall_plugin::config::Config;
+use std::{env, process};
+
+const USAGE: &str = "Usage: heimdall-check-config [--offline] <config>
+
+Loads and validates a plugin config the same way the plugin does, IDLs, environment
+variables and secret files included, then checks that the database can be reached if
+the postgres sink is listed. With --offline the database isn't contacted.";
+
+#[tokio::main]
+async fn main() {
+    let mut offline = false;
+    let mut config_path = None;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--offline" => offline = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ => config_path = Some(arg),
+        }
+    }
+    let Some(config_path) = config_path else {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    };
+
+    let config = match Config::load(&config_path) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{} is invalid:", config_path);
+            for line in e.to_string().lines() {
+                eprintln!("  {}", line);
+            }
+            process::exit(1);
+        }
+    };
+
+    if !offline && config.has_postgres_sink() {
+        if let Err(e) = config.check_database().await {
+            eprintln!("{} is invalid:", config_path);
+            eprintln!("  database_url: can't connect: {}", e);
+            process::exit(1);
+        }
+    }
+    println!("{} is valid", config_path);
+}
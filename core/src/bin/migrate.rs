@@ -0,0 +1,55 @@
+use heimdall_plugin::migrations::{applied_versions, latest_version, migrate, MIGRATIONS};
+use sqlx::postgres::PgPoolOptions;
+use std::{env, error::Error};
+
+const USAGE: &str = "Usage: heimdall-migrate [--status] [database_url]
+
+Applies the plugin's pending schema migrations, the same ones the plugin runs on load.
+The database URL defaults to DATABASE_URL. With --status nothing is applied.";
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    env_logger::Builder::new().parse_filters("info,sqlx=warn").init();
+
+    let mut status = false;
+    let mut database_url = env::var("DATABASE_URL").ok();
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--status" => status = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ => database_url = Some(arg),
+        }
+    }
+    let Some(database_url) = database_url else {
+        return Err(USAGE.into());
+    };
+
+    let pool = PgPoolOptions::new()
+        .max_connections(1)
+        .connect(&database_url)
+        .await?;
+
+    if status {
+        let applied = applied_versions(&pool).await?;
+        for migration in MIGRATIONS {
+            let state = if applied.contains(&migration.version) {
+                "applied"
+            } else {
+                "pending"
+            };
+            println!("{:>4} {:<30} {}", migration.version, migration.name, state);
+        }
+        return Ok(());
+    }
+
+    let migrated = migrate(&pool).await?;
+    println!(
+        "Applied {} migrations, schema is at version {}",
+        migrated.len(),
+        latest_version()
+    );
+    Ok(())
+}
@@ -0,0 +1,1545 @@
+use serde_json::Value;
+use sqlx::{
+    postgres::{PgConnectOptions, PgPoolCopyExt, PgPoolOptions, PgSslMode},
+    Pool, Postgres, Row,
+};
+use std::{collections::HashMap, str::FromStr, time::Duration};
+
+use crate::{
+    config::DatabaseConfig,
+    idl::IdlAccount,
+    models::{
+        AccountVersion, Block, Listing, MasterEdition, MintInfo, NativeAccount, NativeState,
+        NftMetadata, NonceAccount, ProgramEvent, SecurityEvent, StakeAccount, TokenAuthorities,
+        TokenBalance, Transaction, VoteAccount,
+    },
+};
+
+pub fn connect_options(
+    url: &str,
+    config: &DatabaseConfig,
+) -> Result<PgConnectOptions, sqlx::Error> {
+    let mut options = PgConnectOptions::from_str(url)?;
+    if let Some(ssl_mode) = &config.ssl_mode {
+        options = options.ssl_mode(PgSslMode::from_str(ssl_mode)?);
+    }
+    if let Some(cert) = &config.ssl_root_cert {
+        options = options.ssl_root_cert(cert);
+    }
+    if let Some(timeout) = config.statement_timeout_ms {
+        options = options.options([("statement_timeout", timeout.to_string())]);
+    }
+    Ok(options)
+}
+
+pub async fn connect(url: &str, config: &DatabaseConfig) -> Result<Pool<Postgres>, sqlx::Error> {
+    let options = connect_options(url, config)?;
+    PgPoolOptions::new()
+        .max_connections(config.max_connections.max(1))
+        .min_connections(config.min_connections)
+        .acquire_timeout(Duration::from_millis(config.acquire_timeout_ms))
+        .idle_timeout(config.idle_timeout_ms.map(Duration::from_millis))
+        .max_lifetime(config.max_lifetime_ms.map(Duration::from_millis))
+        .connect_with(options)
+        .await
+}
+
+// retries while the database is unreachable, for up to `connect_retry_ms`. Anything
+// else, like bad credentials, fails right away
+pub async fn connect_with_retry(
+    url: &str,
+    config: &DatabaseConfig,
+) -> Result<Pool<Postgres>, sqlx::Error> {
+    let deadline = tokio::time::Instant::now() + Duration::from_millis(config.connect_retry_ms);
+    let mut backoff = Backoff::new(config);
+    loop {
+        match connect(url, config).await {
+            Ok(pool) => return Ok(pool),
+            Err(e) if is_unavailable(&e) && tokio::time::Instant::now() < deadline => {
+                let delay = backoff.next();
+                log::warn!("Database unreachable, retrying in {:?}: {}", delay, e);
+                tokio::time::sleep(delay).await;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// errors that mean the database couldn't be reached rather than rejecting the write,
+// the write is worth retrying once it's back
+pub fn is_unavailable(error: &sqlx::Error) -> bool {
+    match error {
+        sqlx::Error::Io(_)
+        | sqlx::Error::Tls(_)
+        | sqlx::Error::Protocol(_)
+        | sqlx::Error::PoolTimedOut
+        | sqlx::Error::PoolClosed
+        | sqlx::Error::WorkerCrashed => true,
+        // connection exceptions, shutdowns and too many connections
+        sqlx::Error::Database(e) => e.code().is_some_and(|code| {
+            code.starts_with("08") || code.starts_with("57P") || code == "53300"
+        }),
+        _ => false,
+    }
+}
+
+// exponential delay between reconnect attempts
+#[derive(Debug)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    current: Duration,
+}
+
+impl Backoff {
+    pub fn new(config: &DatabaseConfig) -> Self {
+        let initial = Duration::from_millis(config.backoff_initial_ms.max(1));
+        Backoff {
+            initial,
+            max: Duration::from_millis(config.backoff_max_ms).max(initial),
+            current: initial,
+        }
+    }
+
+    pub fn next(&mut self) -> Duration {
+        let delay = self.current;
+        self.current = (self.current * 2).min(self.max);
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.current = self.initial;
+    }
+}
+
+// ON CONFLICT condition that only lets a strictly newer (slot, write_version) through,
+// rows written before versions were stored always lose
+pub fn newer_version_sql(table: &str) -> String {
+    format!(
+        "(COALESCE(\"{0}\".slot, -1), COALESCE(\"{0}\".write_version, -1))
+            < (EXCLUDED.slot, EXCLUDED.write_version)",
+        table
+    )
+}
+
+// listing upsert from `source` rows `r`, numeric columns may arrive as text
+fn listings_upsert_sql(source: &str) -> String {
+    format!(
+        "INSERT INTO listings (
+            account, name, seed, mint, funding_goal, pool_mint_supply,
+            funding_raised, available_tokens, base_price, tokens_sold,
+            bump, vault_bump, mint_bump, slot, write_version
+        )
+        SELECT account, name, seed, mint, funding_goal, CAST(pool_mint_supply AS NUMERIC),
+            funding_raised, CAST(available_tokens AS NUMERIC), base_price, CAST(tokens_sold AS NUMERIC),
+            bump, vault_bump, mint_bump, slot, write_version
+        FROM {}
+        ON CONFLICT (account) DO UPDATE SET
+            name = EXCLUDED.name,
+            seed = EXCLUDED.seed,
+            mint = EXCLUDED.mint,
+            funding_goal = EXCLUDED.funding_goal,
+            pool_mint_supply = EXCLUDED.pool_mint_supply,
+            funding_raised = EXCLUDED.funding_raised,
+            available_tokens = EXCLUDED.available_tokens,
+            base_price = EXCLUDED.base_price,
+            tokens_sold = EXCLUDED.tokens_sold,
+            bump = EXCLUDED.bump,
+            vault_bump = EXCLUDED.vault_bump,
+            mint_bump = EXCLUDED.mint_bump,
+            slot = EXCLUDED.slot,
+            write_version = EXCLUDED.write_version,
+            updated_at = CURRENT_TIMESTAMP
+        WHERE {}",
+        source,
+        newer_version_sql("listings")
+    )
+}
+
+// appends the `source` rows `r` to `listing_history`, numeric columns may arrive as text.
+// A row is skipped when its funding equals the row before it: the previous version of
+// the account among `source`, or for its oldest one the newest older history row
+fn listing_history_sql(source: &str) -> String {
+    format!(
+        "WITH r AS (
+            SELECT account, funding_goal, funding_raised,
+                CAST(available_tokens AS NUMERIC) AS available_tokens,
+                CAST(tokens_sold AS NUMERIC) AS tokens_sold, base_price, slot, write_version,
+                true AS is_new
+            FROM {}
+        ),
+        oldest AS (
+            SELECT DISTINCT ON (account) account, slot, write_version
+            FROM r ORDER BY account, slot, write_version
+        ),
+        c AS (
+            SELECT * FROM r
+            UNION ALL
+            SELECT p.*, false FROM oldest o CROSS JOIN LATERAL (
+                SELECT h.account, h.funding_goal, h.funding_raised, h.available_tokens,
+                    h.tokens_sold, h.base_price, h.slot, h.write_version
+                FROM listing_history h
+                WHERE h.account = o.account
+                    AND (h.slot, h.write_version) < (o.slot, o.write_version)
+                ORDER BY h.slot DESC, h.write_version DESC LIMIT 1
+            ) p
+        ),
+        d AS (
+            SELECT c.*, LAG((funding_goal, funding_raised, available_tokens, tokens_sold,
+                base_price)) OVER (PARTITION BY account ORDER BY slot, write_version) AS previous
+            FROM c
+        )
+        INSERT INTO listing_history (
+            account, funding_goal, funding_raised, available_tokens, tokens_sold, base_price,
+            slot, write_version, block_time
+        )
+        SELECT d.account, d.funding_goal, d.funding_raised, d.available_tokens, d.tokens_sold,
+            d.base_price, d.slot, d.write_version,
+            (SELECT b.block_time FROM blocks b WHERE b.slot = d.slot)
+        FROM d
+        WHERE d.is_new
+            AND d.previous IS DISTINCT FROM (d.funding_goal, d.funding_raised,
+                d.available_tokens, d.tokens_sold, d.base_price)
+        ON CONFLICT (account, slot, write_version) DO NOTHING",
+        source
+    )
+}
+
+pub const LISTINGS_COPY_COLUMNS: &str =
+    "account, name, seed, mint, funding_goal, pool_mint_supply, \
+    funding_raised, available_tokens, base_price, tokens_sold, bump, vault_bump, mint_bump, slot, \
+    write_version";
+
+// moves the newest row per account from a startup staging table into listings
+pub fn merge_listings_sql(staging_table: &str) -> String {
+    listings_upsert_sql(&format!(
+        "(SELECT DISTINCT ON (account) * FROM \"{}\" ORDER BY account, slot DESC, write_version DESC) AS r",
+        staging_table
+    ))
+}
+
+// records the newest row per account of a startup staging table in `listing_history`
+pub fn stage_listing_history_sql(staging_table: &str) -> String {
+    listing_history_sql(&format!(
+        "(SELECT DISTINCT ON (account) * FROM \"{}\" ORDER BY account, slot DESC, write_version DESC) AS r",
+        staging_table
+    ))
+}
+
+// every listing version of a batch, not just the newest that was upserted, so the history
+// keeps each funding change. `versions` are rows of `listings` as JSON
+pub async fn insert_listing_history(
+    pool: &Pool<Postgres>,
+    versions: &[&AccountVersion],
+) -> Result<(), sqlx::Error> {
+    let mut accounts = Vec::with_capacity(versions.len());
+    let mut data = Vec::with_capacity(versions.len());
+    let mut slots = Vec::with_capacity(versions.len());
+    let mut write_versions = Vec::with_capacity(versions.len());
+
+    for version in versions {
+        let Some(row) = &version.data else {
+            continue;
+        };
+        accounts.push(version.account.as_str());
+        data.push(row);
+        slots.push(version.slot as i64);
+        write_versions.push(version.write_version as i64);
+    }
+
+    let query = listing_history_sql(
+        "(SELECT v.account, (v.data->>'funding_goal')::bigint AS funding_goal,
+            (v.data->>'funding_raised')::bigint AS funding_raised,
+            v.data->>'available_tokens' AS available_tokens, v.data->>'tokens_sold' AS tokens_sold,
+            (v.data->>'base_price')::float8 AS base_price, v.slot, v.write_version
+        FROM UNNEST($1::text[], $2::jsonb[], $3::bigint[], $4::bigint[])
+            AS v(account, data, slot, write_version)) AS r",
+    );
+    sqlx::query(&query)
+        .bind(accounts)
+        .bind(data)
+        .bind(slots)
+        .bind(write_versions)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn upsert_listings(
+    pool: &Pool<Postgres>,
+    listings: &HashMap<String, (Listing, u64, u64)>,
+) -> Result<(), sqlx::Error> {
+    let mut accounts = Vec::with_capacity(listings.len());
+    let mut names = Vec::with_capacity(listings.len());
+    let mut seeds = Vec::with_capacity(listings.len());
+    let mut mints = Vec::with_capacity(listings.len());
+    let mut funding_goals = Vec::with_capacity(listings.len());
+    let mut pool_mint_supplies = Vec::with_capacity(listings.len());
+    let mut funding_raised = Vec::with_capacity(listings.len());
+    let mut available_tokens = Vec::with_capacity(listings.len());
+    let mut base_prices = Vec::with_capacity(listings.len());
+    let mut tokens_sold = Vec::with_capacity(listings.len());
+    let mut bumps = Vec::with_capacity(listings.len());
+    let mut vault_bumps = Vec::with_capacity(listings.len());
+    let mut mint_bumps = Vec::with_capacity(listings.len());
+    let mut slots = Vec::with_capacity(listings.len());
+    let mut write_versions = Vec::with_capacity(listings.len());
+
+    for (account, (listing, slot, write_version)) in listings {
+        accounts.push(account.as_str());
+        names.push(listing.name.as_str());
+        seeds.push(listing.seed as i64);
+        mints.push(listing.mint.as_str());
+        funding_goals.push(listing.funding_goal as i64);
+        pool_mint_supplies.push(listing.pool_mint_supply.to_string());
+        funding_raised.push(listing.funding_raised as i64);
+        available_tokens.push(listing.available_tokens.to_string());
+        base_prices.push(listing.base_price);
+        tokens_sold.push(listing.tokens_sold.to_string());
+        bumps.push(listing.bump as i16);
+        vault_bumps.push(listing.vault_bump as i16);
+        mint_bumps.push(listing.mint_bump as i16);
+        slots.push(*slot as i64);
+        write_versions.push(*write_version as i64);
+    }
+
+    let listing_query = listings_upsert_sql(
+        "UNNEST(
+            $1::text[], $2::text[], $3::bigint[], $4::text[], $5::bigint[], $6::text[], $7::bigint[],
+            $8::text[], $9::float8[], $10::text[], $11::smallint[], $12::smallint[], $13::smallint[],
+            $14::bigint[], $15::bigint[]
+        ) AS r(
+            account, name, seed, mint, funding_goal, pool_mint_supply,
+            funding_raised, available_tokens, base_price, tokens_sold,
+            bump, vault_bump, mint_bump, slot, write_version
+        )",
+    );
+
+    sqlx::query(&listing_query)
+        .bind(accounts)
+        .bind(names)
+        .bind(seeds)
+        .bind(mints)
+        .bind(funding_goals)
+        .bind(pool_mint_supplies)
+        .bind(funding_raised)
+        .bind(available_tokens)
+        .bind(base_prices)
+        .bind(tokens_sold)
+        .bind(bumps)
+        .bind(vault_bumps)
+        .bind(mint_bumps)
+        .bind(slots)
+        .bind(write_versions)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+// rows are account -> (program id, decoded JSON, slot, write version)
+pub async fn upsert_idl_accounts(
+    pool: &Pool<Postgres>,
+    idl_account: &IdlAccount,
+    rows: &HashMap<String, (String, Value, u64, u64)>,
+) -> Result<(), sqlx::Error> {
+    let mut accounts = Vec::with_capacity(rows.len());
+    let mut program_ids = Vec::with_capacity(rows.len());
+    let mut data = Vec::with_capacity(rows.len());
+    let mut slots = Vec::with_capacity(rows.len());
+    let mut write_versions = Vec::with_capacity(rows.len());
+
+    for (account, (program_id, value, slot, write_version)) in rows {
+        accounts.push(account.as_str());
+        program_ids.push(program_id.as_str());
+        data.push(value);
+        slots.push(*slot as i64);
+        write_versions.push(*write_version as i64);
+    }
+
+    sqlx::query(&idl_account.upsert_sql)
+        .bind(accounts)
+        .bind(program_ids)
+        .bind(data)
+        .bind(slots)
+        .bind(write_versions)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+// makes sure every tracked user has a wallets row before balances reference it
+pub async fn register_wallets(
+    pool: &Pool<Postgres>,
+    wallets: &[String],
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO wallets (address) SELECT * FROM UNNEST($1::text[])
+        ON CONFLICT (address) DO NOTHING",
+    )
+    .bind(wallets)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// rows are (kind, address, idl path, table prefix, filters)
+pub async fn tracked_entities(
+    pool: &Pool<Postgres>,
+) -> Result<
+    Vec<(
+        String,
+        String,
+        Option<String>,
+        Option<String>,
+        Option<Value>,
+    )>,
+    sqlx::Error,
+> {
+    sqlx::query_as(
+        "SELECT kind, address, idl_path, table_prefix, filters FROM tracked_entities
+        ORDER BY created_at",
+    )
+    .fetch_all(pool)
+    .await
+}
+
+// rows are (wallet, lamports, slot, write version), any number per wallet. Every one of
+// them lands in the history, the wallet's current balance only moves forward to the newest
+pub async fn upsert_sol_balances(
+    pool: &Pool<Postgres>,
+    balances: &[(&str, u64, u64, u64)],
+) -> Result<(), sqlx::Error> {
+    let mut wallets = Vec::with_capacity(balances.len());
+    let mut lamports = Vec::with_capacity(balances.len());
+    let mut slots = Vec::with_capacity(balances.len());
+    let mut write_versions = Vec::with_capacity(balances.len());
+
+    for (wallet, balance, slot, write_version) in balances {
+        wallets.push(*wallet);
+        lamports.push(*balance as i64);
+        slots.push(*slot as i64);
+        write_versions.push(*write_version as i64);
+    }
+
+    sqlx::query(
+        "WITH r AS (
+            SELECT * FROM UNNEST($1::text[], $2::bigint[], $3::bigint[], $4::bigint[])
+                AS r(wallet, lamports, slot, write_version)
+        ), history AS (
+            INSERT INTO sol_balance_history (wallet, lamports, slot, write_version)
+            SELECT wallet, lamports, slot, write_version FROM r
+            ON CONFLICT (wallet, slot, write_version) DO NOTHING
+        )
+        INSERT INTO wallets (address, lamports, slot, write_version)
+        SELECT DISTINCT ON (wallet) wallet, lamports, slot, write_version FROM r
+        ORDER BY wallet, slot DESC, write_version DESC
+        ON CONFLICT (address) DO UPDATE SET
+            lamports = EXCLUDED.lamports,
+            slot = EXCLUDED.slot,
+            write_version = EXCLUDED.write_version,
+            updated_at = CURRENT_TIMESTAMP
+        WHERE (COALESCE(wallets.slot, -1), COALESCE(wallets.write_version, -1))
+            < (EXCLUDED.slot, EXCLUDED.write_version)",
+    )
+    .bind(wallets)
+    .bind(lamports)
+    .bind(slots)
+    .bind(write_versions)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// rows are (wallet, token account, mint, balance), the newest per token account; emptied
+// balances stay as zero rows so an older update can't bring them back. The (wallet, token
+// account, authorities, (slot, write version)) each applied change replaced are returned,
+// the defaults and no version for a holding seen for the first time
+pub async fn upsert_token_balances(
+    pool: &Pool<Postgres>,
+    balances: &[(&str, &str, &str, &TokenBalance)],
+) -> Result<Vec<(String, String, TokenAuthorities, Option<(u64, u64)>)>, sqlx::Error> {
+    let mut wallets = Vec::with_capacity(balances.len());
+    let mut token_accounts = Vec::with_capacity(balances.len());
+    let mut mints = Vec::with_capacity(balances.len());
+    let mut amounts = Vec::with_capacity(balances.len());
+    let mut token_programs = Vec::with_capacity(balances.len());
+    let mut extensions = Vec::with_capacity(balances.len());
+    let mut delegates = Vec::with_capacity(balances.len());
+    let mut delegated_amounts = Vec::with_capacity(balances.len());
+    let mut states = Vec::with_capacity(balances.len());
+    let mut close_authorities = Vec::with_capacity(balances.len());
+    let mut slots = Vec::with_capacity(balances.len());
+    let mut write_versions = Vec::with_capacity(balances.len());
+
+    for (wallet, token_account, mint, balance) in balances {
+        let authorities = balance.authorities.as_ref();
+        wallets.push(*wallet);
+        token_accounts.push(*token_account);
+        mints.push(*mint);
+        amounts.push(balance.amount.to_string());
+        token_programs.push(balance.token_program);
+        extensions.push(&balance.extensions);
+        delegates.push(authorities.and_then(|authorities| authorities.delegate.as_deref()));
+        delegated_amounts.push(
+            authorities
+                .map(|authorities| authorities.delegated_amount)
+                .unwrap_or_default()
+                .to_string(),
+        );
+        states.push(match authorities {
+            Some(authorities) if authorities.frozen => "frozen",
+            _ => "initialized",
+        });
+        close_authorities
+            .push(authorities.and_then(|authorities| authorities.close_authority.as_deref()));
+        slots.push(balance.slot as i64);
+        write_versions.push(balance.write_version as i64);
+    }
+
+    let rows = sqlx::query(
+        "WITH r AS (
+            SELECT wallet, token_account, mint, CAST(amount AS NUMERIC) AS amount,
+                token_program, extensions, delegate,
+                CAST(delegated_amount AS NUMERIC) AS delegated_amount, state, close_authority,
+                slot, write_version
+            FROM UNNEST(
+                $1::text[], $2::text[], $3::text[], $4::text[], $5::text[], $6::jsonb[],
+                $7::text[], $8::text[], $9::text[], $10::text[], $11::bigint[], $12::bigint[]
+            ) AS r(
+                wallet, token_account, mint, amount, token_program, extensions, delegate,
+                delegated_amount, state, close_authority, slot, write_version
+            )
+        ), old AS (
+            SELECT t.wallet, t.token_account, t.delegate, t.delegated_amount, t.state,
+                t.close_authority, t.slot, t.write_version
+            FROM token_balances t
+            JOIN r ON r.wallet = t.wallet AND r.token_account = t.token_account
+        ), upserted AS (
+            INSERT INTO token_balances (
+                wallet, token_account, mint, amount, ui_amount, token_program, extensions,
+                delegate, delegated_amount, state, close_authority, slot, write_version
+            )
+            SELECT r.wallet, r.token_account, r.mint, r.amount, ui_amount(r.amount, m.decimals),
+                r.token_program, r.extensions, r.delegate, r.delegated_amount, r.state,
+                r.close_authority, r.slot, r.write_version
+            FROM r LEFT JOIN mints m ON m.mint = r.mint
+            ON CONFLICT (wallet, token_account) DO UPDATE SET
+                mint = EXCLUDED.mint,
+                amount = EXCLUDED.amount,
+                ui_amount = EXCLUDED.ui_amount,
+                token_program = EXCLUDED.token_program,
+                extensions = EXCLUDED.extensions,
+                delegate = EXCLUDED.delegate,
+                delegated_amount = EXCLUDED.delegated_amount,
+                state = EXCLUDED.state,
+                close_authority = EXCLUDED.close_authority,
+                slot = EXCLUDED.slot,
+                write_version = EXCLUDED.write_version,
+                updated_at = CURRENT_TIMESTAMP
+            WHERE (token_balances.slot, token_balances.write_version)
+                < (EXCLUDED.slot, EXCLUDED.write_version)
+            RETURNING wallet, token_account
+        )
+        SELECT u.wallet, u.token_account, o.delegate,
+            CAST(o.delegated_amount AS TEXT) AS delegated_amount,
+            COALESCE(o.state = 'frozen', false) AS frozen, o.close_authority,
+            o.slot, o.write_version
+        FROM upserted u
+        LEFT JOIN old o ON o.wallet = u.wallet AND o.token_account = u.token_account",
+    )
+    .bind(wallets)
+    .bind(token_accounts)
+    .bind(mints)
+    .bind(amounts)
+    .bind(token_programs)
+    .bind(extensions)
+    .bind(delegates)
+    .bind(delegated_amounts)
+    .bind(states)
+    .bind(close_authorities)
+    .bind(slots)
+    .bind(write_versions)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let delegated_amount: Option<String> = row.get("delegated_amount");
+            let previous = TokenAuthorities {
+                delegate: row.get("delegate"),
+                delegated_amount: delegated_amount
+                    .and_then(|amount| amount.parse().ok())
+                    .unwrap_or_default(),
+                frozen: row.get("frozen"),
+                close_authority: row.get("close_authority"),
+            };
+            let slot: Option<i64> = row.get("slot");
+            let write_version: Option<i64> = row.get("write_version");
+            let version = slot.map(|slot| (slot as u64, write_version.unwrap_or_default() as u64));
+            (
+                row.get("wallet"),
+                row.get("token_account"),
+                previous,
+                version,
+            )
+        })
+        .collect())
+}
+
+// rows are (wallet, token account, mint, balance), every change to a holding and not only
+// the newest. Changes that are already stored are skipped
+pub async fn insert_token_account_history(
+    pool: &Pool<Postgres>,
+    changes: &[(&str, &str, &str, &TokenBalance)],
+) -> Result<(), sqlx::Error> {
+    let mut wallets = Vec::with_capacity(changes.len());
+    let mut token_accounts = Vec::with_capacity(changes.len());
+    let mut mints = Vec::with_capacity(changes.len());
+    let mut amounts = Vec::with_capacity(changes.len());
+    let mut delegates = Vec::with_capacity(changes.len());
+    let mut delegated_amounts = Vec::with_capacity(changes.len());
+    let mut states = Vec::with_capacity(changes.len());
+    let mut close_authorities = Vec::with_capacity(changes.len());
+    let mut slots = Vec::with_capacity(changes.len());
+    let mut write_versions = Vec::with_capacity(changes.len());
+
+    for (wallet, token_account, mint, balance) in changes {
+        let authorities = balance.authorities.as_ref();
+        wallets.push(*wallet);
+        token_accounts.push(*token_account);
+        mints.push(*mint);
+        amounts.push(balance.amount.to_string());
+        delegates.push(authorities.and_then(|authorities| authorities.delegate.as_deref()));
+        delegated_amounts.push(
+            authorities
+                .map(|authorities| authorities.delegated_amount)
+                .unwrap_or_default()
+                .to_string(),
+        );
+        states.push(match authorities {
+            Some(authorities) if authorities.frozen => "frozen",
+            _ => "initialized",
+        });
+        close_authorities
+            .push(authorities.and_then(|authorities| authorities.close_authority.as_deref()));
+        slots.push(balance.slot as i64);
+        write_versions.push(balance.write_version as i64);
+    }
+
+    sqlx::query(
+        "INSERT INTO token_account_history (
+            wallet, token_account, mint, amount, delegate, delegated_amount, state,
+            close_authority, slot, write_version
+        )
+        SELECT wallet, token_account, mint, CAST(amount AS NUMERIC), delegate,
+            CAST(delegated_amount AS NUMERIC), state, close_authority, slot, write_version
+        FROM UNNEST(
+            $1::text[], $2::text[], $3::text[], $4::text[], $5::text[], $6::text[], $7::text[],
+            $8::text[], $9::bigint[], $10::bigint[]
+        ) AS r(
+            wallet, token_account, mint, amount, delegate, delegated_amount, state,
+            close_authority, slot, write_version
+        )
+        ON CONFLICT (wallet, mint, slot, write_version) DO NOTHING",
+    )
+    .bind(wallets)
+    .bind(token_accounts)
+    .bind(mints)
+    .bind(amounts)
+    .bind(delegates)
+    .bind(delegated_amounts)
+    .bind(states)
+    .bind(close_authorities)
+    .bind(slots)
+    .bind(write_versions)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// rows are (token account, wallet, mint, token program) of every stored holding
+pub async fn token_accounts(
+    pool: &Pool<Postgres>,
+) -> Result<Vec<(String, String, String, String)>, sqlx::Error> {
+    sqlx::query_as("SELECT token_account, wallet, mint, token_program FROM token_balances")
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn insert_security_events(
+    pool: &Pool<Postgres>,
+    events: &[SecurityEvent],
+) -> Result<(), sqlx::Error> {
+    let mut wallets = Vec::with_capacity(events.len());
+    let mut mints = Vec::with_capacity(events.len());
+    let mut kinds = Vec::with_capacity(events.len());
+    let mut details = Vec::with_capacity(events.len());
+    let mut slots = Vec::with_capacity(events.len());
+
+    for event in events {
+        wallets.push(event.wallet.as_str());
+        mints.push(event.mint.as_str());
+        kinds.push(event.event);
+        details.push(&event.details);
+        slots.push(event.slot as i64);
+    }
+
+    sqlx::query(
+        "INSERT INTO security_events (wallet, mint, event, details, slot)
+        SELECT * FROM UNNEST($1::text[], $2::text[], $3::text[], $4::jsonb[], $5::bigint[])",
+    )
+    .bind(wallets)
+    .bind(mints)
+    .bind(kinds)
+    .bind(details)
+    .bind(slots)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// also fills in `ui_amount` of the mint's balances, which may have been written first
+pub async fn upsert_mints(
+    pool: &Pool<Postgres>,
+    mints: &HashMap<String, MintInfo>,
+) -> Result<(), sqlx::Error> {
+    let mut accounts = Vec::with_capacity(mints.len());
+    let mut decimals = Vec::with_capacity(mints.len());
+    let mut supplies = Vec::with_capacity(mints.len());
+    let mut mint_authorities = Vec::with_capacity(mints.len());
+    let mut freeze_authorities = Vec::with_capacity(mints.len());
+    let mut token_programs = Vec::with_capacity(mints.len());
+    let mut extensions = Vec::with_capacity(mints.len());
+    let mut slots = Vec::with_capacity(mints.len());
+    let mut write_versions = Vec::with_capacity(mints.len());
+
+    for (mint, info) in mints {
+        accounts.push(mint.as_str());
+        decimals.push(info.decimals as i16);
+        supplies.push(info.supply.to_string());
+        mint_authorities.push(info.mint_authority.as_deref());
+        freeze_authorities.push(info.freeze_authority.as_deref());
+        token_programs.push(info.token_program);
+        extensions.push(&info.extensions);
+        slots.push(info.slot as i64);
+        write_versions.push(info.write_version as i64);
+    }
+
+    sqlx::query(&format!(
+        "WITH upserted AS (
+            INSERT INTO mints (
+                mint, decimals, supply, mint_authority, freeze_authority, token_program,
+                extensions, slot, write_version
+            )
+            SELECT mint, decimals, CAST(supply AS NUMERIC), mint_authority, freeze_authority,
+                token_program, extensions, slot, write_version
+            FROM UNNEST(
+                $1::text[], $2::smallint[], $3::text[], $4::text[], $5::text[], $6::text[],
+                $7::jsonb[], $8::bigint[], $9::bigint[]
+            ) AS r(
+                mint, decimals, supply, mint_authority, freeze_authority, token_program,
+                extensions, slot, write_version
+            )
+            ON CONFLICT (mint) DO UPDATE SET
+                decimals = EXCLUDED.decimals,
+                supply = EXCLUDED.supply,
+                mint_authority = EXCLUDED.mint_authority,
+                freeze_authority = EXCLUDED.freeze_authority,
+                token_program = EXCLUDED.token_program,
+                extensions = EXCLUDED.extensions,
+                slot = EXCLUDED.slot,
+                write_version = EXCLUDED.write_version,
+                updated_at = CURRENT_TIMESTAMP
+            WHERE {}
+            RETURNING mint, decimals
+        )
+        UPDATE token_balances t SET ui_amount = ui_amount(t.amount, u.decimals)
+        FROM upserted u
+        WHERE t.mint = u.mint AND t.ui_amount IS DISTINCT FROM ui_amount(t.amount, u.decimals)",
+        newer_version_sql("mints")
+    ))
+    .bind(accounts)
+    .bind(decimals)
+    .bind(supplies)
+    .bind(mint_authorities)
+    .bind(freeze_authorities)
+    .bind(token_programs)
+    .bind(extensions)
+    .bind(slots)
+    .bind(write_versions)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn upsert_nft_metadata(
+    pool: &Pool<Postgres>,
+    metadata: &HashMap<String, NftMetadata>,
+) -> Result<(), sqlx::Error> {
+    let mut mints = Vec::with_capacity(metadata.len());
+    let mut update_authorities = Vec::with_capacity(metadata.len());
+    let mut names = Vec::with_capacity(metadata.len());
+    let mut symbols = Vec::with_capacity(metadata.len());
+    let mut uris = Vec::with_capacity(metadata.len());
+    let mut seller_fees = Vec::with_capacity(metadata.len());
+    let mut creators = Vec::with_capacity(metadata.len());
+    let mut primary_sales = Vec::with_capacity(metadata.len());
+    let mut mutable = Vec::with_capacity(metadata.len());
+    let mut token_standards = Vec::with_capacity(metadata.len());
+    let mut collections = Vec::with_capacity(metadata.len());
+    let mut collections_verified = Vec::with_capacity(metadata.len());
+    let mut slots = Vec::with_capacity(metadata.len());
+    let mut write_versions = Vec::with_capacity(metadata.len());
+
+    for (mint, row) in metadata {
+        mints.push(mint.as_str());
+        update_authorities.push(row.update_authority.as_str());
+        names.push(row.name.as_str());
+        symbols.push(row.symbol.as_str());
+        uris.push(row.uri.as_str());
+        seller_fees.push(row.seller_fee_basis_points as i32);
+        creators.push(&row.creators);
+        primary_sales.push(row.primary_sale_happened);
+        mutable.push(row.is_mutable);
+        token_standards.push(row.token_standard);
+        collections.push(row.collection.as_deref());
+        collections_verified.push(row.collection_verified);
+        slots.push(row.slot as i64);
+        write_versions.push(row.write_version as i64);
+    }
+
+    sqlx::query(&format!(
+        "INSERT INTO nft_metadata (
+            mint, update_authority, name, symbol, uri, seller_fee_basis_points, creators,
+            primary_sale_happened, is_mutable, token_standard, collection, collection_verified,
+            slot, write_version
+        )
+        SELECT * FROM UNNEST(
+            $1::text[], $2::text[], $3::text[], $4::text[], $5::text[], $6::int[], $7::jsonb[],
+            $8::bool[], $9::bool[], $10::text[], $11::text[], $12::bool[], $13::bigint[],
+            $14::bigint[]
+        )
+        ON CONFLICT (mint) DO UPDATE SET
+            update_authority = EXCLUDED.update_authority,
+            name = EXCLUDED.name,
+            symbol = EXCLUDED.symbol,
+            uri = EXCLUDED.uri,
+            seller_fee_basis_points = EXCLUDED.seller_fee_basis_points,
+            creators = EXCLUDED.creators,
+            primary_sale_happened = EXCLUDED.primary_sale_happened,
+            is_mutable = EXCLUDED.is_mutable,
+            token_standard = EXCLUDED.token_standard,
+            collection = EXCLUDED.collection,
+            collection_verified = EXCLUDED.collection_verified,
+            slot = EXCLUDED.slot,
+            write_version = EXCLUDED.write_version,
+            updated_at = CURRENT_TIMESTAMP
+        WHERE {}",
+        newer_version_sql("nft_metadata")
+    ))
+    .bind(mints)
+    .bind(update_authorities)
+    .bind(names)
+    .bind(symbols)
+    .bind(uris)
+    .bind(seller_fees)
+    .bind(creators)
+    .bind(primary_sales)
+    .bind(mutable)
+    .bind(token_standards)
+    .bind(collections)
+    .bind(collections_verified)
+    .bind(slots)
+    .bind(write_versions)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn upsert_master_editions(
+    pool: &Pool<Postgres>,
+    editions: &HashMap<String, MasterEdition>,
+) -> Result<(), sqlx::Error> {
+    let mut mints = Vec::with_capacity(editions.len());
+    let mut supplies = Vec::with_capacity(editions.len());
+    let mut max_supplies = Vec::with_capacity(editions.len());
+    let mut slots = Vec::with_capacity(editions.len());
+    let mut write_versions = Vec::with_capacity(editions.len());
+
+    for (mint, edition) in editions {
+        mints.push(mint.as_str());
+        supplies.push(edition.supply.to_string());
+        max_supplies.push(edition.max_supply.map(|max_supply| max_supply.to_string()));
+        slots.push(edition.slot as i64);
+        write_versions.push(edition.write_version as i64);
+    }
+
+    sqlx::query(&format!(
+        "INSERT INTO master_editions (mint, supply, max_supply, slot, write_version)
+        SELECT mint, CAST(supply AS NUMERIC), CAST(max_supply AS NUMERIC), slot, write_version
+        FROM UNNEST($1::text[], $2::text[], $3::text[], $4::bigint[], $5::bigint[])
+            AS r(mint, supply, max_supply, slot, write_version)
+        ON CONFLICT (mint) DO UPDATE SET
+            supply = EXCLUDED.supply,
+            max_supply = EXCLUDED.max_supply,
+            slot = EXCLUDED.slot,
+            write_version = EXCLUDED.write_version,
+            updated_at = CURRENT_TIMESTAMP
+        WHERE {}",
+        newer_version_sql("master_editions")
+    ))
+    .bind(mints)
+    .bind(supplies)
+    .bind(max_supplies)
+    .bind(slots)
+    .bind(write_versions)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// one statement per native account type in `accounts`
+pub async fn upsert_native_accounts(
+    pool: &Pool<Postgres>,
+    accounts: &HashMap<String, NativeAccount>,
+) -> Result<(), sqlx::Error> {
+    let mut stakes = Vec::new();
+    let mut votes = Vec::new();
+    let mut nonces = Vec::new();
+    for (account, native) in accounts {
+        match &native.state {
+            NativeState::Stake(stake) => stakes.push((account.as_str(), native, stake)),
+            NativeState::Vote(vote) => votes.push((account.as_str(), native, vote)),
+            NativeState::Nonce(nonce) => nonces.push((account.as_str(), native, nonce)),
+        }
+    }
+
+    if !stakes.is_empty() {
+        upsert_stake_accounts(pool, &stakes).await?;
+    }
+    if !votes.is_empty() {
+        upsert_vote_accounts(pool, &votes).await?;
+    }
+    if !nonces.is_empty() {
+        upsert_nonce_accounts(pool, &nonces).await?;
+    }
+    Ok(())
+}
+
+async fn upsert_stake_accounts(
+    pool: &Pool<Postgres>,
+    stakes: &[(&str, &NativeAccount, &StakeAccount)],
+) -> Result<(), sqlx::Error> {
+    let mut accounts = Vec::with_capacity(stakes.len());
+    let mut stakers = Vec::with_capacity(stakes.len());
+    let mut withdrawers = Vec::with_capacity(stakes.len());
+    let mut lamports = Vec::with_capacity(stakes.len());
+    let mut rent_exempt_reserves = Vec::with_capacity(stakes.len());
+    let mut lockup_timestamps = Vec::with_capacity(stakes.len());
+    let mut lockup_epochs = Vec::with_capacity(stakes.len());
+    let mut lockup_custodians = Vec::with_capacity(stakes.len());
+    let mut voters = Vec::with_capacity(stakes.len());
+    let mut delegated_stakes = Vec::with_capacity(stakes.len());
+    let mut activation_epochs = Vec::with_capacity(stakes.len());
+    let mut deactivation_epochs = Vec::with_capacity(stakes.len());
+    let mut credits_observed = Vec::with_capacity(stakes.len());
+    let mut slots = Vec::with_capacity(stakes.len());
+    let mut write_versions = Vec::with_capacity(stakes.len());
+
+    for (account, native, stake) in stakes {
+        accounts.push(*account);
+        stakers.push(stake.staker.as_str());
+        withdrawers.push(stake.withdrawer.as_str());
+        lamports.push(native.lamports as i64);
+        rent_exempt_reserves.push(stake.rent_exempt_reserve as i64);
+        lockup_timestamps.push(stake.lockup_unix_timestamp);
+        lockup_epochs.push(stake.lockup_epoch.to_string());
+        lockup_custodians.push(stake.lockup_custodian.as_deref());
+        voters.push(stake.voter.as_deref());
+        delegated_stakes.push(stake.delegated_stake.map(|stake| stake as i64));
+        activation_epochs.push(stake.activation_epoch.map(|epoch| epoch as i64));
+        deactivation_epochs.push(stake.deactivation_epoch.map(|epoch| epoch as i64));
+        credits_observed.push(stake.credits_observed.map(|credits| credits as i64));
+        slots.push(native.slot as i64);
+        write_versions.push(native.write_version as i64);
+    }
+
+    sqlx::query(&format!(
+        "INSERT INTO stake_accounts (
+            account, staker, withdrawer, lamports, rent_exempt_reserve, lockup_unix_timestamp,
+            lockup_epoch, lockup_custodian, voter, delegated_stake, activation_epoch,
+            deactivation_epoch, credits_observed, slot, write_version
+        )
+        SELECT account, staker, withdrawer, lamports, rent_exempt_reserve, lockup_unix_timestamp,
+            CAST(lockup_epoch AS NUMERIC), lockup_custodian, voter, delegated_stake,
+            activation_epoch, deactivation_epoch, credits_observed, slot, write_version
+        FROM UNNEST(
+            $1::text[], $2::text[], $3::text[], $4::bigint[], $5::bigint[], $6::bigint[],
+            $7::text[], $8::text[], $9::text[], $10::bigint[], $11::bigint[], $12::bigint[],
+            $13::bigint[], $14::bigint[], $15::bigint[]
+        ) AS r(
+            account, staker, withdrawer, lamports, rent_exempt_reserve, lockup_unix_timestamp,
+            lockup_epoch, lockup_custodian, voter, delegated_stake, activation_epoch,
+            deactivation_epoch, credits_observed, slot, write_version
+        )
+        ON CONFLICT (account) DO UPDATE SET
+            staker = EXCLUDED.staker,
+            withdrawer = EXCLUDED.withdrawer,
+            lamports = EXCLUDED.lamports,
+            rent_exempt_reserve = EXCLUDED.rent_exempt_reserve,
+            lockup_unix_timestamp = EXCLUDED.lockup_unix_timestamp,
+            lockup_epoch = EXCLUDED.lockup_epoch,
+            lockup_custodian = EXCLUDED.lockup_custodian,
+            voter = EXCLUDED.voter,
+            delegated_stake = EXCLUDED.delegated_stake,
+            activation_epoch = EXCLUDED.activation_epoch,
+            deactivation_epoch = EXCLUDED.deactivation_epoch,
+            credits_observed = EXCLUDED.credits_observed,
+            slot = EXCLUDED.slot,
+            write_version = EXCLUDED.write_version,
+            updated_at = CURRENT_TIMESTAMP
+        WHERE {}",
+        newer_version_sql("stake_accounts")
+    ))
+    .bind(accounts)
+    .bind(stakers)
+    .bind(withdrawers)
+    .bind(lamports)
+    .bind(rent_exempt_reserves)
+    .bind(lockup_timestamps)
+    .bind(lockup_epochs)
+    .bind(lockup_custodians)
+    .bind(voters)
+    .bind(delegated_stakes)
+    .bind(activation_epochs)
+    .bind(deactivation_epochs)
+    .bind(credits_observed)
+    .bind(slots)
+    .bind(write_versions)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+async fn upsert_vote_accounts(
+    pool: &Pool<Postgres>,
+    votes: &[(&str, &NativeAccount, &VoteAccount)],
+) -> Result<(), sqlx::Error> {
+    let mut accounts = Vec::with_capacity(votes.len());
+    let mut node_pubkeys = Vec::with_capacity(votes.len());
+    let mut withdrawers = Vec::with_capacity(votes.len());
+    let mut voters = Vec::with_capacity(votes.len());
+    let mut commissions = Vec::with_capacity(votes.len());
+    let mut credits = Vec::with_capacity(votes.len());
+    let mut last_vote_slots = Vec::with_capacity(votes.len());
+    let mut root_slots = Vec::with_capacity(votes.len());
+    let mut lamports = Vec::with_capacity(votes.len());
+    let mut slots = Vec::with_capacity(votes.len());
+    let mut write_versions = Vec::with_capacity(votes.len());
+
+    for (account, native, vote) in votes {
+        accounts.push(*account);
+        node_pubkeys.push(vote.node_pubkey.as_str());
+        withdrawers.push(vote.authorized_withdrawer.as_str());
+        voters.push(vote.authorized_voter.as_deref());
+        commissions.push(vote.commission as i16);
+        credits.push(vote.credits as i64);
+        last_vote_slots.push(vote.last_vote_slot.map(|slot| slot as i64));
+        root_slots.push(vote.root_slot.map(|slot| slot as i64));
+        lamports.push(native.lamports as i64);
+        slots.push(native.slot as i64);
+        write_versions.push(native.write_version as i64);
+    }
+
+    sqlx::query(&format!(
+        "INSERT INTO vote_accounts (
+            account, node_pubkey, authorized_withdrawer, authorized_voter, commission, credits,
+            last_vote_slot, root_slot, lamports, slot, write_version
+        )
+        SELECT * FROM UNNEST(
+            $1::text[], $2::text[], $3::text[], $4::text[], $5::smallint[], $6::bigint[],
+            $7::bigint[], $8::bigint[], $9::bigint[], $10::bigint[], $11::bigint[]
+        )
+        ON CONFLICT (account) DO UPDATE SET
+            node_pubkey = EXCLUDED.node_pubkey,
+            authorized_withdrawer = EXCLUDED.authorized_withdrawer,
+            authorized_voter = EXCLUDED.authorized_voter,
+            commission = EXCLUDED.commission,
+            credits = EXCLUDED.credits,
+            last_vote_slot = EXCLUDED.last_vote_slot,
+            root_slot = EXCLUDED.root_slot,
+            lamports = EXCLUDED.lamports,
+            slot = EXCLUDED.slot,
+            write_version = EXCLUDED.write_version,
+            updated_at = CURRENT_TIMESTAMP
+        WHERE {}",
+        newer_version_sql("vote_accounts")
+    ))
+    .bind(accounts)
+    .bind(node_pubkeys)
+    .bind(withdrawers)
+    .bind(voters)
+    .bind(commissions)
+    .bind(credits)
+    .bind(last_vote_slots)
+    .bind(root_slots)
+    .bind(lamports)
+    .bind(slots)
+    .bind(write_versions)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+async fn upsert_nonce_accounts(
+    pool: &Pool<Postgres>,
+    nonces: &[(&str, &NativeAccount, &NonceAccount)],
+) -> Result<(), sqlx::Error> {
+    let mut accounts = Vec::with_capacity(nonces.len());
+    let mut authorities = Vec::with_capacity(nonces.len());
+    let mut blockhashes = Vec::with_capacity(nonces.len());
+    let mut fees = Vec::with_capacity(nonces.len());
+    let mut lamports = Vec::with_capacity(nonces.len());
+    let mut slots = Vec::with_capacity(nonces.len());
+    let mut write_versions = Vec::with_capacity(nonces.len());
+
+    for (account, native, nonce) in nonces {
+        accounts.push(*account);
+        authorities.push(nonce.authority.as_str());
+        blockhashes.push(nonce.blockhash.as_str());
+        fees.push(nonce.lamports_per_signature as i64);
+        lamports.push(native.lamports as i64);
+        slots.push(native.slot as i64);
+        write_versions.push(native.write_version as i64);
+    }
+
+    sqlx::query(&format!(
+        "INSERT INTO nonce_accounts (
+            account, authority, blockhash, lamports_per_signature, lamports, slot, write_version
+        )
+        SELECT * FROM UNNEST(
+            $1::text[], $2::text[], $3::text[], $4::bigint[], $5::bigint[], $6::bigint[],
+            $7::bigint[]
+        )
+        ON CONFLICT (account) DO UPDATE SET
+            authority = EXCLUDED.authority,
+            blockhash = EXCLUDED.blockhash,
+            lamports_per_signature = EXCLUDED.lamports_per_signature,
+            lamports = EXCLUDED.lamports,
+            slot = EXCLUDED.slot,
+            write_version = EXCLUDED.write_version,
+            updated_at = CURRENT_TIMESTAMP
+        WHERE {}",
+        newer_version_sql("nonce_accounts")
+    ))
+    .bind(accounts)
+    .bind(authorities)
+    .bind(blockhashes)
+    .bind(fees)
+    .bind(lamports)
+    .bind(slots)
+    .bind(write_versions)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// epochs are epoch -> first slot seen in it
+pub async fn upsert_epochs(
+    pool: &Pool<Postgres>,
+    epochs: &HashMap<u64, u64>,
+) -> Result<(), sqlx::Error> {
+    let (numbers, slots): (Vec<i64>, Vec<i64>) = epochs
+        .iter()
+        .map(|(epoch, slot)| (*epoch as i64, *slot as i64))
+        .unzip();
+
+    sqlx::query(
+        "INSERT INTO epochs (epoch, first_slot)
+        SELECT * FROM UNNEST($1::bigint[], $2::bigint[])
+        ON CONFLICT (epoch) DO UPDATE SET first_slot = LEAST(epochs.first_slot, EXCLUDED.first_slot)",
+    )
+    .bind(numbers)
+    .bind(slots)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// nested arrays can't go through UNNEST, so the per-row lists travel as JSON arrays
+pub async fn insert_transactions(
+    pool: &Pool<Postgres>,
+    transactions: &[Transaction],
+) -> Result<(), sqlx::Error> {
+    let mut signatures = Vec::with_capacity(transactions.len());
+    let mut slots = Vec::with_capacity(transactions.len());
+    let mut indexes = Vec::with_capacity(transactions.len());
+    let mut fees = Vec::with_capacity(transactions.len());
+    let mut errs = Vec::with_capacity(transactions.len());
+    let mut signers = Vec::with_capacity(transactions.len());
+    let mut account_keys = Vec::with_capacity(transactions.len());
+    let mut log_messages = Vec::with_capacity(transactions.len());
+    let mut compute_units = Vec::with_capacity(transactions.len());
+
+    for transaction in transactions {
+        signatures.push(transaction.signature.as_str());
+        slots.push(transaction.slot as i64);
+        indexes.push(transaction.index.map(|index| index as i64));
+        fees.push(transaction.fee as i64);
+        errs.push(transaction.err.as_deref());
+        signers.push(serde_json::json!(transaction.signers));
+        account_keys.push(serde_json::json!(transaction.account_keys));
+        log_messages.push(
+            transaction
+                .log_messages
+                .as_ref()
+                .map(|logs| serde_json::json!(logs)),
+        );
+        compute_units.push(transaction.compute_units.map(|units| units as i64));
+    }
+
+    sqlx::query(
+        "INSERT INTO transactions (
+            signature, slot, transaction_index, fee, success, err,
+            signers, account_keys, log_messages, compute_units
+        )
+        SELECT r.signature, r.slot, r.transaction_index, r.fee, r.err IS NULL, r.err,
+            ARRAY(SELECT jsonb_array_elements_text(r.signers)),
+            ARRAY(SELECT jsonb_array_elements_text(r.account_keys)),
+            CASE WHEN r.log_messages IS NULL THEN NULL
+                ELSE ARRAY(SELECT jsonb_array_elements_text(r.log_messages)) END,
+            r.compute_units
+        FROM UNNEST(
+            $1::text[], $2::bigint[], $3::bigint[], $4::bigint[], $5::text[],
+            $6::jsonb[], $7::jsonb[], $8::jsonb[], $9::bigint[]
+        ) AS r(
+            signature, slot, transaction_index, fee, err,
+            signers, account_keys, log_messages, compute_units
+        )
+        ON CONFLICT (signature, slot) DO NOTHING",
+    )
+    .bind(signatures)
+    .bind(slots)
+    .bind(indexes)
+    .bind(fees)
+    .bind(errs)
+    .bind(signers)
+    .bind(account_keys)
+    .bind(log_messages)
+    .bind(compute_units)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn insert_events(
+    pool: &Pool<Postgres>,
+    events: &[ProgramEvent],
+) -> Result<(), sqlx::Error> {
+    let mut signatures = Vec::with_capacity(events.len());
+    let mut slots = Vec::with_capacity(events.len());
+    let mut event_indexes = Vec::with_capacity(events.len());
+    let mut instruction_indexes = Vec::with_capacity(events.len());
+    let mut program_ids = Vec::with_capacity(events.len());
+    let mut names = Vec::with_capacity(events.len());
+    let mut data = Vec::with_capacity(events.len());
+
+    for event in events {
+        signatures.push(event.signature.as_str());
+        slots.push(event.slot as i64);
+        event_indexes.push(event.event_index as i32);
+        instruction_indexes.push(event.instruction_index as i32);
+        program_ids.push(event.program_id.as_str());
+        names.push(event.name.as_str());
+        data.push(&event.data);
+    }
+
+    sqlx::query(
+        "INSERT INTO events (
+            signature, slot, event_index, instruction_index, program_id, name, data
+        )
+        SELECT * FROM UNNEST(
+            $1::text[], $2::bigint[], $3::integer[], $4::integer[], $5::text[], $6::text[],
+            $7::jsonb[]
+        )
+        ON CONFLICT (signature, slot, event_index) DO NOTHING",
+    )
+    .bind(signatures)
+    .bind(slots)
+    .bind(event_indexes)
+    .bind(instruction_indexes)
+    .bind(program_ids)
+    .bind(names)
+    .bind(data)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn upsert_blocks(
+    pool: &Pool<Postgres>,
+    blocks: &HashMap<u64, Block>,
+) -> Result<(), sqlx::Error> {
+    let mut slots = Vec::with_capacity(blocks.len());
+    let mut parent_slots = Vec::with_capacity(blocks.len());
+    let mut blockhashes = Vec::with_capacity(blocks.len());
+    let mut parent_blockhashes = Vec::with_capacity(blocks.len());
+    let mut block_times = Vec::with_capacity(blocks.len());
+    let mut block_heights = Vec::with_capacity(blocks.len());
+    let mut rewards = Vec::with_capacity(blocks.len());
+    let mut transaction_counts = Vec::with_capacity(blocks.len());
+
+    for block in blocks.values() {
+        slots.push(block.slot as i64);
+        parent_slots.push(block.parent_slot.map(|slot| slot as i64));
+        blockhashes.push(block.blockhash.as_str());
+        parent_blockhashes.push(block.parent_blockhash.as_deref());
+        block_times.push(block.block_time);
+        block_heights.push(block.block_height.map(|height| height as i64));
+        rewards.push(&block.rewards);
+        transaction_counts.push(block.executed_transaction_count.map(|count| count as i64));
+    }
+
+    sqlx::query(
+        "INSERT INTO blocks (
+            slot, parent_slot, blockhash, parent_blockhash, block_time,
+            block_height, rewards, executed_transaction_count
+        )
+        SELECT r.slot, r.parent_slot, r.blockhash, r.parent_blockhash, to_timestamp(r.block_time),
+            r.block_height, r.rewards, r.executed_transaction_count
+        FROM UNNEST(
+            $1::bigint[], $2::bigint[], $3::text[], $4::text[], $5::bigint[],
+            $6::bigint[], $7::jsonb[], $8::bigint[]
+        ) AS r(
+            slot, parent_slot, blockhash, parent_blockhash, block_time,
+            block_height, rewards, executed_transaction_count
+        )
+        ON CONFLICT (slot) DO UPDATE SET
+            parent_slot = EXCLUDED.parent_slot,
+            blockhash = EXCLUDED.blockhash,
+            parent_blockhash = EXCLUDED.parent_blockhash,
+            block_time = EXCLUDED.block_time,
+            block_height = EXCLUDED.block_height,
+            rewards = EXCLUDED.rewards,
+            executed_transaction_count = EXCLUDED.executed_transaction_count,
+            updated_at = CURRENT_TIMESTAMP",
+    )
+    .bind(&slots)
+    .bind(parent_slots)
+    .bind(blockhashes)
+    .bind(parent_blockhashes)
+    .bind(block_times)
+    .bind(block_heights)
+    .bind(rewards)
+    .bind(transaction_counts)
+    .execute(pool)
+    .await?;
+
+    // listing changes usually reach the database before their slot's block
+    sqlx::query(
+        "UPDATE listing_history h SET block_time = b.block_time
+        FROM blocks b
+        WHERE b.slot = ANY($1) AND h.slot = b.slot AND h.block_time IS NULL",
+    )
+    .bind(slots)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// rows are slot -> (parent, status); a status never moves back, e.g. rooted to confirmed
+pub async fn upsert_slots(
+    pool: &Pool<Postgres>,
+    slots: &HashMap<u64, (Option<u64>, &'static str)>,
+) -> Result<(), sqlx::Error> {
+    let mut slot_numbers = Vec::with_capacity(slots.len());
+    let mut parents = Vec::with_capacity(slots.len());
+    let mut statuses = Vec::with_capacity(slots.len());
+
+    for (slot, (parent, status)) in slots {
+        slot_numbers.push(*slot as i64);
+        parents.push(parent.map(|parent| parent as i64));
+        statuses.push(*status);
+    }
+
+    sqlx::query(
+        "INSERT INTO slots (slot, parent, status)
+        SELECT * FROM UNNEST($1::bigint[], $2::bigint[], $3::text[])
+        ON CONFLICT (slot) DO UPDATE SET
+            parent = COALESCE(EXCLUDED.parent, slots.parent),
+            status = EXCLUDED.status,
+            updated_at = CURRENT_TIMESTAMP
+        WHERE (CASE EXCLUDED.status WHEN 'rooted' THEN 2 WHEN 'confirmed' THEN 1 ELSE 0 END)
+            >= (CASE slots.status WHEN 'rooted' THEN 2 WHEN 'confirmed' THEN 1 ELSE 0 END)",
+    )
+    .bind(slot_numbers)
+    .bind(parents)
+    .bind(statuses)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// accounts are account -> (slot, write version) of the close, returns the accounts whose
+// row was older than their close and got deleted
+pub async fn delete_accounts(
+    pool: &Pool<Postgres>,
+    table: &str,
+    accounts: &HashMap<String, (u64, u64)>,
+) -> Result<Vec<String>, sqlx::Error> {
+    let mut keys = Vec::with_capacity(accounts.len());
+    let mut slots = Vec::with_capacity(accounts.len());
+    let mut write_versions = Vec::with_capacity(accounts.len());
+
+    for (account, (slot, write_version)) in accounts {
+        keys.push(account.as_str());
+        slots.push(*slot as i64);
+        write_versions.push(*write_version as i64);
+    }
+
+    sqlx::query_scalar(&format!(
+        "DELETE FROM \"{0}\"
+        USING UNNEST($1::text[], $2::bigint[], $3::bigint[]) AS c(account, slot, write_version)
+        WHERE \"{0}\".account = c.account
+            AND (COALESCE(\"{0}\".slot, -1), COALESCE(\"{0}\".write_version, -1))
+                < (c.slot, c.write_version)
+        RETURNING \"{0}\".account",
+        table
+    ))
+    .bind(keys)
+    .bind(slots)
+    .bind(write_versions)
+    .fetch_all(pool)
+    .await
+}
+
+// every version is kept, not only the newest, so each commitment level can be served
+// the newest one it has reached
+pub async fn insert_account_versions(
+    pool: &Pool<Postgres>,
+    versions: &[AccountVersion],
+) -> Result<(), sqlx::Error> {
+    let mut tables = Vec::with_capacity(versions.len());
+    let mut accounts = Vec::with_capacity(versions.len());
+    let mut slots = Vec::with_capacity(versions.len());
+    let mut write_versions = Vec::with_capacity(versions.len());
+    let mut data = Vec::with_capacity(versions.len());
+
+    for version in versions {
+        tables.push(version.table.as_str());
+        accounts.push(version.account.as_str());
+        slots.push(version.slot as i64);
+        write_versions.push(version.write_version as i64);
+        data.push(version.data.as_ref());
+    }
+
+    sqlx::query(
+        "INSERT INTO account_versions (table_name, account, slot, write_version, data)
+        SELECT * FROM UNNEST($1::text[], $2::text[], $3::bigint[], $4::bigint[], $5::jsonb[])
+        ON CONFLICT (table_name, account, slot, write_version) DO NOTHING",
+    )
+    .bind(tables)
+    .bind(accounts)
+    .bind(slots)
+    .bind(write_versions)
+    .bind(data)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// drops the versions a version from one of the `rooted` slots supersedes, and rooted
+// closes themselves: no commitment level can be served anything older anymore
+pub async fn prune_account_versions(
+    pool: &Pool<Postgres>,
+    rooted: &[i64],
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "DELETE FROM account_versions v
+        USING account_versions n
+        WHERE n.slot = ANY($1)
+            AND v.table_name = n.table_name
+            AND v.account = n.account
+            AND CASE WHEN n.data IS NULL
+                THEN (v.slot, v.write_version) <= (n.slot, n.write_version)
+                ELSE (v.slot, v.write_version) < (n.slot, n.write_version)
+            END",
+    )
+    .bind(rooted)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// records the newest staged row per account as its version, `data` is the version's JSON
+// built from the staged row `r`
+pub fn stage_versions_sql(staging_table: &str, table: &str, data: &str) -> String {
+    format!(
+        "INSERT INTO account_versions (table_name, account, slot, write_version, data)
+        SELECT '{}', r.account, r.slot, r.write_version, {}
+        FROM (SELECT DISTINCT ON (account) * FROM \"{}\" ORDER BY account, slot DESC, write_version DESC) AS r
+        ON CONFLICT (table_name, account, slot, write_version) DO NOTHING",
+        table, data, staging_table
+    )
+}
+
+// streams CSV rows into `table` with COPY, returns the number of rows written
+pub async fn copy_csv(
+    pool: &Pool<Postgres>,
+    table: &str,
+    columns: &str,
+    csv: &str,
+) -> Result<u64, sqlx::Error> {
+    let mut copy = pool
+        .copy_in_raw(&format!(
+            "COPY \"{}\" ({}) FROM STDIN WITH (FORMAT csv)",
+            table, columns
+        ))
+        .await?;
+    copy.send(csv.as_bytes()).await?;
+    copy.finish().await
+}
+
+// one round trip for every (channel, payload) pair of a flush
+pub async fn notify(
+    pool: &Pool<Postgres>,
+    notifications: &[(&str, String)],
+) -> Result<(), sqlx::Error> {
+    if notifications.is_empty() {
+        return Ok(());
+    }
+
+    let (channels, payloads): (Vec<&str>, Vec<&str>) = notifications
+        .iter()
+        .map(|(channel, payload)| (*channel, payload.as_str()))
+        .unzip();
+
+    sqlx::query("SELECT pg_notify(channel, payload) FROM UNNEST($1::text[], $2::text[]) AS n(channel, payload)")
+        .bind(channels)
+        .bind(payloads)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
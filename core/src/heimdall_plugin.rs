@@ -1,25 +1,71 @@
 use anchor_lang::solana_program::clock::Slot;
-use anchor_lang::AnchorDeserialize;
 use solana_geyser_plugin_interface::geyser_plugin_interface::{
-    GeyserPlugin, GeyserPluginError, ReplicaAccountInfoVersions, Result as PluginResult,
+    GeyserPlugin, GeyserPluginError, ReplicaAccountInfoV3, ReplicaAccountInfoVersions,
+    ReplicaBlockInfoVersions, ReplicaTransactionInfoVersions, Result as PluginResult, SlotStatus,
 };
-use spl_token::solana_program::program_pack::Pack;
-use spl_token::solana_program::pubkey::Pubkey;
-use spl_token::state::Account as TokenAccount;
-use spl_token::ID as SPL_TOKEN_PROGRAM_ID;
-use sqlx::{postgres::PgPoolOptions, Pool, Postgres, Row};
-use tokio::runtime::Runtime;
+use sqlx::{Pool, Postgres};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, RwLock,
+    },
+};
+use tokio::{runtime::Runtime, task::JoinHandle};
 
 use crate::{
-    config::Config,
-    models::{AnchorListing, Listing},
+    backfill::Backfill,
+    config::{Config, SinkConfig},
+    db,
+    logging::{limited, setup_logger},
+    logs, metaplex,
+    metrics::{self, metrics, set_max},
+    migrations,
+    models::{
+        AnchorListing, Block, Listing, MasterEdition, NativeAccount, ProgramEvent, TokenBalance,
+        Transaction,
+    },
+    native,
+    registry::{AccountRegistry, DecodedAccount, LISTINGS_TABLE},
+    reload::{load_tracked, Reloader, SharedTracked},
+    sink::{postgres::PostgresSink, AccountSink, MemorySink, NdjsonSink, SinkEvent},
+    token,
 };
 
+// accounts we've written rows for, so their closure can be traced back to those rows
+#[derive(Debug)]
+enum KnownAccount {
+    Program {
+        table: String,
+    },
+    TokenAccount {
+        user: String,
+        mint: String,
+        token_program: &'static str,
+    },
+}
+
 #[derive(Debug)]
 pub struct Heimdall {
     db_pool: Option<Pool<Postgres>>,
     config: Option<Config>,
-    programs: Vec<[u8; 32]>,
+    tracked: SharedTracked,
+    reloader: Reloader,
+    known_accounts: RwLock<HashMap<[u8; 32], KnownAccount>>,
+    // mints of token accounts owned by tracked users and of listings
+    tracked_mints: RwLock<HashSet<[u8; 32]>>,
+    // master edition address -> mint, for every tracked mint
+    master_editions: RwLock<HashMap<[u8; 32], [u8; 32]>>,
+    // newest epoch the clock sysvar reported
+    epoch: AtomicU64,
+    // fetches the accounts of newly tracked mints, when an RPC URL is configured
+    backfill: Option<Backfill>,
+    postgres: Option<Arc<PostgresSink>>,
+    // every configured sink, the Postgres one included if it's listed
+    sinks: Vec<Arc<dyn AccountSink>>,
+    // merges the staged snapshot once startup ends, set the first time it does
+    startup_merge: Mutex<Option<JoinHandle<()>>>,
+    metrics_server: Option<JoinHandle<()>>,
     runtime: Runtime,
 }
 
@@ -28,7 +74,17 @@ impl Default for Heimdall {
         Heimdall {
             db_pool: None,
             config: None,
-            programs: Vec::new(),
+            tracked: SharedTracked::default(),
+            reloader: Reloader::default(),
+            known_accounts: RwLock::new(HashMap::new()),
+            tracked_mints: RwLock::new(HashSet::new()),
+            master_editions: RwLock::new(HashMap::new()),
+            epoch: AtomicU64::new(0),
+            backfill: None,
+            postgres: None,
+            sinks: Vec::new(),
+            startup_merge: Mutex::new(None),
+            metrics_server: None,
             runtime: Runtime::new().unwrap(),
         }
     }
@@ -39,100 +95,165 @@ impl GeyserPlugin for Heimdall {
         "Heimdall"
     }
 
-    fn on_load(&mut self, config_file: &str, _is_reload: bool) -> PluginResult<()> {
+    fn on_load(&mut self, config_file: &str, is_reload: bool) -> PluginResult<()> {
         let config = match Config::load(config_file) {
             Ok(c) => c,
-            Err(_e) => {
+            Err(e) => {
                 return Err(GeyserPluginError::ConfigFileReadError {
-                    msg: String::from("Error opening, or reading config file"),
+                    msg: format!("Error opening, or reading config file: {}", e),
                 });
             }
         };
+        setup_logger(&config.log_level);
 
-        let rt = Runtime::new().unwrap();
-        let pool = rt
-            .block_on(async {
-                PgPoolOptions::new()
-                    .max_connections(5)
-                    .connect(&config.database_url)
-                    .await
-            })
-            .map_err(|_e| GeyserPluginError::ConfigFileReadError {
-                msg: String::from("Error connecting to local postgres database"),
-            })?;
+        // only the postgres sink needs the database
+        let pool = if config.has_postgres_sink() {
+            let pool = self
+                .runtime
+                .block_on(db::connect_with_retry(
+                    &config.database_url,
+                    &config.database,
+                ))
+                .map_err(|e| GeyserPluginError::ConfigFileReadError {
+                    msg: format!("Error connecting to local postgres database: {}", e),
+                })?;
+            // the plugin doesn't run against a schema it can't bring up to date
+            self.runtime
+                .block_on(migrations::migrate(&pool))
+                .map_err(|e| GeyserPluginError::Custom(Box::new(e)))?;
+            Some(pool)
+        } else {
+            None
+        };
+
+        let tracked = self
+            .runtime
+            .block_on(load_tracked(pool.as_ref(), &config, config_file, None))
+            .map_err(|e| GeyserPluginError::ConfigFileReadError { msg: e.to_string() })?;
+        self.tracked.set(tracked);
 
-        self.db_pool = Some(pool);
-        let pool = self.db_pool.as_ref().unwrap();
-
-        // Create listings table
-        let create_listings_result = self.runtime.block_on(async {
-            sqlx::query(
-                "CREATE TABLE IF NOT EXISTS listings (
-                    account TEXT PRIMARY KEY,
-                    name TEXT NOT NULL,
-                    seed BIGINT NOT NULL,
-                    mint TEXT NOT NULL,
-                    funding_goal BIGINT NOT NULL,
-                    pool_mint_supply NUMERIC NOT NULL,
-                    funding_raised BIGINT NOT NULL,
-                    available_tokens NUMERIC NOT NULL,
-                    base_price DOUBLE PRECISION NOT NULL,
-                    tokens_sold NUMERIC NOT NULL,
-                    bump SMALLINT NOT NULL,
-                    vault_bump SMALLINT NOT NULL,
-                    mint_bump SMALLINT NOT NULL,
-                    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-                )",
+        if let Some(pool) = &pool {
+            // so a token account that closed or changed owner while the plugin was down
+            // still empties the holding it left behind
+            let token_accounts = self
+                .runtime
+                .block_on(db::token_accounts(pool))
+                .map_err(|e| GeyserPluginError::Custom(Box::new(e)))?;
+            self.remember_token_accounts(token_accounts);
+
+            let postgres = PostgresSink::start(
+                &self.runtime,
+                pool.clone(),
+                &config.pipeline,
+                &config.database,
+                self.tracked.clone(),
+                is_reload,
             )
-            .execute(pool)
-            .await
+            .map_err(|e| GeyserPluginError::ConfigFileReadError {
+                msg: format!("Error opening spool: {}", e),
+            })?;
+            self.postgres = Some(Arc::new(postgres));
+        }
+        for sink in &config.sinks {
+            let sink: Arc<dyn AccountSink> = match sink {
+                SinkConfig::Postgres => self
+                    .postgres
+                    .clone()
+                    .expect("started whenever the postgres sink is listed"),
+                SinkConfig::Ndjson {
+                    path,
+                    max_bytes,
+                    max_files,
+                    queue_capacity,
+                } => Arc::new(
+                    NdjsonSink::open(path, *max_bytes, *max_files, *queue_capacity).map_err(
+                        |e| GeyserPluginError::ConfigFileReadError {
+                            msg: format!("Error opening sink file {}: {}", path, e),
+                        },
+                    )?,
+                ),
+                SinkConfig::Memory { capacity } => Arc::new(MemorySink::new(*capacity)),
+            };
+            self.sinks.push(sink);
+        }
+        self.backfill = config.rpc.url.as_ref().map(|url| {
+            let sinks = self.sinks.clone();
+            Backfill::start(self.runtime.handle(), url, &config.rpc, move |event| {
+                for sink in &sinks {
+                    sink.send(&event, false);
+                }
+            })
         });
-
-        if let Err(e) = create_listings_result {
-            println!("Error creating listings table: {:?}", e);
+        if let Some(metrics_config) = &config.metrics {
+            let server =
+                metrics::serve(&self.runtime, metrics_config.bind_address).map_err(|e| {
+                    GeyserPluginError::ConfigFileReadError {
+                        msg: format!(
+                            "Error serving metrics on {}: {}",
+                            metrics_config.bind_address, e
+                        ),
+                    }
+                })?;
+            self.metrics_server = Some(server);
         }
+        self.reloader = Reloader::start(
+            &self.runtime,
+            pool.clone(),
+            config_file,
+            &config.reload,
+            self.tracked.clone(),
+        );
+        self.db_pool = pool;
+        self.config = Some(config);
 
-        if let Some(users) = &config.tracked_users {
-            for user in users {
-                let create_user_table = format!(
-                    "CREATE TABLE IF NOT EXISTS user_{} (
-                        timestamp TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-                        sol_balance NUMERIC NOT NULL,
-                        token_holdings JSONB,
-                        nft_holdings JSONB
-                    )",
-                    user.replace(&['.' as char, '-' as char][..], "_")
-                );
-
-                let result = self
-                    .runtime
-                    .block_on(async { sqlx::query(&create_user_table).execute(pool).await });
+        Ok(())
+    }
 
-                if let Err(e) = result {
-                    println!("Error creating table for user {}: {:?}", user, e);
-                }
+    fn on_unload(&mut self) {
+        self.reloader.stop();
+        if let Some(backfill) = self.backfill.take() {
+            self.runtime.block_on(backfill.stop());
+        }
+        if let Some(server) = self.metrics_server.take() {
+            server.abort();
+        }
+        // the staged snapshot has to be in before the pipeline shuts down
+        self.finish_startup();
+        if let Some(merge) = self.startup_merge.get_mut().unwrap().take() {
+            if let Err(e) = self.runtime.block_on(merge) {
+                log::error!("Startup merge failed: {:?}", e);
             }
         }
 
-        if let Some(accounts) = config.programs.as_ref() {
-            accounts.iter().for_each(|account| {
-                let mut acc_bytes = [0u8; 32];
-                acc_bytes.copy_from_slice(&bs58::decode(account).into_vec().unwrap()[0..32]);
-                self.programs.push(acc_bytes);
-            });
+        for sink in self.sinks.drain(..) {
+            sink.close();
+        }
+        // the sinks held the only other reference
+        if let Some(postgres) = self
+            .postgres
+            .take()
+            .and_then(|postgres| Arc::try_unwrap(postgres).ok())
+        {
+            self.runtime.block_on(postgres.shutdown());
         }
-        self.config = Some(config);
 
-        Ok(())
+        for program in self.tracked.get().registry.programs() {
+            let unknown = program.unknown_discriminators();
+            if unknown > 0 {
+                log::warn!(
+                    program = program.program_id.as_str();
+                    "Ignored {} accounts with unknown discriminators",
+                    unknown
+                );
+            }
+        }
     }
 
-    fn on_unload(&mut self) {}
-
     fn update_account(
         &self,
         account: ReplicaAccountInfoVersions,
-        _slot: Slot,
-        _is_startup: bool,
+        slot: Slot,
+        is_startup: bool,
     ) -> PluginResult<()> {
         let account_info = match account {
             ReplicaAccountInfoVersions::V0_0_1(_) | ReplicaAccountInfoVersions::V0_0_2(_) => {
@@ -143,173 +264,682 @@ impl GeyserPlugin for Heimdall {
             ReplicaAccountInfoVersions::V0_0_3(account_info) => account_info,
         };
 
+        metrics().account_updates.inc();
+        set_max(&metrics().account_update_slot, slot);
+
         let account_pubkey = bs58::encode(account_info.pubkey).into_string();
+        let tracked = self.tracked.get();
+
+        if tracked.is_user(account_info.pubkey) {
+            self.emit(
+                SinkEvent::WalletBalanceChanged {
+                    wallet: account_pubkey.clone(),
+                    lamports: account_info.lamports,
+                    slot,
+                    write_version: account_info.write_version,
+                },
+                is_startup,
+            );
+        }
 
-        if let Some(tracked_users) = &self.config.as_ref().unwrap().tracked_users {
-            if tracked_users.contains(&account_pubkey) {
-                self.update_user_sol_balance(&account_pubkey, account_info.lamports)?;
+        if let Some(epoch) = native::clock_epoch(account_info.pubkey, account_info.data) {
+            if self.epoch.fetch_max(epoch, Ordering::Relaxed) < epoch {
+                self.emit(SinkEvent::EpochStarted { epoch, slot }, is_startup);
             }
+            return Ok(());
+        }
 
-            if let Ok(owner_pubkey) = Pubkey::try_from(account_info.owner) {
-                if owner_pubkey == SPL_TOKEN_PROGRAM_ID {
-                    if let Ok(token_account) = TokenAccount::unpack(&account_info.data) {
-                        let owner = bs58::encode(token_account.owner).into_string();
-                        if tracked_users.contains(&owner) {
-                            let mint = bs58::encode(token_account.mint).into_string();
-                            self.update_user_token_holding(&owner, &mint, token_account.amount)?;
-                        }
-                    }
-                }
+        // a closed account has no lamports and usually emptied data owned by the system
+        // program, so it can only be matched by address
+        if account_info.lamports == 0 {
+            self.close_account(
+                account_info.pubkey,
+                account_pubkey,
+                slot,
+                account_info.write_version,
+            );
+            return Ok(());
+        }
+
+        if let Some(token_account) =
+            token::parse_token_account(account_info.owner, account_info.data)
+        {
+            self.release_token_account(
+                account_info.pubkey,
+                &token_account.owner,
+                slot,
+                account_info.write_version,
+            );
+            if tracked.is_user(&token_account.owner) {
+                let user = bs58::encode(token_account.owner).into_string();
+                let mint = bs58::encode(token_account.mint).into_string();
+                self.remember(account_info.pubkey, || KnownAccount::TokenAccount {
+                    user: user.clone(),
+                    mint: mint.clone(),
+                    token_program: token_account.token_program,
+                });
+                self.track_mint(token_account.mint);
+                self.emit(
+                    SinkEvent::TokenHoldingChanged {
+                        wallet: user,
+                        token_account: account_pubkey.clone(),
+                        mint,
+                        balance: TokenBalance {
+                            amount: token_account.amount,
+                            token_program: token_account.token_program,
+                            extensions: token_account.extensions,
+                            authorities: Some(token_account.authorities),
+                            slot,
+                            write_version: account_info.write_version,
+                        },
+                    },
+                    is_startup,
+                );
             }
+        } else if self.is_tracked_mint(account_info.pubkey) {
+            if let Some(mint) = token::parse_mint(account_info.owner, account_info.data) {
+                self.emit(
+                    SinkEvent::MintUpdated {
+                        mint: account_pubkey.clone(),
+                        info: token::mint_info(mint, slot, account_info.write_version),
+                    },
+                    is_startup,
+                );
+            }
+        } else if metaplex::is_metadata_program(account_info.owner) {
+            self.update_metaplex_account(
+                account_info.pubkey,
+                account_info.data,
+                slot,
+                account_info.write_version,
+                is_startup,
+            );
+        } else if let Some(table) =
+            native::native_table(account_info.owner, account_info.data.len())
+        {
+            self.update_native_account(
+                account_info,
+                account_pubkey.clone(),
+                table,
+                slot,
+                is_startup,
+            );
         }
 
-        self.programs.iter().for_each(|program| {
-            if program == account_info.owner {
-                if account_info.data.len() > 8 {
-                    let mut account_data_slice = &account_info.data[8..];
-                    if let Ok(anchor_listing) = AnchorListing::deserialize(&mut account_data_slice)
-                    {
-                        self.update_listing(&account_pubkey, anchor_listing);
-                    }
+        // filters run before any decoding, an account that stops matching them is
+        // removed like a closed one
+        let program = tracked.registry.get(account_info.owner);
+        if let Some(program) = program {
+            metrics()
+                .accounts_seen
+                .with_label_values(&[&program.program_id])
+                .inc();
+        }
+        let filtered_out = program
+            .is_some_and(|program| !program.matches(account_info.lamports, account_info.data));
+        if filtered_out {
+            self.close_account(
+                account_info.pubkey,
+                account_pubkey,
+                slot,
+                account_info.write_version,
+            );
+            return Ok(());
+        }
+
+        if let Some((program, decoded)) = tracked
+            .registry
+            .decode(account_info.owner, account_info.data)
+        {
+            if decoded.is_ok() {
+                metrics()
+                    .accounts_matched
+                    .with_label_values(&[&program.program_id])
+                    .inc();
+            }
+            match decoded {
+                Ok(DecodedAccount::Listing(anchor_listing)) => {
+                    self.track_mint(anchor_listing.mint.to_bytes());
+                    self.remember(account_info.pubkey, || KnownAccount::Program {
+                        table: LISTINGS_TABLE.to_string(),
+                    });
+                    self.update_listing(
+                        &account_pubkey,
+                        anchor_listing,
+                        slot,
+                        account_info.write_version,
+                        is_startup,
+                    )
+                }
+                Ok(DecodedAccount::Idl(idl_account, data)) => {
+                    self.remember(account_info.pubkey, || KnownAccount::Program {
+                        table: idl_account.table.clone(),
+                    });
+                    self.emit(
+                        SinkEvent::IdlAccountUpdated {
+                            account: account_pubkey,
+                            program_id: program.program_id.clone(),
+                            idl_account: idl_account.clone(),
+                            data,
+                            slot,
+                            write_version: account_info.write_version,
+                        },
+                        is_startup,
+                    )
+                }
+                Err(e) => {
+                    metrics()
+                        .decode_failures
+                        .with_label_values(&[&program.program_id, "error"])
+                        .inc();
+                    limited!(
+                        warn,
+                        account = account_pubkey.as_str(),
+                        program = program.program_id.as_str(),
+                        slot;
+                        "Error decoding account: {}",
+                        e
+                    )
                 }
             }
-        });
+        }
 
         Ok(())
     }
-}
 
-impl Heimdall {
-    fn update_user_sol_balance(&self, user_pubkey: &str, lamports: u64) -> PluginResult<()> {
-        let user_table = format!(
-            "user_{}",
-            user_pubkey.replace(&['.' as char, '-' as char][..], "_")
-        );
-        let sol_balance = lamports as f64 / 1_000_000_000.0;
-
-        let query = format!(
-            "INSERT INTO {} (sol_balance, token_holdings, nft_holdings) 
-             VALUES ($1, 
-                    COALESCE((SELECT token_holdings FROM {} ORDER BY timestamp DESC LIMIT 1), '[]'::jsonb),
-                    COALESCE((SELECT nft_holdings FROM {} ORDER BY timestamp DESC LIMIT 1), '[]'::jsonb))",
-            user_table, user_table, user_table
+    fn update_slot_status(
+        &self,
+        slot: Slot,
+        parent: Option<u64>,
+        status: SlotStatus,
+    ) -> PluginResult<()> {
+        set_max(&metrics().slot.with_label_values(&[status.as_str()]), slot);
+        self.emit(
+            SinkEvent::SlotStatusChanged {
+                slot,
+                parent,
+                status,
+            },
+            false,
         );
+        for sink in &self.sinks {
+            sink.flush();
+        }
+
+        Ok(())
+    }
 
-        let _ = self.runtime.block_on(async {
-            sqlx::query(&query)
-                .bind(sol_balance)
-                .execute(self.db_pool.as_ref().unwrap())
-                .await
+    fn notify_transaction(
+        &self,
+        transaction: ReplicaTransactionInfoVersions,
+        slot: Slot,
+    ) -> PluginResult<()> {
+        let (signature, is_vote, transaction, meta, index) = match transaction {
+            ReplicaTransactionInfoVersions::V0_0_1(info) => (
+                info.signature,
+                info.is_vote,
+                info.transaction,
+                info.transaction_status_meta,
+                None,
+            ),
+            ReplicaTransactionInfoVersions::V0_0_2(info) => (
+                info.signature,
+                info.is_vote,
+                info.transaction,
+                info.transaction_status_meta,
+                Some(info.index as u64),
+            ),
+        };
+
+        if is_vote {
+            return Ok(());
+        }
+
+        let message = transaction.message();
+        let account_keys = message.account_keys();
+        let tracked = self.tracked.get();
+        let touches_tracked = account_keys.iter().any(|key| {
+            tracked.registry.get(key.as_ref()).is_some() || tracked.is_user(key.as_ref())
         });
+        if !touches_tracked {
+            return Ok(());
+        }
 
-        let notify_payload = serde_json::json!({
-            "account": user_pubkey,
-            "action": "user_update"
-        })
-        .to_string();
-
-        let notify_result = self.runtime.block_on(async {
-            sqlx::query("SELECT pg_notify('user_updates', $1)")
-                .bind(&notify_payload)
-                .execute(self.db_pool.as_ref().unwrap())
-                .await
+        let signers = account_keys
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| message.is_signer(*i))
+            .map(|(_, key)| key.to_string())
+            .collect();
+
+        let event = SinkEvent::TransactionProcessed(Transaction {
+            signature: signature.to_string(),
+            slot,
+            index,
+            fee: meta.fee,
+            err: meta.status.as_ref().err().map(|e| e.to_string()),
+            signers,
+            account_keys: account_keys.iter().map(|key| key.to_string()).collect(),
+            log_messages: meta.log_messages.clone(),
+            compute_units: meta.compute_units_consumed,
         });
+        self.emit(event, false);
 
-        if let Err(e) = notify_result {
-            println!("Failed to send user update notification: {:?}", e);
+        // a failed transaction's events never happened
+        if let (Ok(()), Some(log_messages)) = (&meta.status, &meta.log_messages) {
+            self.emit_events(
+                &tracked.registry,
+                &signature.to_string(),
+                slot,
+                log_messages,
+            );
         }
 
         Ok(())
     }
 
-    fn update_user_token_holding(
-        &self,
-        user_pubkey: &str,
-        mint: &str,
-        amount: u64,
-    ) -> PluginResult<()> {
-        let user_table = format!(
-            "user_{}",
-            user_pubkey.replace(&['.' as char, '-' as char][..], "_")
-        );
+    fn notify_block_metadata(&self, blockinfo: ReplicaBlockInfoVersions) -> PluginResult<()> {
+        let block = match blockinfo {
+            ReplicaBlockInfoVersions::V0_0_1(info) => Block {
+                slot: info.slot,
+                parent_slot: None,
+                blockhash: info.blockhash.to_string(),
+                parent_blockhash: None,
+                block_time: info.block_time,
+                block_height: info.block_height,
+                rewards: serde_json::json!(info.rewards),
+                executed_transaction_count: None,
+            },
+            ReplicaBlockInfoVersions::V0_0_2(info) => Block {
+                slot: info.slot,
+                parent_slot: Some(info.parent_slot),
+                blockhash: info.blockhash.to_string(),
+                parent_blockhash: Some(info.parent_blockhash.to_string()),
+                block_time: info.block_time,
+                block_height: info.block_height,
+                rewards: serde_json::json!(info.rewards),
+                executed_transaction_count: Some(info.executed_transaction_count),
+            },
+            ReplicaBlockInfoVersions::V0_0_3(info) => Block {
+                slot: info.slot,
+                parent_slot: Some(info.parent_slot),
+                blockhash: info.blockhash.to_string(),
+                parent_blockhash: Some(info.parent_blockhash.to_string()),
+                block_time: info.block_time,
+                block_height: info.block_height,
+                rewards: serde_json::json!(info.rewards),
+                executed_transaction_count: Some(info.executed_transaction_count),
+            },
+        };
 
-        let query = format!(
-            "SELECT token_holdings, nft_holdings FROM {} ORDER BY timestamp DESC LIMIT 1",
-            user_table
-        );
+        self.emit(SinkEvent::BlockProduced(block), false);
 
-        let result = self.runtime.block_on(async {
-            sqlx::query(&query)
-                .fetch_optional(self.db_pool.as_ref().unwrap())
-                .await
-        });
+        Ok(())
+    }
 
-        let (mut token_holdings, nft_holdings) = match result {
-            Ok(Some(row)) => {
-                let tokens: serde_json::Value = row
-                    .try_get(0)
-                    .map_err(|e| GeyserPluginError::Custom(Box::new(e)))?;
-                let nfts: serde_json::Value = row
-                    .try_get(1)
-                    .map_err(|e| GeyserPluginError::Custom(Box::new(e)))?;
-                (tokens, nfts)
-            }
-            _ => (serde_json::json!([]), serde_json::json!([])),
+    fn notify_end_of_startup(&self) -> PluginResult<()> {
+        self.finish_startup();
+        Ok(())
+    }
+
+    // only asks the validator for transactions when something could match, now or after
+    // a reload
+    fn transaction_notifications_enabled(&self) -> bool {
+        !self.tracked.get().is_empty() || self.reloader.is_active()
+    }
+}
+
+impl Heimdall {
+    fn emit(&self, event: SinkEvent, is_startup: bool) {
+        for sink in &self.sinks {
+            sink.send(&event, is_startup);
+        }
+    }
+
+    // loads the staged snapshot in the background, merging and indexing a full snapshot
+    // would hold up the validator, and announces it with one event instead of one per
+    // account once it's in
+    fn finish_startup(&self) {
+        let (Some(postgres), Some(pool)) = (&self.postgres, &self.db_pool) else {
+            // nothing is staged without the postgres sink
+            metrics().startup_complete.set(1);
+            return;
         };
+        let mut merge = self.startup_merge.lock().unwrap();
+        if merge.is_some() {
+            return;
+        }
 
-        if let serde_json::Value::Array(ref mut tokens) = token_holdings {
-            tokens.retain(|t| t["mint"] != mint);
+        let (postgres, pool) = (postgres.clone(), pool.clone());
+        *merge = Some(self.runtime.spawn(async move {
+            let Some(accounts) = postgres.finish_startup().await else {
+                return;
+            };
+            log::info!(accounts; "Startup complete");
 
-            if amount > 0 {
-                tokens.push(serde_json::json!({
-                    "mint": mint,
-                    "amount": amount,
-                }));
+            let payload = serde_json::json!({
+                "event": "startup_complete",
+                "accounts": accounts,
+            });
+            if let Err(e) = db::notify(&pool, &[("heimdall_events", payload.to_string())]).await {
+                metrics().notify_failures.inc();
+                log::error!("Failed to send startup complete notification: {:?}", e);
             }
+        }));
+    }
+
+    fn remember(&self, pubkey: &[u8], known: impl FnOnce() -> KnownAccount) {
+        let Ok(pubkey) = <[u8; 32]>::try_from(pubkey) else {
+            return;
+        };
+        if !self.known_accounts.read().unwrap().contains_key(&pubkey) {
+            self.known_accounts.write().unwrap().insert(pubkey, known());
+        }
+    }
+
+    // closing a token account removes the mint from the user's holdings
+    fn close_account(&self, pubkey: &[u8], account_pubkey: String, slot: Slot, write_version: u64) {
+        let Ok(pubkey) = <[u8; 32]>::try_from(pubkey) else {
+            return;
+        };
+        // almost every zero-lamport account on chain is unrelated, check before locking for write
+        if !self.known_accounts.read().unwrap().contains_key(&pubkey) {
+            return;
         }
 
-        let update_query = format!(
-            "INSERT INTO {} (sol_balance, token_holdings, nft_holdings) 
-             VALUES (COALESCE((SELECT sol_balance FROM {} ORDER BY timestamp DESC LIMIT 1), 0),
-                    $1, $2)",
-            user_table, user_table
+        let known = self.known_accounts.write().unwrap().remove(&pubkey);
+        match known {
+            // the snapshot holds no closed accounts
+            Some(KnownAccount::Program { table }) => self.emit(
+                SinkEvent::AccountClosed {
+                    account: account_pubkey,
+                    table,
+                    slot,
+                    write_version,
+                },
+                false,
+            ),
+            Some(KnownAccount::TokenAccount {
+                user,
+                mint,
+                token_program,
+            }) => self.empty_holding(
+                user,
+                account_pubkey,
+                mint,
+                token_program,
+                slot,
+                write_version,
+            ),
+            None => {}
+        }
+    }
+
+    // a token account handed to a new owner leaves the previous owner's holdings as if it
+    // was closed, the new owner picks it up like any other account
+    fn release_token_account(&self, pubkey: &[u8], owner: &[u8], slot: Slot, write_version: u64) {
+        let Ok(pubkey) = <[u8; 32]>::try_from(pubkey) else {
+            return;
+        };
+        // only accounts of tracked users are known, nearly every lookup misses
+        let moved = matches!(
+            self.known_accounts.read().unwrap().get(&pubkey),
+            Some(KnownAccount::TokenAccount { user, .. })
+                if *user != bs58::encode(owner).into_string()
         );
+        if !moved {
+            return;
+        }
 
-        let result = self.runtime.block_on(async {
-            sqlx::query(&update_query)
-                .bind(token_holdings)
-                .bind(nft_holdings)
-                .execute(self.db_pool.as_ref().unwrap())
-                .await
-        });
+        let known = self.known_accounts.write().unwrap().remove(&pubkey);
+        if let Some(KnownAccount::TokenAccount {
+            user,
+            mint,
+            token_program,
+        }) = known
+        {
+            self.empty_holding(
+                user,
+                bs58::encode(pubkey).into_string(),
+                mint,
+                token_program,
+                slot,
+                write_version,
+            );
+        }
+    }
 
-        if let Err(e) = result {
-            println!("Error updating token holdings: {:?}", e);
-        } else {
-            let notify_payload = serde_json::json!({
-                "account": user_pubkey,
-                "action": "user_update"
-            })
-            .to_string();
+    // rows are (token account, wallet, mint, token program)
+    fn remember_token_accounts(&self, token_accounts: Vec<(String, String, String, String)>) {
+        let mut known_accounts = self.known_accounts.write().unwrap();
+        for (token_account, user, mint, token_program) in token_accounts {
+            let pubkey = bs58::decode(&token_account)
+                .into_vec()
+                .ok()
+                .and_then(|pubkey| <[u8; 32]>::try_from(pubkey).ok());
+            let token_program = [token::SPL_TOKEN, token::SPL_TOKEN_2022]
+                .into_iter()
+                .find(|known| *known == token_program);
+            if let (Some(pubkey), Some(token_program)) = (pubkey, token_program) {
+                known_accounts.insert(
+                    pubkey,
+                    KnownAccount::TokenAccount {
+                        user,
+                        mint,
+                        token_program,
+                    },
+                );
+            }
+        }
+    }
 
-            let notify_result = self.runtime.block_on(async {
-                sqlx::query("SELECT pg_notify('user_updates', $1)")
-                    .bind(&notify_payload)
-                    .execute(self.db_pool.as_ref().unwrap())
-                    .await
-            });
+    fn empty_holding(
+        &self,
+        user: String,
+        token_account: String,
+        mint: String,
+        token_program: &'static str,
+        slot: Slot,
+        write_version: u64,
+    ) {
+        self.emit(
+            SinkEvent::TokenHoldingChanged {
+                wallet: user,
+                token_account,
+                mint,
+                balance: TokenBalance {
+                    amount: 0,
+                    token_program,
+                    extensions: serde_json::json!({}),
+                    authorities: None,
+                    slot,
+                    write_version,
+                },
+            },
+            false,
+        )
+    }
 
-            if let Err(e) = notify_result {
-                println!("Failed to send user update notification: {:?}", e);
+    fn track_mint(&self, mint: [u8; 32]) {
+        if self.tracked_mints.read().unwrap().contains(&mint) {
+            return;
+        }
+        if self.tracked_mints.write().unwrap().insert(mint) {
+            let address = metaplex::master_edition_address(&mint);
+            self.master_editions.write().unwrap().insert(address, mint);
+            if let Some(backfill) = &self.backfill {
+                backfill.fetch_mint(mint);
             }
         }
+    }
 
-        Ok(())
+    // metadata and master editions are only stored for tracked mints. Metadata of any
+    // other mint isn't decoded at all
+    fn update_metaplex_account(
+        &self,
+        pubkey: &[u8],
+        data: &[u8],
+        slot: Slot,
+        write_version: u64,
+        is_startup: bool,
+    ) {
+        if let Some(mint) = metaplex::metadata_mint(data) {
+            if !self.is_tracked_mint(mint) {
+                return;
+            }
+            if let Some(metadata) = metaplex::parse_metadata(data) {
+                self.emit(
+                    SinkEvent::NftMetadataUpdated {
+                        mint: metadata.mint.to_string(),
+                        metadata: Box::new(metaplex::nft_metadata(&metadata, slot, write_version)),
+                    },
+                    is_startup,
+                );
+            }
+            return;
+        }
+
+        let Ok(pubkey) = <[u8; 32]>::try_from(pubkey) else {
+            return;
+        };
+        let Some(mint) = self.master_editions.read().unwrap().get(&pubkey).copied() else {
+            return;
+        };
+        if let Some(edition) = metaplex::parse_master_edition(data) {
+            self.emit(
+                SinkEvent::MasterEditionUpdated {
+                    mint: bs58::encode(mint).into_string(),
+                    edition: MasterEdition {
+                        supply: edition.supply,
+                        max_supply: edition.max_supply,
+                        slot,
+                        write_version,
+                    },
+                },
+                is_startup,
+            );
+        }
     }
 
-    fn update_listing(&self, account_pubkey: &str, anchor_listing: AnchorListing) {
+    // decodes the `Program data:` lines of tracked programs through their IDL events
+    fn emit_events(
+        &self,
+        registry: &AccountRegistry,
+        signature: &str,
+        slot: Slot,
+        log_messages: &[String],
+    ) {
+        for line in logs::program_data(log_messages) {
+            let Some(program) = bs58::decode(line.program_id)
+                .into_vec()
+                .ok()
+                .and_then(|program_id| registry.get(&program_id))
+            else {
+                continue;
+            };
+            // anything that isn't one of the IDL's events, e.g. plain `sol_log_data`
+            let Some(decoded) = program.decode_event(&line.data) else {
+                continue;
+            };
+
+            match decoded {
+                Ok((name, data)) => self.emit(
+                    SinkEvent::EventEmitted(ProgramEvent {
+                        signature: signature.to_string(),
+                        slot,
+                        event_index: line.index,
+                        instruction_index: line.instruction_index,
+                        program_id: program.program_id.clone(),
+                        name: name.to_string(),
+                        data,
+                    }),
+                    false,
+                ),
+                Err(e) => {
+                    metrics()
+                        .decode_failures
+                        .with_label_values(&[&program.program_id, "event"])
+                        .inc();
+                    limited!(
+                        warn,
+                        signature,
+                        program = program.program_id.as_str(),
+                        slot;
+                        "Error decoding event: {}",
+                        e
+                    )
+                }
+            }
+        }
+    }
+
+    // stake, vote and nonce accounts are stored while a tracked user is among their
+    // authorities, losing the last one removes them like a close
+    fn update_native_account(
+        &self,
+        account_info: &ReplicaAccountInfoV3,
+        account_pubkey: String,
+        table: &'static str,
+        slot: Slot,
+        is_startup: bool,
+    ) {
+        let (pubkey, data) = (account_info.pubkey, account_info.data);
+        let tracked = self.tracked.get();
+        let mut wallets = Vec::new();
+        for authority in native::authorities(table, data) {
+            if !tracked.is_user(&authority) {
+                continue;
+            }
+            // the staker and withdrawer are often the same
+            let wallet = bs58::encode(authority).into_string();
+            if !wallets.contains(&wallet) {
+                wallets.push(wallet);
+            }
+        }
+
+        let state = (!wallets.is_empty())
+            .then(|| native::parse_native(table, data))
+            .flatten();
+        let Some(state) = state else {
+            self.close_account(pubkey, account_pubkey, slot, account_info.write_version);
+            return;
+        };
+
+        self.remember(pubkey, || KnownAccount::Program {
+            table: table.to_string(),
+        });
+        self.emit(
+            SinkEvent::NativeAccountUpdated {
+                account: account_pubkey,
+                native: Box::new(NativeAccount {
+                    wallets,
+                    lamports: account_info.lamports,
+                    state,
+                    slot,
+                    write_version: account_info.write_version,
+                }),
+            },
+            is_startup,
+        );
+    }
+
+    fn is_tracked_mint(&self, pubkey: &[u8]) -> bool {
+        <[u8; 32]>::try_from(pubkey)
+            .map(|pubkey| self.tracked_mints.read().unwrap().contains(&pubkey))
+            .unwrap_or(false)
+    }
+
+    fn update_listing(
+        &self,
+        account_pubkey: &str,
+        anchor_listing: AnchorListing,
+        slot: Slot,
+        write_version: u64,
+        is_startup: bool,
+    ) {
         let listing = Listing {
-            name: anchor_listing.name,
+            // Postgres text can't hold NUL bytes
+            name: anchor_listing.name.replace('\0', ""),
             seed: anchor_listing.seed,
             mint: bs58::encode(anchor_listing.mint).into_string(),
             funding_goal: anchor_listing.funding_goal,
@@ -323,65 +953,372 @@ impl Heimdall {
             mint_bump: anchor_listing.mint_bump,
         };
 
-        let listing_query = "INSERT INTO listings (
-            account, name, seed, mint, funding_goal, pool_mint_supply,
-            funding_raised, available_tokens, base_price, tokens_sold,
-            bump, vault_bump, mint_bump
-        ) VALUES ($1, $2, $3, $4, $5, CAST($6 AS NUMERIC), $7, CAST($8 AS NUMERIC), $9, CAST($10 AS NUMERIC), $11, $12, $13)
-        ON CONFLICT (account) DO UPDATE SET
-            name = EXCLUDED.name,
-            seed = EXCLUDED.seed,
-            mint = EXCLUDED.mint,
-            funding_goal = EXCLUDED.funding_goal,
-            pool_mint_supply = EXCLUDED.pool_mint_supply,
-            funding_raised = EXCLUDED.funding_raised,
-            available_tokens = EXCLUDED.available_tokens,
-            base_price = EXCLUDED.base_price,
-            tokens_sold = EXCLUDED.tokens_sold,
-            bump = EXCLUDED.bump,
-            vault_bump = EXCLUDED.vault_bump,
-            mint_bump = EXCLUDED.mint_bump,
-            updated_at = CURRENT_TIMESTAMP";
-
-        let result = self.runtime.block_on(async {
-            sqlx::query(listing_query)
-                .bind(account_pubkey)
-                .bind(&listing.name)
-                .bind(listing.seed as i64)
-                .bind(&listing.mint)
-                .bind(listing.funding_goal as i64)
-                .bind(listing.pool_mint_supply.to_string())
-                .bind(listing.funding_raised as i64)
-                .bind(listing.available_tokens.to_string())
-                .bind(listing.base_price)
-                .bind(listing.tokens_sold.to_string())
-                .bind(listing.bump as i16)
-                .bind(listing.vault_bump as i16)
-                .bind(listing.mint_bump as i16)
-                .execute(self.db_pool.as_ref().unwrap())
-                .await
-        });
+        self.emit(
+            SinkEvent::ListingUpdated {
+                account: account_pubkey.to_string(),
+                listing,
+                slot,
+                write_version,
+            },
+            is_startup,
+        );
+    }
+}
 
-        match result {
-            Ok(_) => {
-                let notify_payload = serde_json::json!({
-                    "account": account_pubkey,
-                    "action": "account_update"
-                })
-                .to_string();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+    use solana_geyser_plugin_interface::geyser_plugin_interface::{
+        ReplicaBlockInfo, ReplicaBlockInfoV3, ReplicaTransactionInfo, ReplicaTransactionInfoV2,
+    };
+    use spl_token::solana_program::{program_pack::Pack, pubkey::Pubkey};
 
-                let notify_result = self.runtime.block_on(async {
-                    sqlx::query("SELECT pg_notify('account_updates', $1)")
-                        .bind(&notify_payload)
-                        .execute(self.db_pool.as_ref().unwrap())
-                        .await
-                });
+    use crate::{config::ProgramConfig, idl, reload::Tracked};
 
-                if let Err(e) = notify_result {
-                    println!("Failed to send account update notification: {:?}", e);
-                }
-            }
-            Err(e) => println!("Error inserting/updating listing: {:?}", e),
+    fn account<'a>(
+        pubkey: &'a [u8],
+        owner: &'a [u8],
+        lamports: u64,
+        data: &'a [u8],
+    ) -> ReplicaAccountInfoV3<'a> {
+        ReplicaAccountInfoV3 {
+            pubkey,
+            lamports,
+            owner,
+            executable: false,
+            rent_epoch: 0,
+            data,
+            write_version: 7,
+            txn: None,
+        }
+    }
+
+    #[test]
+    fn sends_every_update_to_the_sinks() {
+        let user = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let token_account = Pubkey::new_unique();
+        let memory = Arc::new(MemorySink::new(100));
+        let heimdall = Heimdall {
+            sinks: vec![memory.clone()],
+            ..Heimdall::default()
+        };
+        heimdall.tracked.set(Tracked {
+            users: HashSet::from([user.to_bytes()]),
+            ..Tracked::default()
+        });
+
+        let mut data = vec![0; spl_token::state::Account::LEN];
+        spl_token::state::Account {
+            mint,
+            owner: user,
+            amount: 5,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
         }
+        .pack_into_slice(&mut data);
+        let system = [0; 32];
+        let wallet = account(user.as_ref(), &system, 10, &[]);
+        heimdall
+            .update_account(ReplicaAccountInfoVersions::V0_0_3(&wallet), 40, true)
+            .unwrap();
+        let holding = account(token_account.as_ref(), spl_token::ID.as_ref(), 10, &data);
+        heimdall
+            .update_account(ReplicaAccountInfoVersions::V0_0_3(&holding), 41, false)
+            .unwrap();
+        heimdall
+            .update_slot_status(41, Some(40), SlotStatus::Confirmed)
+            .unwrap();
+
+        let events = memory.events();
+        assert_eq!(events.len(), 3, "{:?}", events);
+        assert!(matches!(
+            &events[0],
+            SinkEvent::WalletBalanceChanged { wallet, lamports: 10, slot: 40, .. }
+                if *wallet == user.to_string()
+        ));
+        let SinkEvent::TokenHoldingChanged {
+            wallet,
+            token_account: address,
+            mint: holding_mint,
+            balance,
+        } = &events[1]
+        else {
+            panic!("expected a token holding, got {:?}", events[1]);
+        };
+        assert_eq!(*wallet, user.to_string());
+        assert_eq!(*address, token_account.to_string());
+        assert_eq!(*holding_mint, mint.to_string());
+        assert_eq!((balance.amount, balance.slot), (5, 41));
+        assert!(matches!(
+            events[2],
+            SinkEvent::SlotStatusChanged {
+                slot: 41,
+                parent: Some(40),
+                status: SlotStatus::Confirmed
+            }
+        ));
+
+        // closing the token account empties the holding
+        memory.clear();
+        let closed = account(token_account.as_ref(), &system, 0, &[]);
+        heimdall
+            .update_account(ReplicaAccountInfoVersions::V0_0_3(&closed), 42, false)
+            .unwrap();
+        assert!(matches!(
+            &memory.events()[..],
+            [SinkEvent::TokenHoldingChanged { balance, .. }]
+                if balance.amount == 0 && balance.authorities.is_none()
+        ));
+    }
+
+    #[test]
+    fn sends_transactions_that_touch_tracked_accounts() {
+        use solana_sdk::{
+            pubkey::Pubkey,
+            signature::{Keypair, Signature, Signer},
+            system_instruction,
+            transaction::{SanitizedTransaction, Transaction as SdkTransaction, TransactionError},
+        };
+        use solana_transaction_status::TransactionStatusMeta;
+
+        let user = Keypair::new();
+        let memory = Arc::new(MemorySink::new(100));
+        let heimdall = Heimdall {
+            sinks: vec![memory.clone()],
+            ..Heimdall::default()
+        };
+        heimdall.tracked.set(Tracked {
+            users: HashSet::from([user.pubkey().to_bytes()]),
+            ..Tracked::default()
+        });
+
+        let transfer = |payer: &Pubkey| {
+            SanitizedTransaction::from_transaction_for_tests(SdkTransaction::new_with_payer(
+                &[system_instruction::transfer(
+                    payer,
+                    &Pubkey::new_unique(),
+                    1,
+                )],
+                Some(payer),
+            ))
+        };
+        let tracked = transfer(&user.pubkey());
+        let untracked = transfer(&Pubkey::new_unique());
+        let signature = Signature::new_unique();
+        let meta = TransactionStatusMeta {
+            fee: 5_000,
+            log_messages: Some(vec![
+                "Program 11111111111111111111111111111111 invoke [1]".into()
+            ]),
+            compute_units_consumed: Some(150),
+            ..TransactionStatusMeta::default()
+        };
+        let notify = |is_vote, transaction, meta, index| {
+            let info = ReplicaTransactionInfoV2 {
+                signature: &signature,
+                is_vote,
+                transaction,
+                transaction_status_meta: meta,
+                index,
+            };
+            heimdall
+                .notify_transaction(ReplicaTransactionInfoVersions::V0_0_2(&info), 60)
+                .unwrap();
+        };
+
+        notify(false, &tracked, &meta, 3);
+        // votes and transactions that don't touch anything tracked are skipped
+        notify(true, &tracked, &meta, 4);
+        notify(false, &untracked, &meta, 5);
+        let events = memory.events();
+        let [SinkEvent::TransactionProcessed(transaction)] = &events[..] else {
+            panic!("expected one transaction, got {:?}", events);
+        };
+        assert_eq!(transaction.signature, signature.to_string());
+        assert_eq!((transaction.slot, transaction.index), (60, Some(3)));
+        assert_eq!(
+            (transaction.fee, transaction.compute_units),
+            (5_000, Some(150))
+        );
+        assert_eq!(transaction.err, None);
+        assert_eq!(transaction.signers, [user.pubkey().to_string()]);
+        assert_eq!(transaction.account_keys.len(), 3);
+        assert_eq!(transaction.log_messages, meta.log_messages);
+
+        // a failed transaction without any recorded logs, from a validator without indexes
+        memory.clear();
+        let meta = TransactionStatusMeta {
+            status: Err(TransactionError::AccountNotFound),
+            ..TransactionStatusMeta::default()
+        };
+        let info = ReplicaTransactionInfo {
+            signature: &signature,
+            is_vote: false,
+            transaction: &tracked,
+            transaction_status_meta: &meta,
+        };
+        heimdall
+            .notify_transaction(ReplicaTransactionInfoVersions::V0_0_1(&info), 61)
+            .unwrap();
+        let events = memory.events();
+        let [SinkEvent::TransactionProcessed(transaction)] = &events[..] else {
+            panic!("expected one transaction, got {:?}", events);
+        };
+        assert_eq!((transaction.slot, transaction.index), (61, None));
+        assert_eq!(
+            transaction.err.as_deref(),
+            Some(TransactionError::AccountNotFound.to_string().as_str())
+        );
+        assert_eq!(
+            (transaction.log_messages.as_ref(), transaction.compute_units),
+            (None, None)
+        );
+    }
+
+    #[test]
+    fn sends_block_metadata() {
+        use solana_transaction_status::{Reward, RewardType};
+
+        let memory = Arc::new(MemorySink::new(100));
+        let heimdall = Heimdall {
+            sinks: vec![memory.clone()],
+            ..Heimdall::default()
+        };
+        let rewards = [Reward {
+            pubkey: Pubkey::new_unique().to_string(),
+            lamports: 5_000,
+            post_balance: 10_000,
+            reward_type: Some(RewardType::Fee),
+            commission: None,
+        }];
+
+        let info = ReplicaBlockInfoV3 {
+            parent_slot: 69,
+            parent_blockhash: "parent",
+            slot: 70,
+            blockhash: "block",
+            rewards: &rewards,
+            block_time: Some(1_700_000_000),
+            block_height: Some(65),
+            executed_transaction_count: 12,
+            entry_count: 4,
+        };
+        heimdall
+            .notify_block_metadata(ReplicaBlockInfoVersions::V0_0_3(&info))
+            .unwrap();
+        // the first version has no parent or transaction count
+        let info = ReplicaBlockInfo {
+            slot: 71,
+            blockhash: "next",
+            rewards: &[],
+            block_time: None,
+            block_height: None,
+        };
+        heimdall
+            .notify_block_metadata(ReplicaBlockInfoVersions::V0_0_1(&info))
+            .unwrap();
+
+        let events = memory.events();
+        let [SinkEvent::BlockProduced(block), SinkEvent::BlockProduced(next)] = &events[..] else {
+            panic!("expected two blocks, got {:?}", events);
+        };
+        assert_eq!((block.slot, block.parent_slot), (70, Some(69)));
+        assert_eq!(
+            (block.blockhash.as_str(), block.parent_blockhash.as_deref()),
+            ("block", Some("parent"))
+        );
+        assert_eq!(
+            (block.block_time, block.block_height),
+            (Some(1_700_000_000), Some(65))
+        );
+        assert_eq!(block.executed_transaction_count, Some(12));
+        assert_eq!(block.rewards[0]["lamports"], 5_000);
+        assert_eq!(
+            (next.slot, next.parent_slot, next.parent_blockhash.as_ref()),
+            (71, None, None)
+        );
+        assert_eq!(
+            (next.executed_transaction_count, &next.rewards),
+            (None, &serde_json::json!([]))
+        );
+    }
+
+    #[test]
+    fn emits_idl_events_of_tracked_programs() {
+        let program_id = Pubkey::new_unique().to_string();
+        let outer = Pubkey::new_unique().to_string();
+        let idl = serde_json::json!({
+            "events": [{"name": "Swapped", "fields": [{"name": "amount", "type": "u64"}]}]
+        });
+        let path =
+            std::env::temp_dir().join(format!("heimdall-events-{}.json", std::process::id()));
+        std::fs::write(&path, idl.to_string()).unwrap();
+        let registry = AccountRegistry::from_config(&[ProgramConfig {
+            program_id: program_id.clone(),
+            idl_path: Some(path.to_string_lossy().into_owned()),
+            table_prefix: None,
+            filters: Vec::new(),
+        }])
+        .unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let mut swapped = idl::event_discriminator("Swapped").to_vec();
+        swapped.extend_from_slice(&42u64.to_le_bytes());
+        let swapped = BASE64.encode(swapped);
+        let log_messages: Vec<String> = [
+            "Program ComputeBudget111111111111111111111111111111 invoke [1]".to_string(),
+            "Program ComputeBudget111111111111111111111111111111 success".to_string(),
+            format!("Program {} invoke [1]", outer),
+            format!("Program data: {}", swapped),
+            format!("Program {} invoke [2]", program_id),
+            format!("Program data: {}", swapped),
+            // plain `sol_log_data` output of the tracked program
+            format!("Program data: {}", BASE64.encode([1u8; 16])),
+            format!("Program {} success", program_id),
+            format!("Program {} success", outer),
+            "Log truncated".to_string(),
+            format!("Program {} invoke [1]", program_id),
+            format!("Program data: {}", swapped),
+        ]
+        .into();
+
+        let memory = Arc::new(MemorySink::new(100));
+        let heimdall = Heimdall {
+            sinks: vec![memory.clone()],
+            ..Heimdall::default()
+        };
+        heimdall.emit_events(&registry, "signature", 50, &log_messages);
+
+        let events = memory.events();
+        let [SinkEvent::EventEmitted(event)] = &events[..] else {
+            panic!("expected one event, got {:?}", events);
+        };
+        assert_eq!(event.program_id, program_id);
+        assert_eq!((event.name.as_str(), event.slot), ("Swapped", 50));
+        assert_eq!((event.instruction_index, event.event_index), (1, 1));
+        assert_eq!(event.data, serde_json::json!({"amount": 42}));
+    }
+
+    #[test]
+    fn loads_without_a_database() {
+        let path =
+            std::env::temp_dir().join(format!("heimdall-memory-{}.json", std::process::id()));
+        let config = serde_json::json!({
+            "tracked_users": [Pubkey::new_unique().to_string()],
+            "sinks": [{"type": "memory"}],
+        });
+        std::fs::write(&path, config.to_string()).unwrap();
+
+        let mut heimdall = Heimdall::default();
+        heimdall.on_load(path.to_str().unwrap(), false).unwrap();
+        assert!(heimdall.db_pool.is_none() && heimdall.postgres.is_none());
+        assert_eq!(heimdall.sinks.len(), 1);
+        assert_eq!(heimdall.tracked.get().users.len(), 1);
+        // fetching through RPC is opt-in
+        assert!(heimdall.backfill.is_none());
+        heimdall.on_unload();
+        std::fs::remove_file(path).unwrap();
     }
 }
@@ -0,0 +1,928 @@
+use anchor_lang::solana_program::hash::hash;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::Deserialize;
+use serde_json::{Map, Number, Value};
+use std::{collections::HashMap, fmt, fs};
+
+use crate::db;
+
+// Anchor IDL JSON, accepting both the legacy (< 0.30) and the 0.30+ layout
+#[derive(Debug, Deserialize)]
+pub struct Idl {
+    #[serde(default)]
+    pub accounts: Vec<IdlAccountDef>,
+    #[serde(default)]
+    pub events: Vec<IdlEventDef>,
+    #[serde(default)]
+    pub types: Vec<IdlTypeDef>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IdlAccountDef {
+    pub name: String,
+    // 0.30+ only, legacy IDLs derive it from the account name
+    #[serde(default)]
+    pub discriminator: Option<Vec<u8>>,
+    // legacy only, 0.30+ IDLs move the layout into `types`
+    #[serde(rename = "type", default)]
+    pub ty: Option<IdlTypeDefTy>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IdlEventDef {
+    pub name: String,
+    // 0.30+ only, legacy IDLs derive it from the event name
+    #[serde(default)]
+    pub discriminator: Option<Vec<u8>>,
+    // legacy only, 0.30+ IDLs move the layout into `types`
+    #[serde(default)]
+    pub fields: Option<Vec<IdlField>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IdlTypeDef {
+    pub name: String,
+    #[serde(default)]
+    pub serialization: Option<String>,
+    #[serde(rename = "type")]
+    pub ty: IdlTypeDefTy,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum IdlTypeDefTy {
+    Struct {
+        #[serde(default)]
+        fields: Option<IdlFields>,
+    },
+    Enum {
+        variants: Vec<IdlEnumVariant>,
+    },
+    Type {
+        alias: IdlType,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum IdlFields {
+    Named(Vec<IdlField>),
+    Tuple(Vec<IdlType>),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct IdlField {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: IdlType,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct IdlEnumVariant {
+    pub name: String,
+    #[serde(default)]
+    pub fields: Option<IdlFields>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum IdlType {
+    Primitive(String),
+    Complex(IdlComplexType),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdlComplexType {
+    Option(Box<IdlType>),
+    #[serde(rename = "coption")]
+    COption(Box<IdlType>),
+    Vec(Box<IdlType>),
+    Array(Box<IdlType>, IdlArrayLen),
+    Defined(IdlDefined),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum IdlArrayLen {
+    Value(usize),
+    Generic { generic: String },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum IdlDefined {
+    Name(String),
+    Object { name: String },
+}
+
+impl IdlDefined {
+    fn name(&self) -> &str {
+        match self {
+            IdlDefined::Name(name) | IdlDefined::Object { name } => name,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct IdlError(pub String);
+
+impl fmt::Display for IdlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for IdlError {}
+
+// columns every generated account table carries before the IDL fields, and the
+// commitment queries derive from `slot`
+const RESERVED_COLUMNS: [&str; 7] = [
+    "account",
+    "program_id",
+    "data",
+    "slot",
+    "write_version",
+    "updated_at",
+    "slot_commitment",
+];
+
+#[derive(Debug)]
+pub struct IdlColumn {
+    pub field: String,
+    pub name: String,
+    pub sql_type: &'static str,
+}
+
+// one decodable account type of a program, with its target table
+#[derive(Debug)]
+pub struct IdlAccount {
+    pub name: String,
+    pub discriminator: [u8; 8],
+    pub table: String,
+    pub columns: Vec<IdlColumn>,
+    pub upsert_sql: String,
+    fields: IdlFields,
+}
+
+// one event type a program `emit!`s into its logs
+#[derive(Debug)]
+pub struct IdlEvent {
+    pub name: String,
+    fields: IdlFields,
+}
+
+// account types are handed to the registry, events and the type definitions stay here for
+// decoding
+#[derive(Debug)]
+pub struct ProgramIdl {
+    pub accounts: Vec<IdlAccount>,
+    events: HashMap<[u8; 8], IdlEvent>,
+    types: HashMap<String, IdlTypeDef>,
+}
+
+impl ProgramIdl {
+    pub fn load(path: &str, table_prefix: &str) -> Result<Self, IdlError> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| IdlError(format!("failed to read IDL {}: {}", path, e)))?;
+        let idl = serde_json::from_str::<Idl>(&contents)
+            .map_err(|e| IdlError(format!("failed to parse IDL {}: {}", path, e)))?;
+        Self::from_idl(idl, table_prefix)
+    }
+
+    pub fn from_idl(idl: Idl, table_prefix: &str) -> Result<Self, IdlError> {
+        let types: HashMap<String, IdlTypeDef> = idl
+            .types
+            .into_iter()
+            .map(|ty| (ty.name.clone(), ty))
+            .collect();
+
+        let mut program = ProgramIdl {
+            accounts: Vec::new(),
+            events: HashMap::new(),
+            types,
+        };
+
+        for account in idl.accounts {
+            let layout = match account.ty {
+                Some(ty) => ty,
+                None => match program.defined_layout("account", &account.name)? {
+                    Some(ty) => ty,
+                    None => continue,
+                },
+            };
+
+            let fields = match layout {
+                IdlTypeDefTy::Struct { fields } => fields.unwrap_or(IdlFields::Named(Vec::new())),
+                _ => {
+                    return Err(IdlError(format!(
+                        "account {} is not a struct",
+                        account.name
+                    )))
+                }
+            };
+            program.validate_fields(&fields, &mut Vec::new())?;
+
+            let discriminator = match account.discriminator {
+                Some(bytes) => <[u8; 8]>::try_from(bytes.as_slice()).map_err(|_| {
+                    IdlError(format!(
+                        "account {} has an invalid discriminator",
+                        account.name
+                    ))
+                })?,
+                None => account_discriminator(&account.name),
+            };
+
+            let table = format!(
+                "{}{}",
+                to_snake_case(table_prefix),
+                to_snake_case(&account.name)
+            );
+            let columns = program.columns(&account.name, &fields)?;
+            let upsert_sql = upsert_sql(
+                &table,
+                &columns,
+                "UNNEST($1::text[], $2::text[], $3::jsonb[], $4::bigint[], $5::bigint[])
+            AS r(account, program_id, data, slot, write_version)",
+            );
+            program.accounts.push(IdlAccount {
+                name: account.name,
+                discriminator,
+                table,
+                columns,
+                upsert_sql,
+                fields,
+            });
+        }
+
+        for event in idl.events {
+            let fields = match event.fields {
+                Some(fields) => IdlFields::Named(fields),
+                None => match program.defined_layout("event", &event.name)? {
+                    Some(IdlTypeDefTy::Struct { fields }) => {
+                        fields.unwrap_or(IdlFields::Named(Vec::new()))
+                    }
+                    Some(_) => {
+                        return Err(IdlError(format!("event {} is not a struct", event.name)))
+                    }
+                    None => continue,
+                },
+            };
+            program.validate_fields(&fields, &mut Vec::new())?;
+
+            let discriminator = match event.discriminator {
+                Some(bytes) => <[u8; 8]>::try_from(bytes.as_slice()).map_err(|_| {
+                    IdlError(format!("event {} has an invalid discriminator", event.name))
+                })?,
+                None => event_discriminator(&event.name),
+            };
+            if let Some(existing) = program.events.get(&discriminator) {
+                return Err(IdlError(format!(
+                    "events {} and {} share a discriminator",
+                    existing.name, event.name
+                )));
+            }
+            program.events.insert(
+                discriminator,
+                IdlEvent {
+                    name: event.name,
+                    fields,
+                },
+            );
+        }
+
+        Ok(program)
+    }
+
+    // layout of a 0.30+ account or event from `types`, None when its serialization isn't
+    // supported
+    fn defined_layout(&self, kind: &str, name: &str) -> Result<Option<IdlTypeDefTy>, IdlError> {
+        let type_def = self
+            .types
+            .get(name)
+            .ok_or_else(|| IdlError(format!("no type definition for {} {}", kind, name)))?;
+        if let Some(serialization) = type_def.serialization.as_deref() {
+            if serialization != "borsh" {
+                log::warn!(
+                    "Skipping {} {}: {} serialization is not supported",
+                    kind,
+                    name,
+                    serialization
+                );
+                return Ok(None);
+            }
+        }
+        Ok(Some(type_def.ty.clone()))
+    }
+
+    // decodes account data following the discriminator into JSON
+    pub fn decode_account(&self, account: &IdlAccount, data: &[u8]) -> Result<Value, IdlError> {
+        self.decode_fields(&account.fields, &mut Reader { data })
+    }
+
+    // decodes `Program data:` bytes into the event's name and fields, None when the
+    // discriminator isn't one of the IDL's events
+    pub fn decode_event(&self, data: &[u8]) -> Option<Result<(&str, Value), IdlError>> {
+        let event = self.events.get(data.get(..8)?)?;
+        let decoded = self.decode_fields(&event.fields, &mut Reader { data: &data[8..] });
+        Some(decoded.map(|value| (event.name.as_str(), value)))
+    }
+
+    fn validate_fields(&self, fields: &IdlFields, stack: &mut Vec<String>) -> Result<(), IdlError> {
+        match fields {
+            IdlFields::Named(fields) => fields
+                .iter()
+                .try_for_each(|field| self.validate_type(&field.ty, stack)),
+            IdlFields::Tuple(types) => types
+                .iter()
+                .try_for_each(|ty| self.validate_type(ty, stack)),
+        }
+    }
+
+    fn validate_type(&self, ty: &IdlType, stack: &mut Vec<String>) -> Result<(), IdlError> {
+        match ty {
+            IdlType::Primitive(name) => match name.as_str() {
+                "bool" | "u8" | "i8" | "u16" | "i16" | "u32" | "i32" | "u64" | "i64" | "u128"
+                | "i128" | "f32" | "f64" | "string" | "bytes" | "publicKey" | "pubkey" => Ok(()),
+                other => Err(IdlError(format!("unsupported IDL type {}", other))),
+            },
+            IdlType::Complex(complex) => match complex {
+                IdlComplexType::Option(inner)
+                | IdlComplexType::COption(inner)
+                | IdlComplexType::Vec(inner) => self.validate_type(inner, stack),
+                IdlComplexType::Array(inner, len) => match len {
+                    IdlArrayLen::Value(_) => self.validate_type(inner, stack),
+                    IdlArrayLen::Generic { generic } => Err(IdlError(format!(
+                        "generic array length {} is not supported",
+                        generic
+                    ))),
+                },
+                IdlComplexType::Defined(defined) => {
+                    let name = defined.name();
+                    // decoding and column typing follow `defined` types without a bound, so a
+                    // type that contains itself would never finish
+                    if let Some(start) = stack.iter().position(|seen| seen == name) {
+                        let mut cycle = stack[start..].to_vec();
+                        cycle.push(name.to_string());
+                        return Err(IdlError(format!(
+                            "recursive IDL type {}",
+                            cycle.join(" -> ")
+                        )));
+                    }
+                    let type_def = self
+                        .types
+                        .get(name)
+                        .ok_or_else(|| IdlError(format!("undefined IDL type {}", name)))?;
+                    stack.push(name.to_string());
+                    let result = match &type_def.ty {
+                        IdlTypeDefTy::Struct { fields } => match fields {
+                            Some(fields) => self.validate_fields(fields, stack),
+                            None => Ok(()),
+                        },
+                        IdlTypeDefTy::Enum { variants } => {
+                            variants
+                                .iter()
+                                .try_for_each(|variant| match &variant.fields {
+                                    Some(fields) => self.validate_fields(fields, stack),
+                                    None => Ok(()),
+                                })
+                        }
+                        IdlTypeDefTy::Type { alias } => self.validate_type(alias, stack),
+                    };
+                    stack.pop();
+                    result
+                }
+            },
+        }
+    }
+
+    // fails when two fields end up with the same column name, e.g. `fooBar` and `foo_bar`
+    fn columns(&self, account: &str, fields: &IdlFields) -> Result<Vec<IdlColumn>, IdlError> {
+        let IdlFields::Named(fields) = fields else {
+            return Ok(Vec::new());
+        };
+
+        let mut columns: Vec<IdlColumn> = Vec::with_capacity(fields.len());
+        for field in fields {
+            let mut name = to_snake_case(&field.name);
+            if RESERVED_COLUMNS.contains(&name.as_str()) {
+                name = format!("field_{}", name);
+            }
+            if let Some(existing) = columns.iter().find(|column| column.name == name) {
+                return Err(IdlError(format!(
+                    "fields {} and {} of account {} both map to column {}",
+                    existing.field, field.name, account, name
+                )));
+            }
+            columns.push(IdlColumn {
+                field: field.name.clone(),
+                name,
+                sql_type: self.sql_type(&field.ty),
+            });
+        }
+        Ok(columns)
+    }
+
+    fn sql_type(&self, ty: &IdlType) -> &'static str {
+        match ty {
+            IdlType::Primitive(name) => match name.as_str() {
+                "bool" => "BOOLEAN",
+                "u8" | "i8" | "i16" => "SMALLINT",
+                "u16" | "i32" => "INTEGER",
+                "u32" | "i64" => "BIGINT",
+                "u64" | "u128" | "i128" => "NUMERIC",
+                "f32" => "REAL",
+                "f64" => "DOUBLE PRECISION",
+                _ => "TEXT",
+            },
+            IdlType::Complex(IdlComplexType::Option(inner))
+            | IdlType::Complex(IdlComplexType::COption(inner)) => self.sql_type(inner),
+            IdlType::Complex(IdlComplexType::Defined(defined)) => {
+                match self.types.get(defined.name()).map(|def| &def.ty) {
+                    Some(IdlTypeDefTy::Type { alias }) => self.sql_type(alias),
+                    _ => "JSONB",
+                }
+            }
+            IdlType::Complex(_) => "JSONB",
+        }
+    }
+
+    fn decode_fields(&self, fields: &IdlFields, reader: &mut Reader) -> Result<Value, IdlError> {
+        match fields {
+            IdlFields::Named(fields) => {
+                let mut object = Map::with_capacity(fields.len());
+                for field in fields {
+                    object.insert(field.name.clone(), self.decode_type(&field.ty, reader)?);
+                }
+                Ok(Value::Object(object))
+            }
+            IdlFields::Tuple(types) => types
+                .iter()
+                .map(|ty| self.decode_type(ty, reader))
+                .collect::<Result<Vec<_>, _>>()
+                .map(Value::Array),
+        }
+    }
+
+    fn decode_type(&self, ty: &IdlType, reader: &mut Reader) -> Result<Value, IdlError> {
+        match ty {
+            IdlType::Primitive(name) => reader.primitive(name),
+            IdlType::Complex(complex) => match complex {
+                IdlComplexType::Option(inner) => match reader.u8()? {
+                    0 => Ok(Value::Null),
+                    1 => self.decode_type(inner, reader),
+                    tag => Err(IdlError(format!("invalid option tag {}", tag))),
+                },
+                IdlComplexType::COption(inner) => match reader.u32()? {
+                    0 => {
+                        // COption is fixed size, the payload is present even when None
+                        self.decode_type(inner, reader)?;
+                        Ok(Value::Null)
+                    }
+                    1 => self.decode_type(inner, reader),
+                    tag => Err(IdlError(format!("invalid coption tag {}", tag))),
+                },
+                IdlComplexType::Vec(inner) => {
+                    let len = reader.u32()? as usize;
+                    let mut items = Vec::with_capacity(len.min(reader.data.len()));
+                    for _ in 0..len {
+                        items.push(self.decode_type(inner, reader)?);
+                    }
+                    Ok(Value::Array(items))
+                }
+                IdlComplexType::Array(inner, len) => {
+                    let len = match len {
+                        IdlArrayLen::Value(len) => *len,
+                        IdlArrayLen::Generic { generic } => {
+                            return Err(IdlError(format!("generic array length {}", generic)))
+                        }
+                    };
+                    (0..len)
+                        .map(|_| self.decode_type(inner, reader))
+                        .collect::<Result<Vec<_>, _>>()
+                        .map(Value::Array)
+                }
+                IdlComplexType::Defined(defined) => {
+                    let type_def = self.types.get(defined.name()).ok_or_else(|| {
+                        IdlError(format!("undefined IDL type {}", defined.name()))
+                    })?;
+                    match &type_def.ty {
+                        IdlTypeDefTy::Struct { fields } => match fields {
+                            Some(fields) => self.decode_fields(fields, reader),
+                            None => Ok(Value::Object(Map::new())),
+                        },
+                        IdlTypeDefTy::Enum { variants } => {
+                            let index = reader.u8()? as usize;
+                            let variant = variants.get(index).ok_or_else(|| {
+                                IdlError(format!(
+                                    "invalid variant {} for enum {}",
+                                    index, type_def.name
+                                ))
+                            })?;
+                            match &variant.fields {
+                                None => Ok(Value::String(variant.name.clone())),
+                                Some(fields) => {
+                                    let mut object = Map::with_capacity(1);
+                                    object.insert(
+                                        variant.name.clone(),
+                                        self.decode_fields(fields, reader)?,
+                                    );
+                                    Ok(Value::Object(object))
+                                }
+                            }
+                        }
+                        IdlTypeDefTy::Type { alias } => self.decode_type(alias, reader),
+                    }
+                }
+            },
+        }
+    }
+}
+
+impl IdlAccount {
+    // CREATE TABLE plus ADD COLUMN statements so a newer IDL extends an existing table
+    pub fn create_table_sql(&self) -> Vec<String> {
+        let mut statements = vec![format!(
+            "CREATE TABLE IF NOT EXISTS \"{}\" (
+                account TEXT PRIMARY KEY,
+                program_id TEXT NOT NULL,
+                data JSONB NOT NULL,
+                updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            )",
+            self.table
+        )];
+        statements.push(format!(
+            "ALTER TABLE \"{}\" ADD COLUMN IF NOT EXISTS slot BIGINT",
+            self.table
+        ));
+        statements.push(format!(
+            "ALTER TABLE \"{}\" ADD COLUMN IF NOT EXISTS write_version BIGINT",
+            self.table
+        ));
+        statements.extend(self.columns.iter().map(|column| {
+            format!(
+                "ALTER TABLE \"{}\" ADD COLUMN IF NOT EXISTS \"{}\" {}",
+                self.table, column.name, column.sql_type
+            )
+        }));
+        // rows stored before versions were kept become the accounts' first versions
+        statements.push(format!(
+            "INSERT INTO account_versions (table_name, account, slot, write_version, data)
+            SELECT '{0}', t.account, COALESCE(t.slot, 0), COALESCE(t.write_version, 0),
+                jsonb_build_object('program_id', t.program_id, 'data', t.data)
+            FROM \"{0}\" t
+            WHERE NOT EXISTS (
+                SELECT 1 FROM account_versions v
+                WHERE v.table_name = '{0}' AND v.account = t.account
+            )",
+            self.table
+        ));
+        statements
+    }
+
+    // moves the newest row per account from a startup staging table into the account table
+    pub fn merge_sql(&self, staging_table: &str) -> String {
+        upsert_sql(
+            &self.table,
+            &self.columns,
+            &format!(
+                "(SELECT DISTINCT ON (account) * FROM \"{}\" ORDER BY account, slot DESC, write_version DESC) AS r",
+                staging_table
+            ),
+        )
+    }
+}
+
+// multi-row upsert from `source` rows `r` (account, program_id, data, slot, write_version),
+// typed columns projected from the decoded JSON in `data`
+fn upsert_sql(table: &str, columns: &[IdlColumn], source: &str) -> String {
+    let mut column_list = String::from("account, program_id, data, slot, write_version");
+    let mut values = String::from("r.account, r.program_id, r.data, r.slot, r.write_version");
+    let mut updates = String::from(
+        "program_id = EXCLUDED.program_id, data = EXCLUDED.data, slot = EXCLUDED.slot, \
+        write_version = EXCLUDED.write_version",
+    );
+
+    for column in columns {
+        let field = column.field.replace('\'', "''");
+        let value = if column.sql_type == "JSONB" {
+            format!("r.data -> '{}'", field)
+        } else {
+            format!("CAST(r.data ->> '{}' AS {})", field, column.sql_type)
+        };
+        column_list.push_str(&format!(", \"{}\"", column.name));
+        values.push_str(&format!(", {}", value));
+        updates.push_str(&format!(", \"{0}\" = EXCLUDED.\"{0}\"", column.name));
+    }
+
+    format!(
+        "INSERT INTO \"{}\" ({})
+        SELECT {} FROM {}
+        ON CONFLICT (account) DO UPDATE SET {}, updated_at = CURRENT_TIMESTAMP
+        WHERE {}",
+        table,
+        column_list,
+        values,
+        source,
+        updates,
+        db::newer_version_sql(table)
+    )
+}
+
+pub fn account_discriminator(name: &str) -> [u8; 8] {
+    let mut discriminator = [0u8; 8];
+    discriminator.copy_from_slice(&hash(format!("account:{}", name).as_bytes()).to_bytes()[..8]);
+    discriminator
+}
+
+pub fn event_discriminator(name: &str) -> [u8; 8] {
+    let mut discriminator = [0u8; 8];
+    discriminator.copy_from_slice(&hash(format!("event:{}", name).as_bytes()).to_bytes()[..8]);
+    discriminator
+}
+
+// identifiers are always quoted, but keep them to [a-z0-9_] regardless
+pub fn to_snake_case(name: &str) -> String {
+    let mut snake = String::with_capacity(name.len() + 4);
+    let mut prev_lower = false;
+    for c in name.chars() {
+        if c.is_ascii_uppercase() {
+            if prev_lower {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+            prev_lower = false;
+        } else if c.is_ascii_alphanumeric() {
+            snake.push(c);
+            prev_lower = true;
+        } else {
+            snake.push('_');
+            prev_lower = false;
+        }
+    }
+    snake
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], IdlError> {
+        if self.data.len() < len {
+            return Err(IdlError(format!(
+                "unexpected end of data, needed {} more bytes",
+                len - self.data.len()
+            )));
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], IdlError> {
+        let mut bytes = [0u8; N];
+        bytes.copy_from_slice(self.take(N)?);
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, IdlError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, IdlError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn primitive(&mut self, name: &str) -> Result<Value, IdlError> {
+        let value = match name {
+            "bool" => Value::Bool(self.u8()? != 0),
+            "u8" => Value::from(self.u8()?),
+            "i8" => Value::from(i8::from_le_bytes(self.array()?)),
+            "u16" => Value::from(u16::from_le_bytes(self.array()?)),
+            "i16" => Value::from(i16::from_le_bytes(self.array()?)),
+            "u32" => Value::from(self.u32()?),
+            "i32" => Value::from(i32::from_le_bytes(self.array()?)),
+            "u64" => Value::from(u64::from_le_bytes(self.array()?)),
+            "i64" => Value::from(i64::from_le_bytes(self.array()?)),
+            // JSON numbers can't hold 128 bit values, keep them as decimal strings
+            "u128" => Value::String(u128::from_le_bytes(self.array()?).to_string()),
+            "i128" => Value::String(i128::from_le_bytes(self.array()?).to_string()),
+            "f32" => Number::from_f64(f32::from_le_bytes(self.array()?) as f64)
+                .map_or(Value::Null, Value::Number),
+            "f64" => Number::from_f64(f64::from_le_bytes(self.array()?))
+                .map_or(Value::Null, Value::Number),
+            "string" => {
+                let len = self.u32()? as usize;
+                let bytes = self.take(len)?;
+                let string = String::from_utf8(bytes.to_vec())
+                    .map_err(|e| IdlError(format!("invalid utf-8 string: {}", e)))?;
+                // jsonb rejects `\u0000`, fixed-size names are often NUL padded
+                Value::String(string.replace('\0', ""))
+            }
+            "bytes" => {
+                let len = self.u32()? as usize;
+                Value::String(BASE64.encode(self.take(len)?))
+            }
+            "publicKey" | "pubkey" => Value::String(bs58::encode(self.take(32)?).into_string()),
+            other => return Err(IdlError(format!("unsupported IDL type {}", other))),
+        };
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn program(idl: Value) -> Result<ProgramIdl, IdlError> {
+        ProgramIdl::from_idl(serde_json::from_value(idl).unwrap(), "")
+    }
+
+    fn pool_idl() -> Value {
+        json!({
+            "accounts": [{"name": "Pool", "discriminator": [1, 2, 3, 4, 5, 6, 7, 8]}],
+            "types": [
+                {"name": "Pool", "type": {"kind": "struct", "fields": [
+                    {"name": "authority", "type": "pubkey"},
+                    {"name": "feeBps", "type": "u16"},
+                    {"name": "reserve", "type": "u64"},
+                    {"name": "label", "type": {"option": "string"}},
+                    {"name": "status", "type": {"defined": {"name": "Status"}}},
+                    {"name": "weights", "type": {"vec": "u8"}}
+                ]}},
+                {"name": "Status", "type": {"kind": "enum", "variants": [
+                    {"name": "Closed"},
+                    {"name": "Open", "fields": [{"name": "since", "type": "i64"}]}
+                ]}}
+            ]
+        })
+    }
+
+    #[test]
+    fn decodes_account_fields() {
+        let program = program(pool_idl()).unwrap();
+        let account = &program.accounts[0];
+        assert_eq!(account.discriminator, [1, 2, 3, 4, 5, 6, 7, 8]);
+
+        let mut data = vec![7u8; 32];
+        data.extend_from_slice(&250u16.to_le_bytes());
+        data.extend_from_slice(&u64::MAX.to_le_bytes());
+        data.push(1);
+        data.extend_from_slice(&2u32.to_le_bytes());
+        data.extend_from_slice(b"hi");
+        data.push(1);
+        data.extend_from_slice(&(-5i64).to_le_bytes());
+        data.extend_from_slice(&3u32.to_le_bytes());
+        data.extend_from_slice(&[1, 2, 3]);
+
+        assert_eq!(
+            program.decode_account(account, &data).unwrap(),
+            json!({
+                "authority": bs58::encode([7u8; 32]).into_string(),
+                "feeBps": 250,
+                "reserve": u64::MAX,
+                "label": "hi",
+                "status": {"Open": {"since": -5}},
+                "weights": [1, 2, 3]
+            })
+        );
+
+        let err = program.decode_account(account, &data[..40]).unwrap_err();
+        assert!(err.0.contains("unexpected end of data"), "{}", err);
+    }
+
+    #[test]
+    fn generates_table_ddl() {
+        let program = program(pool_idl()).unwrap();
+        let account = &program.accounts[0];
+        assert_eq!(account.table, "pool");
+
+        let columns: Vec<(&str, &str)> = account
+            .columns
+            .iter()
+            .map(|column| (column.name.as_str(), column.sql_type))
+            .collect();
+        assert_eq!(
+            columns,
+            [
+                ("authority", "TEXT"),
+                ("fee_bps", "INTEGER"),
+                ("reserve", "NUMERIC"),
+                ("label", "TEXT"),
+                ("status", "JSONB"),
+                ("weights", "JSONB"),
+            ]
+        );
+
+        let ddl = account.create_table_sql();
+        assert!(ddl[0].starts_with("CREATE TABLE IF NOT EXISTS \"pool\""));
+        assert!(ddl.contains(
+            &"ALTER TABLE \"pool\" ADD COLUMN IF NOT EXISTS \"fee_bps\" INTEGER".to_string()
+        ));
+        assert!(account
+            .upsert_sql
+            .contains("CAST(r.data ->> 'feeBps' AS INTEGER)"));
+    }
+
+    #[test]
+    fn renames_reserved_columns() {
+        let program = program(json!({
+            "accounts": [{"name": "Vault", "type": {"kind": "struct", "fields": [
+                {"name": "slot", "type": "u64"},
+                {"name": "data", "type": "bytes"},
+                {"name": "writeVersion", "type": "u64"}
+            ]}}]
+        }))
+        .unwrap();
+        let names: Vec<&str> = program.accounts[0]
+            .columns
+            .iter()
+            .map(|column| column.name.as_str())
+            .collect();
+        assert_eq!(names, ["field_slot", "field_data", "field_write_version"]);
+    }
+
+    #[test]
+    fn rejects_colliding_columns() {
+        let idl = |fields: Value| json!({"accounts": [{"name": "Vault", "type": {"kind": "struct", "fields": fields}}]});
+
+        let err = program(idl(json!([
+            {"name": "fooBar", "type": "u8"},
+            {"name": "foo_bar", "type": "u8"}
+        ])))
+        .unwrap_err();
+        assert_eq!(
+            err.0,
+            "fields fooBar and foo_bar of account Vault both map to column foo_bar"
+        );
+
+        let err = program(idl(json!([
+            {"name": "writeVersion", "type": "u64"},
+            {"name": "field_write_version", "type": "u64"}
+        ])))
+        .unwrap_err();
+        assert!(err.0.contains("field_write_version"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_recursive_types() {
+        let err = program(json!({
+            "accounts": [{"name": "Tree", "type": {"kind": "struct", "fields": [
+                {"name": "root", "type": {"defined": "Node"}}
+            ]}}],
+            "types": [
+                {"name": "Node", "type": {"kind": "struct", "fields": [
+                    {"name": "children", "type": {"vec": {"defined": "Leaf"}}}
+                ]}},
+                {"name": "Leaf", "type": {"kind": "enum", "variants": [
+                    {"name": "Empty"},
+                    {"name": "Branch", "fields": [{"defined": "Node"}]}
+                ]}}
+            ]
+        }))
+        .unwrap_err();
+        assert_eq!(err.0, "recursive IDL type Node -> Leaf -> Node");
+    }
+
+    #[test]
+    fn decodes_events() {
+        let program = program(json!({
+            "events": [{"name": "Swapped", "fields": [{"name": "amount", "type": "u64"}]}]
+        }))
+        .unwrap();
+
+        let mut data = event_discriminator("Swapped").to_vec();
+        data.extend_from_slice(&42u64.to_le_bytes());
+        let (name, value) = program.decode_event(&data).unwrap().unwrap();
+        assert_eq!(name, "Swapped");
+        assert_eq!(value, json!({"amount": 42}));
+
+        assert!(program.decode_event(&[0u8; 16]).is_none());
+        assert!(program.decode_event(&[1, 2, 3]).is_none());
+    }
+
+    #[test]
+    fn strips_nul_from_strings() {
+        let program = program(json!({
+            "accounts": [{"name": "Named", "type": {"kind": "struct", "fields": [
+                {"name": "name", "type": "string"}
+            ]}}]
+        }))
+        .unwrap();
+        let mut data = 8u32.to_le_bytes().to_vec();
+        data.extend_from_slice(b"pool\0\0\0\0");
+        assert_eq!(
+            program.decode_account(&program.accounts[0], &data).unwrap(),
+            json!({"name": "pool"})
+        );
+    }
+
+    #[test]
+    fn converts_to_snake_case() {
+        assert_eq!(to_snake_case("FeeBps"), "fee_bps");
+        assert_eq!(to_snake_case("lpMint2"), "lp_mint2");
+        assert_eq!(to_snake_case("weird-name"), "weird_name");
+    }
+}
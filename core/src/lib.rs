@@ -1,9 +1,25 @@
 use heimdall_plugin::Heimdall;
 use solana_geyser_plugin_interface::geyser_plugin_interface::GeyserPlugin;
 
-mod config;
+mod backfill;
+pub mod config;
+mod db;
 mod heimdall_plugin;
+mod idl;
+mod logging;
+mod logs;
+mod metaplex;
+mod metrics;
+pub mod migrations;
 mod models;
+mod native;
+mod pipeline;
+mod registry;
+mod reload;
+pub mod sink;
+mod spool;
+mod startup;
+mod token;
 
 #[no_mangle]
 #[allow(improper_ctypes_definitions)]
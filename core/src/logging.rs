@@ -0,0 +1,149 @@
+use log::{Log, Metadata, Record};
+use std::{
+    fmt::{Display, Write},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        RwLock,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// each rate limited call site logs at most this many messages per window
+const WINDOW_SECS: u64 = 10;
+const MESSAGES_PER_WINDOW: u64 = 5;
+
+// keys named in a message about a whole batch
+const SAMPLE_KEYS: usize = 3;
+
+// the logger can only be installed once per process, so the installed one delegates to
+// a logger that's replaced whenever the filters change
+static LOGGER: ReloadableLogger = ReloadableLogger(RwLock::new(None));
+
+struct ReloadableLogger(RwLock<Option<env_logger::Logger>>);
+
+impl Log for ReloadableLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let logger = self.0.read().unwrap();
+        logger
+            .as_ref()
+            .is_some_and(|logger| logger.enabled(metadata))
+    }
+
+    fn log(&self, record: &Record) {
+        if let Some(logger) = self.0.read().unwrap().as_ref() {
+            logger.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(logger) = self.0.read().unwrap().as_ref() {
+            logger.flush();
+        }
+    }
+}
+
+// `filters` uses the RUST_LOG syntax, e.g. `warn,heimdall_plugin::pipeline=debug`.
+// Messages go to stderr like the validator's own log. Called again on every reload, the
+// new filters replace the old ones
+pub fn setup_logger(filters: &str) {
+    let logger = env_logger::Builder::new()
+        .parse_filters(filters)
+        .format_timestamp_millis()
+        .build();
+    let max_level = logger.filter();
+    *LOGGER.0.write().unwrap() = Some(logger);
+    // fails from the second call on, the logger is already in place
+    let _ = log::set_logger(&LOGGER);
+    log::set_max_level(max_level);
+}
+
+// the first few keys of a batch for a log message, e.g. `a, b, c and 7 more`
+pub fn sample<T: Display>(keys: impl ExactSizeIterator<Item = T>) -> String {
+    let len = keys.len();
+    let mut sample = String::new();
+    for (i, key) in keys.take(SAMPLE_KEYS).enumerate() {
+        if i > 0 {
+            sample.push_str(", ");
+        }
+        let _ = write!(sample, "{}", key);
+    }
+    if len > SAMPLE_KEYS {
+        let _ = write!(sample, " and {} more", len - SAMPLE_KEYS);
+    }
+    sample
+}
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    window: AtomicU64,
+    logged: AtomicU64,
+    suppressed: AtomicU64,
+}
+
+impl RateLimiter {
+    pub const fn new() -> Self {
+        RateLimiter {
+            window: AtomicU64::new(0),
+            logged: AtomicU64::new(0),
+            suppressed: AtomicU64::new(0),
+        }
+    }
+
+    // Some(messages suppressed since the last one) if this one may be logged
+    pub fn allow(&self) -> Option<u64> {
+        let window = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_secs() / WINDOW_SECS)
+            .unwrap_or_default();
+        if self.window.swap(window, Ordering::Relaxed) != window {
+            self.logged.store(0, Ordering::Relaxed);
+        }
+
+        if self.logged.fetch_add(1, Ordering::Relaxed) < MESSAGES_PER_WINDOW {
+            Some(self.suppressed.swap(0, Ordering::Relaxed))
+        } else {
+            self.suppressed.fetch_add(1, Ordering::Relaxed);
+            None
+        }
+    }
+}
+
+// `log::$level!` for messages that repeat on every batch or update while something is
+// broken, e.g. the database being down
+macro_rules! limited {
+    ($level:ident, $($arg:tt)+) => {{
+        static LIMITER: $crate::logging::RateLimiter = $crate::logging::RateLimiter::new();
+        if let Some(suppressed) = LIMITER.allow() {
+            if suppressed > 0 {
+                log::$level!(suppressed; "{} similar messages were suppressed", suppressed);
+            }
+            log::$level!($($arg)+);
+        }
+    }};
+}
+
+pub(crate) use limited;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_batch_keys() {
+        assert_eq!(sample(["a", "b"].iter()), "a, b");
+        assert_eq!(
+            sample(["a", "b", "c", "d", "e"].iter()),
+            "a, b, c and 2 more"
+        );
+        assert_eq!(sample(Vec::<String>::new().iter()), "");
+    }
+
+    #[test]
+    fn replaces_the_filters() {
+        setup_logger("warn");
+        assert!(!log::log_enabled!(target: "heimdall_plugin", log::Level::Info));
+        setup_logger("info");
+        assert!(log::log_enabled!(target: "heimdall_plugin", log::Level::Info));
+        assert!(!log::log_enabled!(target: "heimdall_plugin", log::Level::Debug));
+    }
+}
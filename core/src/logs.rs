@@ -0,0 +1,132 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+
+// a `Program data:` line, attributed to the program whose invocation logged it
+#[derive(Debug)]
+pub struct ProgramData<'a> {
+    pub program_id: &'a str,
+    // top-level instruction the line was logged under
+    pub instruction_index: u32,
+    // position among the transaction's `Program data:` lines
+    pub index: u32,
+    pub data: Vec<u8>,
+}
+
+// walks the invocation stack of a transaction's log messages. Lines that aren't valid
+// base64, or that were logged outside of any invocation, are skipped but still counted
+pub fn program_data(log_messages: &[String]) -> Vec<ProgramData<'_>> {
+    let mut stack: Vec<&str> = Vec::new();
+    let mut instructions = 0u32;
+    let mut index = 0u32;
+    let mut found = Vec::new();
+
+    for line in log_messages {
+        if let Some(data) = line.strip_prefix("Program data: ") {
+            if let (Some(program_id), Ok(data)) = (stack.last(), BASE64.decode(data)) {
+                found.push(ProgramData {
+                    program_id,
+                    instruction_index: instructions.saturating_sub(1),
+                    index,
+                    data,
+                });
+            }
+            index += 1;
+        } else if line == "Log truncated" {
+            // the stack can't be followed past this point
+            break;
+        } else if let Some(rest) = line.strip_prefix("Program ") {
+            let mut words = rest.split(' ');
+            let (Some(program_id), Some(word)) = (words.next(), words.next()) else {
+                continue;
+            };
+            if word == "invoke" {
+                if stack.is_empty() {
+                    instructions += 1;
+                }
+                stack.push(program_id);
+            } else if (word == "success" || word == "failed:") && stack.last() == Some(&program_id)
+            {
+                stack.pop();
+            }
+        }
+    }
+
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn logs(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    fn found(log_messages: &[String]) -> Vec<(&str, u32, u32, Vec<u8>)> {
+        program_data(log_messages)
+            .into_iter()
+            .map(|line| {
+                (
+                    line.program_id,
+                    line.instruction_index,
+                    line.index,
+                    line.data,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn attributes_lines_to_the_innermost_invocation() {
+        let log_messages = logs(&[
+            "Program Outer invoke [1]",
+            "Program data: AQ==",
+            "Program Inner invoke [2]",
+            "Program log: Instruction: Swap",
+            "Program data: Ag==",
+            "Program Inner consumed 100 of 200000 compute units",
+            "Program Inner success",
+            "Program data: Aw==",
+            "Program Outer success",
+            "Program Other invoke [1]",
+            "Program Inner invoke [2]",
+            "Program data: BA==",
+            "Program Inner failed: custom program error: 0x1",
+            "Program Other failed: custom program error: 0x1",
+        ]);
+        assert_eq!(
+            found(&log_messages),
+            [
+                ("Outer", 0, 0, vec![1]),
+                ("Inner", 0, 1, vec![2]),
+                ("Outer", 0, 2, vec![3]),
+                ("Inner", 1, 3, vec![4]),
+            ]
+        );
+    }
+
+    #[test]
+    fn counts_lines_it_skips() {
+        let log_messages = logs(&[
+            "Program data: AQ==",
+            "Program Outer invoke [1]",
+            "Program data: not base64!",
+            "Program data: Ag==",
+            "Program Outer success",
+        ]);
+        assert_eq!(found(&log_messages), [("Outer", 0, 2, vec![2])]);
+    }
+
+    #[test]
+    fn stops_at_truncated_logs() {
+        let log_messages = logs(&[
+            "Program Outer invoke [1]",
+            "Program data: AQ==",
+            "Log truncated",
+            "Program data: Ag==",
+            "Program Outer success",
+            "Program Other invoke [1]",
+            "Program data: Aw==",
+        ]);
+        assert_eq!(found(&log_messages), [("Outer", 0, 0, vec![1])]);
+    }
+}
@@ -0,0 +1,209 @@
+use anchor_lang::{prelude::*, AnchorDeserialize};
+use serde_json::{json, Value};
+
+use crate::models::NftMetadata;
+
+pub const METADATA_PROGRAM_ID: Pubkey = pubkey!("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+
+// first byte of every token-metadata account
+const KEY_MASTER_EDITION_V1: u8 = 2;
+const KEY_METADATA_V1: u8 = 4;
+const KEY_MASTER_EDITION_V2: u8 = 6;
+
+// `TokenStandard` variant names, indexed by their borsh tag
+pub const TOKEN_STANDARDS: [&str; 6] = [
+    "NonFungible",
+    "FungibleAsset",
+    "Fungible",
+    "NonFungibleEdition",
+    "ProgrammableNonFungible",
+    "ProgrammableNonFungibleEdition",
+];
+
+// leading fields of a token-metadata `Metadata` account after its key byte, everything
+// after the collection is left undecoded
+#[derive(Debug, AnchorDeserialize)]
+pub struct Metadata {
+    pub update_authority: Pubkey,
+    pub mint: Pubkey,
+    pub data: MetadataData,
+    pub primary_sale_happened: bool,
+    pub is_mutable: bool,
+    // only read to get past it
+    pub _edition_nonce: Option<u8>,
+    pub token_standard: Option<u8>,
+    pub collection: Option<Collection>,
+}
+
+#[derive(Debug, AnchorDeserialize)]
+pub struct MetadataData {
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+    pub seller_fee_basis_points: u16,
+    pub creators: Option<Vec<Creator>>,
+}
+
+#[derive(Debug, AnchorDeserialize)]
+pub struct Creator {
+    pub address: Pubkey,
+    pub verified: bool,
+    pub share: u8,
+}
+
+#[derive(Debug, AnchorDeserialize)]
+pub struct Collection {
+    pub verified: bool,
+    pub key: Pubkey,
+}
+
+// V1 and V2 master editions share their leading fields
+#[derive(Debug, AnchorDeserialize)]
+pub struct MasterEdition {
+    pub supply: u64,
+    pub max_supply: Option<u64>,
+}
+
+pub fn is_metadata_program(owner: &[u8]) -> bool {
+    owner == METADATA_PROGRAM_ID.as_ref()
+}
+
+// accounts are allocated at their maximum size, so the fields of older layouts that
+// were never written read as zeroes, i.e. `None`
+pub fn parse_metadata(data: &[u8]) -> Option<Metadata> {
+    if data.first() != Some(&KEY_METADATA_V1) {
+        return None;
+    }
+    Metadata::deserialize(&mut &data[1..]).ok()
+}
+
+// the mint of a `Metadata` account, read at its fixed offset without decoding the rest
+pub fn metadata_mint(data: &[u8]) -> Option<&[u8]> {
+    if data.first() != Some(&KEY_METADATA_V1) {
+        return None;
+    }
+    data.get(33..65)
+}
+
+pub fn parse_master_edition(data: &[u8]) -> Option<MasterEdition> {
+    match data.first() {
+        Some(&KEY_MASTER_EDITION_V1) | Some(&KEY_MASTER_EDITION_V2) => {
+            MasterEdition::deserialize(&mut &data[1..]).ok()
+        }
+        _ => None,
+    }
+}
+
+// the `Metadata` account of `mint`
+pub fn metadata_address(mint: &[u8; 32]) -> [u8; 32] {
+    let (address, _) = Pubkey::find_program_address(
+        &[b"metadata", METADATA_PROGRAM_ID.as_ref(), mint.as_ref()],
+        &METADATA_PROGRAM_ID,
+    );
+    address.to_bytes()
+}
+
+// a master edition has no mint field, it's only found through its address
+pub fn master_edition_address(mint: &[u8; 32]) -> [u8; 32] {
+    let (address, _) = Pubkey::find_program_address(
+        &[
+            b"metadata",
+            METADATA_PROGRAM_ID.as_ref(),
+            mint.as_ref(),
+            b"edition",
+        ],
+        &METADATA_PROGRAM_ID,
+    );
+    address.to_bytes()
+}
+
+// the `nft_metadata` row of a decoded account
+pub fn nft_metadata(metadata: &Metadata, slot: u64, write_version: u64) -> NftMetadata {
+    let collection = metadata.collection.as_ref();
+    NftMetadata {
+        update_authority: metadata.update_authority.to_string(),
+        name: trim_padding(&metadata.data.name),
+        symbol: trim_padding(&metadata.data.symbol),
+        uri: trim_padding(&metadata.data.uri),
+        seller_fee_basis_points: metadata.data.seller_fee_basis_points,
+        creators: creators_json(&metadata.data.creators),
+        primary_sale_happened: metadata.primary_sale_happened,
+        is_mutable: metadata.is_mutable,
+        token_standard: metadata.token_standard.and_then(token_standard_name),
+        collection: collection.map(|collection| collection.key.to_string()),
+        collection_verified: collection.is_some_and(|collection| collection.verified),
+        slot,
+        write_version,
+    }
+}
+
+pub fn creators_json(creators: &Option<Vec<Creator>>) -> Value {
+    creators
+        .iter()
+        .flatten()
+        .map(|creator| {
+            json!({
+                "address": creator.address.to_string(),
+                "verified": creator.verified,
+                "share": creator.share,
+            })
+        })
+        .collect()
+}
+
+// strings are stored padded with NULs to their maximum length, and Postgres text can't
+// hold a NUL anywhere
+pub fn trim_padding(value: &str) -> String {
+    value.replace('\0', "")
+}
+
+pub fn token_standard_name(token_standard: u8) -> Option<&'static str> {
+    TOKEN_STANDARDS.get(token_standard as usize).copied()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // a `Metadata` account named `name`, padded like the program pads it
+    pub(crate) fn metadata_account(mint: &Pubkey, name: &str) -> Vec<u8> {
+        let mut data = vec![KEY_METADATA_V1];
+        data.extend_from_slice(Pubkey::new_unique().as_ref());
+        data.extend_from_slice(mint.as_ref());
+        for (value, len) in [(name, 32), ("NFT", 10), ("https://example.com", 200)] {
+            data.extend_from_slice(&(len as u32).to_le_bytes());
+            data.extend_from_slice(value.as_bytes());
+            data.resize(data.len() + len - value.len(), 0);
+        }
+        data.extend_from_slice(&500u16.to_le_bytes());
+        // no creators, primary sale happened, mutable, no edition nonce
+        data.extend_from_slice(&[0, 1, 1, 0]);
+        // NonFungible, no collection
+        data.extend_from_slice(&[1, 0, 0]);
+        data.resize(679, 0);
+        data
+    }
+
+    #[test]
+    fn reads_the_metadata_mint_without_decoding() {
+        let mint = Pubkey::new_unique();
+        let data = metadata_account(&mint, "Token #1");
+        assert_eq!(metadata_mint(&data), Some(mint.as_ref()));
+
+        let metadata = parse_metadata(&data).unwrap();
+        assert_eq!(metadata.mint, mint);
+        assert_eq!(trim_padding(&metadata.data.name), "Token #1");
+        assert_eq!(
+            metadata.token_standard.and_then(token_standard_name),
+            Some("NonFungible")
+        );
+
+        // a master edition carries no mint
+        let mut edition = vec![KEY_MASTER_EDITION_V2];
+        edition.extend_from_slice(&1u64.to_le_bytes());
+        edition.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(metadata_mint(&edition), None);
+        assert_eq!(parse_master_edition(&edition).unwrap().max_supply, Some(0));
+        assert_eq!(metadata_mint(&data[..40]), None);
+    }
+}
@@ -0,0 +1,259 @@
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server, StatusCode,
+};
+use prometheus::{
+    exponential_buckets, Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use std::{convert::Infallible, future::Future, net::SocketAddr, sync::LazyLock};
+use tokio::{runtime::Runtime, task::JoinHandle};
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+// everything exposed on the metrics endpoint
+#[derive(Debug)]
+pub struct Metrics {
+    registry: Registry,
+    // every account update the validator sends
+    pub account_updates: IntCounter,
+    // updates of accounts owned by a tracked program, and those that passed its filters
+    // and were decoded
+    pub accounts_seen: IntCounterVec,
+    pub accounts_matched: IntCounterVec,
+    // by program and reason: `unknown_discriminator`, `error` or `event`
+    pub decode_failures: IntCounterVec,
+    // by write target, e.g. `listings` or `notify`
+    pub db_write_seconds: HistogramVec,
+    pub db_write_errors: IntCounterVec,
+    // by pipeline worker
+    pub queue_depth: IntGaugeVec,
+    pub queue_dropped: IntCounter,
+    pub notify_failures: IntCounter,
+    // batches held back on disk while the database is unreachable, and those dropped
+    // because there was no spool or it was full
+    pub spool_batches: IntGauge,
+    pub spool_dropped: IntCounter,
+    // events the ndjson sink dropped because its writer fell behind
+    pub ndjson_dropped: IntCounter,
+    // highest slot seen per status
+    pub slot: IntGaugeVec,
+    // highest slot of an account update and of a slot status written to Postgres
+    pub account_update_slot: IntGauge,
+    pub written_slot: IntGauge,
+    pub startup_accounts: IntCounter,
+    pub startup_complete: IntGauge,
+    // RPC requests for newly tracked accounts that failed and were retried
+    pub backfill_failures: IntCounter,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+
+        let metrics = Metrics {
+            account_updates: IntCounter::new(
+                "heimdall_account_updates_total",
+                "Account updates received from the validator",
+            )
+            .unwrap(),
+            accounts_seen: IntCounterVec::new(
+                Opts::new(
+                    "heimdall_accounts_seen_total",
+                    "Updates of accounts owned by a tracked program",
+                ),
+                &["program"],
+            )
+            .unwrap(),
+            accounts_matched: IntCounterVec::new(
+                Opts::new(
+                    "heimdall_accounts_matched_total",
+                    "Updates of accounts that passed their program's filters and were decoded",
+                ),
+                &["program"],
+            )
+            .unwrap(),
+            decode_failures: IntCounterVec::new(
+                Opts::new(
+                    "heimdall_decode_failures_total",
+                    "Accounts and events of tracked programs that couldn't be decoded",
+                ),
+                &["program", "reason"],
+            )
+            .unwrap(),
+            db_write_seconds: HistogramVec::new(
+                HistogramOpts::new(
+                    "heimdall_db_write_seconds",
+                    "Duration of the database writes of one batch",
+                )
+                .buckets(exponential_buckets(0.001, 2.0, 15).unwrap()),
+                &["target"],
+            )
+            .unwrap(),
+            db_write_errors: IntCounterVec::new(
+                Opts::new("heimdall_db_write_errors_total", "Failed database writes"),
+                &["target"],
+            )
+            .unwrap(),
+            queue_depth: IntGaugeVec::new(
+                Opts::new(
+                    "heimdall_write_queue_depth",
+                    "Updates waiting in a write pipeline worker's queue",
+                ),
+                &["worker"],
+            )
+            .unwrap(),
+            queue_dropped: IntCounter::new(
+                "heimdall_write_queue_dropped_total",
+                "Updates dropped because the write queue was full",
+            )
+            .unwrap(),
+            notify_failures: IntCounter::new(
+                "heimdall_notify_failures_total",
+                "pg_notify calls that failed",
+            )
+            .unwrap(),
+            spool_batches: IntGauge::new(
+                "heimdall_spool_batches",
+                "Batches spooled to disk while the database is unreachable",
+            )
+            .unwrap(),
+            spool_dropped: IntCounter::new(
+                "heimdall_spool_dropped_total",
+                "Batches dropped while the database was unreachable",
+            )
+            .unwrap(),
+            ndjson_dropped: IntCounter::new(
+                "heimdall_ndjson_dropped_total",
+                "Events the ndjson sink dropped because its queue was full",
+            )
+            .unwrap(),
+            slot: IntGaugeVec::new(
+                Opts::new(
+                    "heimdall_slot",
+                    "Highest slot the validator reported per status",
+                ),
+                &["status"],
+            )
+            .unwrap(),
+            account_update_slot: IntGauge::new(
+                "heimdall_account_update_slot",
+                "Highest slot of an account update received",
+            )
+            .unwrap(),
+            written_slot: IntGauge::new(
+                "heimdall_written_slot",
+                "Highest slot whose status was written to the database",
+            )
+            .unwrap(),
+            startup_accounts: IntCounter::new(
+                "heimdall_startup_accounts_total",
+                "Snapshot accounts queued for the startup loader",
+            )
+            .unwrap(),
+            startup_complete: IntGauge::new(
+                "heimdall_startup_complete",
+                "1 once the startup snapshot has been loaded",
+            )
+            .unwrap(),
+            backfill_failures: IntCounter::new(
+                "heimdall_backfill_failures_total",
+                "RPC requests for newly tracked accounts that failed and were retried",
+            )
+            .unwrap(),
+            registry,
+        };
+
+        let collectors: [Box<dyn prometheus::core::Collector>; 18] = [
+            Box::new(metrics.account_updates.clone()),
+            Box::new(metrics.accounts_seen.clone()),
+            Box::new(metrics.accounts_matched.clone()),
+            Box::new(metrics.decode_failures.clone()),
+            Box::new(metrics.db_write_seconds.clone()),
+            Box::new(metrics.db_write_errors.clone()),
+            Box::new(metrics.queue_depth.clone()),
+            Box::new(metrics.queue_dropped.clone()),
+            Box::new(metrics.notify_failures.clone()),
+            Box::new(metrics.spool_batches.clone()),
+            Box::new(metrics.spool_dropped.clone()),
+            Box::new(metrics.ndjson_dropped.clone()),
+            Box::new(metrics.slot.clone()),
+            Box::new(metrics.account_update_slot.clone()),
+            Box::new(metrics.written_slot.clone()),
+            Box::new(metrics.startup_accounts.clone()),
+            Box::new(metrics.startup_complete.clone()),
+            Box::new(metrics.backfill_failures.clone()),
+        ];
+        for collector in collectors {
+            metrics.registry.register(collector).unwrap();
+        }
+
+        metrics
+    }
+}
+
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+// gauges only move forward, slots arrive from several threads out of order
+pub fn set_max(gauge: &IntGauge, value: u64) {
+    let value = value as i64;
+    if value > gauge.get() {
+        gauge.set(value);
+    }
+}
+
+// records how long a database write took and whether it failed
+pub async fn time_write<T, E>(
+    target: &str,
+    write: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    let timer = metrics()
+        .db_write_seconds
+        .with_label_values(&[target])
+        .start_timer();
+    let result = write.await;
+    timer.observe_duration();
+
+    if result.is_err() {
+        metrics().db_write_errors.with_label_values(&[target]).inc();
+    }
+    result
+}
+
+// serves the text exposition format on `/metrics`
+pub fn serve(runtime: &Runtime, address: SocketAddr) -> Result<JoinHandle<()>, hyper::Error> {
+    // binding registers the listener with the runtime's reactor
+    let _guard = runtime.enter();
+    let server = Server::try_bind(&address)?.serve(make_service_fn(|_| async {
+        Ok::<_, Infallible>(service_fn(handle))
+    }));
+
+    Ok(runtime.spawn(async move {
+        if let Err(e) = server.await {
+            log::error!("Metrics server failed: {:?}", e);
+        }
+    }))
+}
+
+async fn handle(request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    if request.uri().path() != "/metrics" {
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return Ok(response);
+    }
+
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    if let Err(e) = encoder.encode(&metrics().registry.gather(), &mut buffer) {
+        log::error!("Error encoding metrics: {:?}", e);
+    }
+
+    let mut response = Response::new(Body::from(buffer));
+    if let Ok(content_type) = encoder.format_type().parse() {
+        response.headers_mut().insert(CONTENT_TYPE, content_type);
+    }
+    Ok(response)
+}
@@ -0,0 +1,236 @@
+use sqlx::{Connection, PgConnection, Pool, Postgres, Row};
+
+// arbitrary key for the advisory lock held while migrating, so a validator and the
+// standalone binary never migrate the same database at once
+const MIGRATION_LOCK: i64 = 0x6865_696d_6461_6c6c;
+
+// schema changes ship as new files, an applied migration is never edited
+#[derive(Debug)]
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "baseline",
+        sql: include_str!("../migrations/0001_baseline.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "native_accounts",
+        sql: include_str!("../migrations/0002_native_accounts.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "listing_history",
+        sql: include_str!("../migrations/0003_listing_history.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "events",
+        sql: include_str!("../migrations/0004_events.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "account_versions",
+        sql: include_str!("../migrations/0005_account_versions.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "token_accounts",
+        sql: include_str!("../migrations/0006_token_accounts.sql"),
+    },
+];
+
+// tables and views the migrations create, IDL account tables may not reuse these names
+pub const PLUGIN_TABLES: &[&str] = &[
+    "schema_version",
+    "listings",
+    "slots",
+    "confirmed_listings",
+    "finalized_listings",
+    "wallets",
+    "sol_balance_history",
+    "token_balances",
+    "mints",
+    "token_account_history",
+    "security_events",
+    "nft_metadata",
+    "master_editions",
+    "nft_holdings",
+    "transactions",
+    "blocks",
+    "tracked_entities",
+    "epochs",
+    "stake_accounts",
+    "vote_accounts",
+    "nonce_accounts",
+    "listing_history",
+    "events",
+    "account_versions",
+];
+
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+// versions recorded in `schema_version`, empty for a database that was never migrated
+pub async fn applied_versions(pool: &Pool<Postgres>) -> Result<Vec<i64>, sqlx::Error> {
+    let exists: bool = sqlx::query("SELECT to_regclass('schema_version') IS NOT NULL")
+        .fetch_one(pool)
+        .await?
+        .get(0);
+    if !exists {
+        return Ok(Vec::new());
+    }
+
+    let rows = sqlx::query("SELECT version FROM schema_version ORDER BY version")
+        .fetch_all(pool)
+        .await?;
+    Ok(rows.iter().map(|row| row.get(0)).collect())
+}
+
+// applies every migration not in `schema_version` yet, in order and each in its own
+// transaction, and returns the ones applied
+pub async fn migrate(pool: &Pool<Postgres>) -> Result<Vec<&'static Migration>, sqlx::Error> {
+    let mut conn = pool.acquire().await?;
+    sqlx::query("SELECT pg_advisory_lock($1)")
+        .bind(MIGRATION_LOCK)
+        .execute(&mut *conn)
+        .await?;
+
+    let migrated = apply_pending(&mut conn).await;
+
+    let unlocked = sqlx::query("SELECT pg_advisory_unlock($1)")
+        .bind(MIGRATION_LOCK)
+        .execute(&mut *conn)
+        .await;
+    let migrated = migrated?;
+    unlocked?;
+    Ok(migrated)
+}
+
+async fn apply_pending(conn: &mut PgConnection) -> Result<Vec<&'static Migration>, sqlx::Error> {
+    sqlx::raw_sql(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version BIGINT PRIMARY KEY,
+            name TEXT NOT NULL,
+            applied_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        )",
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    let applied: Vec<i64> = sqlx::query("SELECT version FROM schema_version")
+        .fetch_all(&mut *conn)
+        .await?
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+
+    // a newer plugin already migrated this database, nothing here knows its schema
+    if let Some(newest) = applied
+        .iter()
+        .max()
+        .filter(|&&version| version > latest_version())
+    {
+        log::warn!(
+            "Database schema version {} is newer than this plugin's {}",
+            newest,
+            latest_version()
+        );
+    }
+
+    let mut migrated = Vec::new();
+    for migration in MIGRATIONS {
+        if applied.contains(&migration.version) {
+            continue;
+        }
+
+        let mut tx = conn.begin().await?;
+        sqlx::raw_sql(migration.sql).execute(&mut *tx).await?;
+        sqlx::query("INSERT INTO schema_version (version, name) VALUES ($1, $2)")
+            .bind(migration.version)
+            .bind(migration.name)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        log::info!(
+            "Applied schema migration {} ({})",
+            migration.version,
+            migration.name
+        );
+        migrated.push(migration);
+    }
+
+    Ok(migrated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plugin_tables_cover_migrations() {
+        for migration in MIGRATIONS {
+            let sql: String = migration
+                .sql
+                .lines()
+                .filter(|line| !line.trim_start().starts_with("--"))
+                .collect::<Vec<_>>()
+                .join("\n");
+            for statement in sql.split(';') {
+                let words: Vec<&str> = statement.split_whitespace().collect();
+                let name = match words.as_slice() {
+                    ["CREATE", "TABLE", "IF", "NOT", "EXISTS", name, ..]
+                    | ["CREATE", "TABLE", name, ..]
+                    | ["CREATE", "OR", "REPLACE", "VIEW", name, ..]
+                    | ["CREATE", "VIEW", name, ..] => name,
+                    _ => continue,
+                };
+                assert!(
+                    PLUGIN_TABLES.contains(name),
+                    "{} from migration {} is missing from PLUGIN_TABLES",
+                    name,
+                    migration.version
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn migrations_are_numbered_in_order() {
+        for (i, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, i as i64 + 1, "{}", migration.name);
+        }
+        assert_eq!(latest_version(), MIGRATIONS.len() as i64);
+    }
+
+    #[test]
+    fn every_migration_file_is_listed() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("migrations");
+        let mut files: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+
+        let listed: Vec<String> = MIGRATIONS
+            .iter()
+            .map(|migration| format!("{:04}_{}.sql", migration.version, migration.name))
+            .collect();
+        assert_eq!(files, listed);
+        for (file, migration) in files.iter().zip(MIGRATIONS) {
+            assert_eq!(
+                std::fs::read_to_string(dir.join(file)).unwrap(),
+                migration.sql,
+                "{} is listed with another file's SQL",
+                file
+            );
+        }
+    }
+}
@@ -1,5 +1,12 @@
 use anchor_lang::{prelude::*, AnchorDeserialize};
-use serde::{Deserialize, Serialize};
+use serde::{de::Error, Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+use crate::{metaplex::TOKEN_STANDARDS, token};
+
+// one of a fixed set of names, e.g. a token program. Spelled as an alias so serde doesn't
+// try to borrow it from the input, the `deserialize_with` functions below map it back
+pub type Label = &'static str;
 
 // reads directly from solana account data
 #[derive(Debug, AnchorDeserialize)]
@@ -19,7 +26,7 @@ pub struct AnchorListing {
 }
 
 // database/JSON operations
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Listing {
     pub name: String,
     pub seed: u64,
@@ -34,3 +41,211 @@ pub struct Listing {
     pub vault_bump: u8,
     pub mint_bump: u8,
 }
+
+// one version of a listing or IDL account row, the row as JSON or None for a close
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccountVersion {
+    pub table: String,
+    pub account: String,
+    pub slot: u64,
+    pub write_version: u64,
+    pub data: Option<Value>,
+}
+
+// transaction touching a tracked program or user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transaction {
+    pub signature: String,
+    pub slot: u64,
+    pub index: Option<u64>,
+    pub fee: u64,
+    pub err: Option<String>,
+    pub signers: Vec<String>,
+    pub account_keys: Vec<String>,
+    pub log_messages: Option<Vec<String>>,
+    pub compute_units: Option<u64>,
+}
+
+// Anchor event a tracked program logged in a successful transaction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProgramEvent {
+    pub signature: String,
+    pub slot: u64,
+    // position among the transaction's `Program data:` lines, decodable or not
+    pub event_index: u32,
+    // top-level instruction the event was logged under, also for CPIs
+    pub instruction_index: u32,
+    pub program_id: String,
+    pub name: String,
+    pub data: Value,
+}
+
+// block metadata, the parent fields and transaction count are missing from the oldest notification version
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Block {
+    pub slot: u64,
+    pub parent_slot: Option<u64>,
+    pub blockhash: String,
+    pub parent_blockhash: Option<String>,
+    pub block_time: Option<i64>,
+    pub block_height: Option<u64>,
+    pub rewards: Value,
+    pub executed_transaction_count: Option<u64>,
+}
+
+// a wallet's balance of one mint, as of the account update at (slot, write_version)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenBalance {
+    pub amount: u64,
+    #[serde(deserialize_with = "token_program")]
+    pub token_program: Label,
+    pub extensions: Value,
+    // None once the token account is closed
+    pub authorities: Option<TokenAuthorities>,
+    pub slot: u64,
+    pub write_version: u64,
+}
+
+// who else can move or close a token account, and whether it's frozen
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TokenAuthorities {
+    pub delegate: Option<String>,
+    pub delegated_amount: u64,
+    pub frozen: bool,
+    pub close_authority: Option<String>,
+}
+
+// change to a tracked user's token account worth alerting on
+#[derive(Debug)]
+pub struct SecurityEvent {
+    pub wallet: String,
+    pub mint: String,
+    pub event: &'static str,
+    pub details: Value,
+    pub slot: u64,
+}
+
+// mint of a token held by a tracked wallet or referenced by a listing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MintInfo {
+    pub decimals: u8,
+    pub supply: u64,
+    pub mint_authority: Option<String>,
+    pub freeze_authority: Option<String>,
+    #[serde(deserialize_with = "token_program")]
+    pub token_program: Label,
+    pub extensions: Value,
+    pub slot: u64,
+    pub write_version: u64,
+}
+
+// Metaplex metadata of a tracked mint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NftMetadata {
+    pub update_authority: String,
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+    pub seller_fee_basis_points: u16,
+    // [{"address", "verified", "share"}]
+    pub creators: Value,
+    pub primary_sale_happened: bool,
+    pub is_mutable: bool,
+    #[serde(deserialize_with = "token_standard")]
+    pub token_standard: Option<Label>,
+    pub collection: Option<String>,
+    pub collection_verified: bool,
+    pub slot: u64,
+    pub write_version: u64,
+}
+
+// Metaplex master edition of a tracked mint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MasterEdition {
+    pub supply: u64,
+    pub max_supply: Option<u64>,
+    pub slot: u64,
+    pub write_version: u64,
+}
+
+// stake, vote or nonce account controlled by a tracked user, as of the account update at
+// (slot, write_version)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NativeAccount {
+    // tracked users among the account's authorities
+    pub wallets: Vec<String>,
+    pub lamports: u64,
+    pub state: NativeState,
+    pub slot: u64,
+    pub write_version: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum NativeState {
+    Stake(StakeAccount),
+    Vote(VoteAccount),
+    Nonce(NonceAccount),
+}
+
+// the delegation fields are None until the stake is delegated, the epochs also when
+// they're u64::MAX (active since genesis, not deactivating)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StakeAccount {
+    pub staker: String,
+    pub withdrawer: String,
+    pub rent_exempt_reserve: u64,
+    pub lockup_unix_timestamp: i64,
+    pub lockup_epoch: u64,
+    pub lockup_custodian: Option<String>,
+    pub voter: Option<String>,
+    pub delegated_stake: Option<u64>,
+    pub activation_epoch: Option<u64>,
+    pub deactivation_epoch: Option<u64>,
+    pub credits_observed: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VoteAccount {
+    pub node_pubkey: String,
+    pub authorized_withdrawer: String,
+    // voter of the newest epoch it's set for
+    pub authorized_voter: Option<String>,
+    pub commission: u8,
+    pub credits: u64,
+    pub last_vote_slot: Option<u64>,
+    pub root_slot: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NonceAccount {
+    pub authority: String,
+    // the durable nonce transactions use in place of a recent blockhash
+    pub blockhash: String,
+    pub lamports_per_signature: u64,
+}
+
+// the `&'static str` labels can only be read back, e.g. from the spool, as one of their
+// known values
+fn token_program<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<&'static str, D::Error> {
+    let name = <String as Deserialize>::deserialize(deserializer)?;
+    [token::SPL_TOKEN, token::SPL_TOKEN_2022]
+        .into_iter()
+        .find(|known| *known == name)
+        .ok_or_else(|| D::Error::custom(format!("Unknown token program {}", name)))
+}
+
+fn token_standard<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<&'static str>, D::Error> {
+    let Some(name) = <Option<String> as Deserialize>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    TOKEN_STANDARDS
+        .into_iter()
+        .find(|known| *known == name)
+        .map(Some)
+        .ok_or_else(|| D::Error::custom(format!("Unknown token standard {}", name)))
+}
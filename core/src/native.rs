@@ -0,0 +1,349 @@
+use solana_program::{
+    nonce::state::{State as NonceState, Versions as NonceVersions},
+    pubkey::Pubkey,
+    stake::{self, state::StakeStateV2},
+    system_program,
+    sysvar::{self, clock::Clock},
+    vote::{
+        self,
+        state::{VoteState, VoteStateVersions},
+    },
+};
+
+use crate::models::{NativeState, NonceAccount, StakeAccount, VoteAccount};
+
+pub const STAKE_ACCOUNTS_TABLE: &str = "stake_accounts";
+pub const VOTE_ACCOUNTS_TABLE: &str = "vote_accounts";
+pub const NONCE_ACCOUNTS_TABLE: &str = "nonce_accounts";
+
+// a nonce account is a system account of exactly this size
+const NONCE_ACCOUNT_LEN: usize = 80;
+
+// table the rows of `owner`'s accounts go to, before anything is decoded. System accounts
+// only count when they're nonce sized
+pub fn native_table(owner: &[u8], data_len: usize) -> Option<&'static str> {
+    if owner == stake::program::ID.as_ref() {
+        Some(STAKE_ACCOUNTS_TABLE)
+    } else if owner == vote::program::ID.as_ref() {
+        Some(VOTE_ACCOUNTS_TABLE)
+    } else if owner == system_program::ID.as_ref() && data_len == NONCE_ACCOUNT_LEN {
+        Some(NONCE_ACCOUNTS_TABLE)
+    } else {
+        None
+    }
+}
+
+// addresses that control the account, read at their fixed offsets so accounts of nobody
+// tracked are never fully decoded. Empty for anything uninitialized
+pub fn authorities(table: &str, data: &[u8]) -> Vec<[u8; 32]> {
+    let offsets: &[usize] = match table {
+        // tag, rent exempt reserve, then the staker and withdrawer
+        STAKE_ACCOUNTS_TABLE if matches!(tag(data), Some(1 | 2)) => &[12, 44],
+        // node identity and withdrawer lead both current layouts, the 0.23.5 one is long
+        // gone from mainnet
+        VOTE_ACCOUNTS_TABLE if matches!(tag(data), Some(1 | 2)) => &[4, 36],
+        // version tag, state tag, then the authority
+        NONCE_ACCOUNTS_TABLE if data.get(4..8) == Some(&1u32.to_le_bytes()[..]) => &[8],
+        _ => &[],
+    };
+    offsets
+        .iter()
+        .filter_map(|offset| data.get(*offset..offset + 32)?.try_into().ok())
+        .collect()
+}
+
+// None for anything that isn't an initialized account of `table`
+pub fn parse_native(table: &str, data: &[u8]) -> Option<NativeState> {
+    match table {
+        STAKE_ACCOUNTS_TABLE => parse_stake(data),
+        VOTE_ACCOUNTS_TABLE => parse_vote(data),
+        NONCE_ACCOUNTS_TABLE => parse_nonce(data),
+        _ => None,
+    }
+}
+
+// epoch of a clock sysvar update
+pub fn clock_epoch(pubkey: &[u8], data: &[u8]) -> Option<u64> {
+    if pubkey != sysvar::clock::ID.as_ref() {
+        return None;
+    }
+    bincode::deserialize::<Clock>(data)
+        .ok()
+        .map(|clock| clock.epoch)
+}
+
+fn tag(data: &[u8]) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(..4)?.try_into().ok()?))
+}
+
+fn parse_stake(data: &[u8]) -> Option<NativeState> {
+    // the account is longer than the state, which bincode doesn't mind
+    let (meta, stake) = match bincode::deserialize::<StakeStateV2>(data).ok()? {
+        StakeStateV2::Initialized(meta) => (meta, None),
+        StakeStateV2::Stake(meta, stake, _) => (meta, Some(stake)),
+        StakeStateV2::Uninitialized | StakeStateV2::RewardsPool => return None,
+    };
+    let delegation = stake.map(|stake| stake.delegation);
+
+    Some(NativeState::Stake(StakeAccount {
+        staker: meta.authorized.staker.to_string(),
+        withdrawer: meta.authorized.withdrawer.to_string(),
+        rent_exempt_reserve: meta.rent_exempt_reserve,
+        lockup_unix_timestamp: meta.lockup.unix_timestamp,
+        lockup_epoch: meta.lockup.epoch,
+        lockup_custodian: optional_pubkey(meta.lockup.custodian),
+        voter: delegation.map(|delegation| delegation.voter_pubkey.to_string()),
+        delegated_stake: delegation.map(|delegation| delegation.stake),
+        // u64::MAX stands for "never": activated at genesis, or not deactivating
+        activation_epoch: delegation
+            .map(|delegation| delegation.activation_epoch)
+            .filter(|epoch| *epoch != u64::MAX),
+        deactivation_epoch: delegation
+            .map(|delegation| delegation.deactivation_epoch)
+            .filter(|epoch| *epoch != u64::MAX),
+        credits_observed: stake.map(|stake| stake.credits_observed),
+    }))
+}
+
+fn parse_vote(data: &[u8]) -> Option<NativeState> {
+    if !VoteStateVersions::is_correct_size_and_initialized(data) {
+        return None;
+    }
+    let vote = VoteState::deserialize(data).ok()?;
+
+    Some(NativeState::Vote(VoteAccount {
+        node_pubkey: vote.node_pubkey.to_string(),
+        authorized_withdrawer: vote.authorized_withdrawer.to_string(),
+        authorized_voter: vote
+            .authorized_voters()
+            .last()
+            .map(|(_, voter)| voter.to_string()),
+        commission: vote.commission,
+        credits: vote.credits(),
+        last_vote_slot: vote.last_voted_slot(),
+        root_slot: vote.root_slot,
+    }))
+}
+
+fn parse_nonce(data: &[u8]) -> Option<NativeState> {
+    let versions = bincode::deserialize::<NonceVersions>(data).ok()?;
+    let NonceState::Initialized(nonce) = versions.state() else {
+        return None;
+    };
+
+    Some(NativeState::Nonce(NonceAccount {
+        authority: nonce.authority.to_string(),
+        blockhash: nonce.blockhash().to_string(),
+        lamports_per_signature: nonce.get_lamports_per_signature(),
+    }))
+}
+
+// an unset lockup custodian is the default pubkey
+fn optional_pubkey(pubkey: Pubkey) -> Option<String> {
+    (pubkey != Pubkey::default()).then(|| pubkey.to_string())
+}
+
+impl NativeState {
+    pub fn table(&self) -> &'static str {
+        match self {
+            NativeState::Stake(_) => STAKE_ACCOUNTS_TABLE,
+            NativeState::Vote(_) => VOTE_ACCOUNTS_TABLE,
+            NativeState::Nonce(_) => NONCE_ACCOUNTS_TABLE,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::{
+        hash::Hash,
+        nonce::state::{Data as NonceData, DurableNonce},
+        stake::{
+            stake_flags::StakeFlags,
+            state::{Authorized, Delegation, Lockup, Meta, Stake},
+        },
+        vote::state::VoteInit,
+    };
+
+    fn stake_data(state: &StakeStateV2) -> Vec<u8> {
+        let mut data = bincode::serialize(state).unwrap();
+        data.resize(StakeStateV2::size_of(), 0);
+        data
+    }
+
+    fn vote_data(node: Pubkey, voter: Pubkey, withdrawer: Pubkey) -> Vec<u8> {
+        let vote = VoteState::new(
+            &VoteInit {
+                node_pubkey: node,
+                authorized_voter: voter,
+                authorized_withdrawer: withdrawer,
+                commission: 5,
+            },
+            &Clock::default(),
+        );
+        let mut data = vec![0; VoteState::size_of()];
+        VoteState::serialize(&VoteStateVersions::new_current(vote), &mut data).unwrap();
+        data
+    }
+
+    fn nonce_data(authority: Pubkey) -> Vec<u8> {
+        let nonce = NonceData::new(
+            authority,
+            DurableNonce::from_blockhash(&Hash::new_unique()),
+            5_000,
+        );
+        bincode::serialize(&NonceVersions::new(NonceState::Initialized(nonce))).unwrap()
+    }
+
+    #[test]
+    fn reads_stake_authorities_at_their_offsets() {
+        let (staker, withdrawer, voter) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let meta = Meta {
+            rent_exempt_reserve: 2_282_880,
+            authorized: Authorized { staker, withdrawer },
+            lockup: Lockup::default(),
+        };
+        let stake = Stake {
+            delegation: Delegation::new(&voter, 1_000_000, 7),
+            credits_observed: 42,
+        };
+        let initialized = stake_data(&StakeStateV2::Initialized(meta));
+        let delegated = stake_data(&StakeStateV2::Stake(meta, stake, StakeFlags::empty()));
+
+        for data in [&initialized, &delegated] {
+            assert_eq!(&data[12..44], staker.as_ref());
+            assert_eq!(&data[44..76], withdrawer.as_ref());
+            assert_eq!(
+                authorities(STAKE_ACCOUNTS_TABLE, data),
+                [staker.to_bytes(), withdrawer.to_bytes()]
+            );
+        }
+
+        let Some(NativeState::Stake(parsed)) = parse_native(STAKE_ACCOUNTS_TABLE, &delegated)
+        else {
+            panic!("expected a stake account");
+        };
+        assert_eq!(parsed.staker, staker.to_string());
+        assert_eq!(parsed.voter, Some(voter.to_string()));
+        assert_eq!(parsed.delegated_stake, Some(1_000_000));
+        assert_eq!(parsed.activation_epoch, Some(7));
+        assert_eq!(parsed.deactivation_epoch, None);
+        assert_eq!(parsed.lockup_custodian, None);
+
+        // uninitialized accounts have nobody in control
+        let uninitialized = stake_data(&StakeStateV2::Uninitialized);
+        assert!(authorities(STAKE_ACCOUNTS_TABLE, &uninitialized).is_empty());
+        assert!(parse_native(STAKE_ACCOUNTS_TABLE, &uninitialized).is_none());
+    }
+
+    #[test]
+    fn reads_vote_authorities_at_their_offsets() {
+        let (node, voter, withdrawer) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let data = vote_data(node, voter, withdrawer);
+
+        assert_eq!(&data[4..36], node.as_ref());
+        assert_eq!(&data[36..68], withdrawer.as_ref());
+        assert_eq!(
+            authorities(VOTE_ACCOUNTS_TABLE, &data),
+            [node.to_bytes(), withdrawer.to_bytes()]
+        );
+
+        let Some(NativeState::Vote(parsed)) = parse_native(VOTE_ACCOUNTS_TABLE, &data) else {
+            panic!("expected a vote account");
+        };
+        assert_eq!(parsed.node_pubkey, node.to_string());
+        assert_eq!(parsed.authorized_withdrawer, withdrawer.to_string());
+        assert_eq!(parsed.authorized_voter, Some(voter.to_string()));
+        assert_eq!(parsed.commission, 5);
+    }
+
+    #[test]
+    fn reads_the_nonce_authority_at_its_offset() {
+        let authority = Pubkey::new_unique();
+        let data = nonce_data(authority);
+
+        assert_eq!(data.len(), NONCE_ACCOUNT_LEN);
+        assert_eq!(&data[8..40], authority.as_ref());
+        assert_eq!(
+            authorities(NONCE_ACCOUNTS_TABLE, &data),
+            [authority.to_bytes()]
+        );
+
+        let Some(NativeState::Nonce(parsed)) = parse_native(NONCE_ACCOUNTS_TABLE, &data) else {
+            panic!("expected a nonce account");
+        };
+        assert_eq!(parsed.authority, authority.to_string());
+        assert_eq!(parsed.lamports_per_signature, 5_000);
+
+        let uninitialized =
+            bincode::serialize(&NonceVersions::new(NonceState::Uninitialized)).unwrap();
+        assert!(authorities(NONCE_ACCOUNTS_TABLE, &uninitialized).is_empty());
+        assert!(parse_native(NONCE_ACCOUNTS_TABLE, &uninitialized).is_none());
+    }
+
+    #[test]
+    fn rejects_truncated_data_and_wrong_lengths() {
+        let system = system_program::ID.as_ref();
+        assert_eq!(
+            native_table(system, NONCE_ACCOUNT_LEN),
+            Some(NONCE_ACCOUNTS_TABLE)
+        );
+        assert_eq!(native_table(system, NONCE_ACCOUNT_LEN - 1), None);
+        assert_eq!(native_table(system, 0), None);
+        assert_eq!(
+            native_table(stake::program::ID.as_ref(), 0),
+            Some(STAKE_ACCOUNTS_TABLE)
+        );
+        assert_eq!(native_table(Pubkey::new_unique().as_ref(), 80), None);
+
+        let stake = stake_data(&StakeStateV2::Initialized(Meta {
+            authorized: Authorized::auto(&Pubkey::new_unique()),
+            ..Meta::default()
+        }));
+        // a cut off authority isn't read, the one before it still is
+        assert_eq!(authorities(STAKE_ACCOUNTS_TABLE, &stake[..60]).len(), 1);
+        assert!(authorities(STAKE_ACCOUNTS_TABLE, &stake[..3]).is_empty());
+        assert!(parse_native(STAKE_ACCOUNTS_TABLE, &stake[..60]).is_none());
+
+        // vote accounts have to be exactly the size of the state
+        let mut vote = vote_data(
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        assert!(parse_native(VOTE_ACCOUNTS_TABLE, &vote[..vote.len() - 1]).is_none());
+        vote.push(0);
+        assert!(parse_native(VOTE_ACCOUNTS_TABLE, &vote).is_none());
+        assert_eq!(authorities(VOTE_ACCOUNTS_TABLE, &vote[..40]).len(), 1);
+
+        let nonce = nonce_data(Pubkey::new_unique());
+        assert!(parse_native(NONCE_ACCOUNTS_TABLE, &nonce[..40]).is_none());
+        assert!(authorities(NONCE_ACCOUNTS_TABLE, &nonce[..39]).is_empty());
+
+        // tables that aren't native are never read
+        assert!(authorities("listings", &stake).is_empty());
+        assert!(parse_native("listings", &stake).is_none());
+    }
+
+    #[test]
+    fn reads_the_epoch_of_the_clock_sysvar_only() {
+        let clock = bincode::serialize(&Clock {
+            epoch: 600,
+            ..Clock::default()
+        })
+        .unwrap();
+
+        assert_eq!(clock_epoch(sysvar::clock::ID.as_ref(), &clock), Some(600));
+        assert_eq!(clock_epoch(Pubkey::new_unique().as_ref(), &clock), None);
+        assert_eq!(clock_epoch(sysvar::clock::ID.as_ref(), &clock[..10]), None);
+    }
+}
@@ -0,0 +1,1742 @@
+use serde::{de::Error, Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use solana_geyser_plugin_interface::geyser_plugin_interface::SlotStatus;
+use sqlx::{Pool, Postgres};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{
+    runtime::Runtime,
+    sync::mpsc::{self, error::TrySendError},
+    task::JoinHandle,
+    time::{Instant, MissedTickBehavior},
+};
+
+use crate::{
+    config::{DatabaseConfig, PipelineConfig, QueueFullPolicy},
+    db::{self, Backoff},
+    idl::IdlAccount,
+    logging::{limited, sample},
+    metrics::{metrics, set_max, time_write},
+    models::{
+        AccountVersion, Block, Listing, MasterEdition, MintInfo, NativeAccount, NftMetadata,
+        ProgramEvent, SecurityEvent, TokenAuthorities, TokenBalance, Transaction,
+    },
+    native::{NONCE_ACCOUNTS_TABLE, STAKE_ACCOUNTS_TABLE, VOTE_ACCOUNTS_TABLE},
+    registry::{AccountRegistry, LISTINGS_TABLE},
+    reload::SharedTracked,
+    spool::Spool,
+    token,
+};
+
+#[derive(Debug)]
+pub enum WriteOp {
+    Listing {
+        account: String,
+        listing: Listing,
+        slot: u64,
+        write_version: u64,
+    },
+    IdlAccount {
+        account: String,
+        program_id: String,
+        idl_account: Arc<IdlAccount>,
+        data: Value,
+        slot: u64,
+        write_version: u64,
+    },
+    SolBalance {
+        user: String,
+        lamports: u64,
+        slot: u64,
+        write_version: u64,
+    },
+    // boxed, the balance and its three keys outweigh most ops
+    TokenHolding {
+        user: String,
+        token_account: String,
+        mint: String,
+        balance: Box<TokenBalance>,
+    },
+    Mint {
+        mint: String,
+        info: MintInfo,
+    },
+    // boxed, it's several times larger than any other op
+    NftMetadata {
+        mint: String,
+        metadata: Box<NftMetadata>,
+    },
+    MasterEdition {
+        mint: String,
+        edition: MasterEdition,
+    },
+    // boxed, the stake fields alone outweigh most ops
+    NativeAccount {
+        account: String,
+        native: Box<NativeAccount>,
+    },
+    // first time the clock reported `epoch`
+    Epoch {
+        epoch: u64,
+        slot: u64,
+    },
+    SlotStatus {
+        slot: u64,
+        parent: Option<u64>,
+        status: SlotStatus,
+    },
+    AccountClosed {
+        account: String,
+        table: String,
+        slot: u64,
+        write_version: u64,
+    },
+    Transaction(Transaction),
+    Event(ProgramEvent),
+    Block(Block),
+}
+
+impl WriteOp {
+    // updates sharing a key always land on the same worker, so they stay in order
+    fn hash_key<H: Hasher>(&self, state: &mut H) {
+        match self {
+            WriteOp::Listing { account, .. }
+            | WriteOp::IdlAccount { account, .. }
+            | WriteOp::NativeAccount { account, .. }
+            | WriteOp::AccountClosed { account, .. } => account.hash(state),
+            WriteOp::SolBalance { user, .. } | WriteOp::TokenHolding { user, .. } => {
+                user.hash(state)
+            }
+            WriteOp::Mint { mint, .. }
+            | WriteOp::NftMetadata { mint, .. }
+            | WriteOp::MasterEdition { mint, .. } => mint.hash(state),
+            WriteOp::Epoch { epoch, .. } => epoch.hash(state),
+            WriteOp::SlotStatus { slot, .. } => slot.hash(state),
+            WriteOp::Transaction(transaction) => transaction.signature.hash(state),
+            WriteOp::Event(event) => event.signature.hash(state),
+            WriteOp::Block(block) => block.slot.hash(state),
+        }
+    }
+}
+
+// bounded queue in front of background workers that coalesce and flush in batches
+#[derive(Debug)]
+pub struct WritePipeline {
+    senders: Vec<mpsc::Sender<WriteOp>>,
+    workers: Vec<JoinHandle<()>>,
+    policy: QueueFullPolicy,
+    dropped: AtomicU64,
+}
+
+impl WritePipeline {
+    // fails if the spool directory can't be opened
+    pub fn start(
+        runtime: &Runtime,
+        pool: Pool<Postgres>,
+        config: &PipelineConfig,
+        database: &DatabaseConfig,
+        tracked: SharedTracked,
+    ) -> std::io::Result<Self> {
+        let workers = config.workers.max(1);
+        let capacity = (config.queue_capacity / workers).max(1);
+        let batch_size = config.batch_size.max(1);
+        let flush_interval = Duration::from_millis(config.flush_interval_ms.max(1));
+
+        let mut senders = Vec::with_capacity(workers);
+        let mut handles = Vec::with_capacity(workers);
+        for index in 0..workers {
+            let spool = match &database.spool {
+                Some(spool) => Some(Spool::open(
+                    Path::new(&spool.path),
+                    index,
+                    workers,
+                    spool.max_bytes.map(|max_bytes| max_bytes / workers as u64),
+                )?),
+                None => None,
+            };
+            // batches left from the previous run are replayed before anything new
+            let held_back = spool.as_ref().is_some_and(|spool| !spool.is_empty());
+            if let Some(spool) = &spool {
+                metrics().spool_batches.add(spool.len() as i64);
+            }
+            let writer = Writer {
+                index,
+                pool: pool.clone(),
+                spool,
+                tracked: tracked.clone(),
+                backoff: Backoff::new(database),
+                retry_at: held_back.then(Instant::now),
+            };
+
+            let (tx, rx) = mpsc::channel(capacity);
+            senders.push(tx);
+            handles.push(runtime.spawn(run_worker(rx, writer, batch_size, flush_interval)));
+        }
+
+        Ok(WritePipeline {
+            senders,
+            workers: handles,
+            policy: config.queue_full_policy,
+            dropped: AtomicU64::new(0),
+        })
+    }
+
+    // called from validator threads, only waits when the policy is `block`
+    pub fn send(&self, op: WriteOp) {
+        let mut hasher = DefaultHasher::new();
+        op.hash_key(&mut hasher);
+        let index = hasher.finish() as usize % self.senders.len();
+        let sender = &self.senders[index];
+
+        match sender.try_send(op) {
+            Ok(()) => metrics()
+                .queue_depth
+                .with_label_values(&[&index.to_string()])
+                .set((sender.max_capacity() - sender.capacity()) as i64),
+            Err(TrySendError::Full(op)) => match self.policy {
+                QueueFullPolicy::Block => {
+                    if sender.blocking_send(op).is_err() {
+                        limited!(warn, "Write pipeline is shut down, update dropped");
+                    }
+                }
+                QueueFullPolicy::Drop => {
+                    metrics().queue_dropped.inc();
+                    let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                    if dropped % 10_000 == 1 {
+                        log::warn!("Write queue full, {} updates dropped so far", dropped);
+                    }
+                }
+            },
+            Err(TrySendError::Closed(_)) => {
+                limited!(warn, "Write pipeline is shut down, update dropped")
+            }
+        }
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    // closes the queues and waits for the workers to flush what's left
+    pub async fn shutdown(self) {
+        drop(self.senders);
+        for worker in self.workers {
+            if let Err(e) = worker.await {
+                log::error!("Write worker failed: {:?}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+impl WritePipeline {
+    // one queue without a worker, the caller reads it instead
+    pub fn unstarted(policy: QueueFullPolicy, capacity: usize) -> (Self, mpsc::Receiver<WriteOp>) {
+        let (tx, rx) = mpsc::channel(capacity);
+        let pipeline = WritePipeline {
+            senders: vec![tx],
+            workers: Vec::new(),
+            policy,
+            dropped: AtomicU64::new(0),
+        };
+        (pipeline, rx)
+    }
+}
+
+// balance and holding changes for one user
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UserUpdate {
+    // (value, slot, write version), the newest one wins
+    pub lamports: Option<(u64, u64, u64)>,
+    // every balance change, each one is kept in the history
+    #[serde(default)]
+    pub lamports_changes: Vec<(u64, u64, u64)>,
+    // token account -> (mint, balance). Holdings spooled before they were kept per token
+    // account have no account to be written to and are dropped
+    #[serde(default)]
+    pub holdings: HashMap<String, (String, TokenBalance)>,
+    // (token account, mint, balance) of every holding change, each one is kept in the
+    // history
+    #[serde(default)]
+    pub holding_changes: Vec<(String, String, TokenBalance)>,
+    pub slot: u64,
+}
+
+impl UserUpdate {
+    pub fn set_lamports(&mut self, lamports: u64, slot: u64, write_version: u64) {
+        self.lamports_changes.push((lamports, slot, write_version));
+        if self.lamports.is_none_or(|(_, old_slot, old_version)| {
+            (slot, write_version) > (old_slot, old_version)
+        }) {
+            self.lamports = Some((lamports, slot, write_version));
+        }
+        self.slot = self.slot.max(slot);
+    }
+
+    pub fn set_token(&mut self, token_account: String, mint: String, balance: TokenBalance) {
+        self.slot = self.slot.max(balance.slot);
+        self.holding_changes
+            .push((token_account.clone(), mint.clone(), balance.clone()));
+        let newer = self
+            .holdings
+            .get(&token_account)
+            .is_none_or(|(_, old)| balance.version() > old.version());
+        if newer {
+            self.holdings.insert(token_account, (mint, balance));
+        }
+    }
+}
+
+// rows that carry the (slot, write version) of the account update they came from
+trait Versioned {
+    fn version(&self) -> (u64, u64);
+}
+
+impl Versioned for TokenBalance {
+    fn version(&self) -> (u64, u64) {
+        (self.slot, self.write_version)
+    }
+}
+
+impl Versioned for MintInfo {
+    fn version(&self) -> (u64, u64) {
+        (self.slot, self.write_version)
+    }
+}
+
+impl Versioned for NftMetadata {
+    fn version(&self) -> (u64, u64) {
+        (self.slot, self.write_version)
+    }
+}
+
+impl Versioned for MasterEdition {
+    fn version(&self) -> (u64, u64) {
+        (self.slot, self.write_version)
+    }
+}
+
+impl Versioned for NativeAccount {
+    fn version(&self) -> (u64, u64) {
+        (self.slot, self.write_version)
+    }
+}
+
+fn insert_newest<V: Versioned>(map: &mut HashMap<String, V>, key: String, value: V) {
+    if map
+        .get(&key)
+        .is_none_or(|old| value.version() > old.version())
+    {
+        map.insert(key, value);
+    }
+}
+
+// balances and holdings of every user in `users`, each as one multi-row statement.
+// Returns the security events raised by the token account changes that were applied
+pub async fn write_user_updates(
+    pool: &Pool<Postgres>,
+    users: &HashMap<String, UserUpdate>,
+) -> Result<Vec<SecurityEvent>, sqlx::Error> {
+    let sol_balances: Vec<_> = users
+        .iter()
+        .flat_map(|(user, update)| {
+            // batches spooled before every change was kept only have the newest
+            let changes = if update.lamports_changes.is_empty() {
+                update.lamports.as_slice()
+            } else {
+                &update.lamports_changes
+            };
+            changes.iter().map(|(lamports, slot, write_version)| {
+                (user.as_str(), *lamports, *slot, *write_version)
+            })
+        })
+        .collect();
+    let token_balances: Vec<_> = users
+        .iter()
+        .flat_map(|(user, update)| {
+            update
+                .holdings
+                .iter()
+                .map(|(token_account, (mint, balance))| {
+                    (
+                        user.as_str(),
+                        token_account.as_str(),
+                        mint.as_str(),
+                        balance,
+                    )
+                })
+        })
+        .collect();
+
+    let holding_changes: Vec<_> = users
+        .iter()
+        .flat_map(|(user, update)| {
+            update
+                .holding_changes
+                .iter()
+                .map(|(token_account, mint, balance)| {
+                    (
+                        user.as_str(),
+                        token_account.as_str(),
+                        mint.as_str(),
+                        balance,
+                    )
+                })
+        })
+        .collect();
+
+    if !sol_balances.is_empty() {
+        db::upsert_sol_balances(pool, &sol_balances).await?;
+    }
+    if token_balances.is_empty() {
+        return Ok(Vec::new());
+    }
+    db::insert_token_account_history(pool, &holding_changes).await?;
+
+    let mut events = Vec::new();
+    for (wallet, token_account, previous, stored) in
+        db::upsert_token_balances(pool, &token_balances).await?
+    {
+        let Some(update) = users.get(&wallet) else {
+            continue;
+        };
+        let mut changes: Vec<_> = update
+            .holding_changes
+            .iter()
+            .filter(|(account, _, _)| *account == token_account)
+            .map(|(_, mint, balance)| (mint, balance))
+            .collect();
+        // batches spooled before every change was kept only have the newest
+        if changes.is_empty() {
+            changes.extend(
+                update
+                    .holdings
+                    .get(&token_account)
+                    .map(|(mint, balance)| (mint, balance)),
+            );
+        }
+        events.extend(holding_events(&wallet, previous, stored, changes));
+    }
+
+    Ok(events)
+}
+
+// security events of one token account, each change compared against the one before it
+// starting from the stored authorities, so a change undone within one batch still raises
+// them. Changes the stored row is as new as were checked before. A close raises nothing
+// and leaves an account without authorities behind
+fn holding_events(
+    wallet: &str,
+    mut previous: TokenAuthorities,
+    stored: Option<(u64, u64)>,
+    mut changes: Vec<(&String, &TokenBalance)>,
+) -> Vec<SecurityEvent> {
+    changes.sort_by_key(|(_, balance)| balance.version());
+    changes.dedup_by_key(|(_, balance)| balance.version());
+
+    let mut events = Vec::new();
+    for (mint, balance) in changes {
+        if stored.is_some_and(|stored| balance.version() <= stored) {
+            continue;
+        }
+        let Some(authorities) = &balance.authorities else {
+            previous = TokenAuthorities::default();
+            continue;
+        };
+        for (event, details) in token::security_events(&previous, authorities) {
+            events.push(SecurityEvent {
+                wallet: wallet.to_string(),
+                mint: mint.clone(),
+                event,
+                details,
+                slot: balance.slot,
+            });
+        }
+        previous = authorities.clone();
+    }
+    events
+}
+
+// account -> (program id, decoded data, slot, write version)
+type IdlRows = HashMap<String, (String, Value, u64, u64)>;
+
+// slot -> (parent, status)
+type SlotStatuses = HashMap<u64, (Option<u64>, &'static str)>;
+
+#[derive(Debug)]
+struct IdlBatch {
+    idl_account: Arc<IdlAccount>,
+    rows: IdlRows,
+}
+
+// pending writes, coalesced so only the latest state per key is flushed
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Batch {
+    listings: HashMap<String, (Listing, u64, u64)>,
+    // spooled separately, see `SpooledBatch`
+    #[serde(skip)]
+    idl_accounts: HashMap<String, IdlBatch>,
+    users: HashMap<String, UserUpdate>,
+    mints: HashMap<String, MintInfo>,
+    nft_metadata: HashMap<String, NftMetadata>,
+    master_editions: HashMap<String, MasterEdition>,
+    native_accounts: HashMap<String, NativeAccount>,
+    // epoch -> first slot it was seen at
+    epochs: HashMap<u64, u64>,
+    #[serde(deserialize_with = "slot_statuses")]
+    slots: SlotStatuses,
+    transactions: Vec<Transaction>,
+    events: Vec<ProgramEvent>,
+    blocks: HashMap<u64, Block>,
+    // table -> account -> (slot, write version) the account was closed at
+    closed: HashMap<String, HashMap<String, (u64, u64)>>,
+    // every listing and IDL account write and close, before coalescing
+    #[serde(default)]
+    versions: Vec<AccountVersion>,
+}
+
+// a batch as it's written to the spool. IDL rows are kept by table and go back to the
+// table's current account type when they're replayed
+#[derive(Debug, Serialize, Deserialize)]
+struct SpooledBatch {
+    batch: Batch,
+    idl_rows: HashMap<String, IdlRows>,
+}
+
+impl From<Batch> for SpooledBatch {
+    fn from(mut batch: Batch) -> Self {
+        let idl_rows = std::mem::take(&mut batch.idl_accounts)
+            .into_iter()
+            .map(|(table, idl_batch)| (table, idl_batch.rows))
+            .collect();
+        SpooledBatch { batch, idl_rows }
+    }
+}
+
+impl SpooledBatch {
+    // rows of tables no program is decoded into anymore are dropped
+    fn into_batch(self, registry: &AccountRegistry) -> Batch {
+        let mut batch = self.batch;
+        for (table, rows) in self.idl_rows {
+            match registry.idl_account(&table) {
+                Some(idl_account) => {
+                    batch.idl_accounts.insert(
+                        table,
+                        IdlBatch {
+                            idl_account: idl_account.clone(),
+                            rows,
+                        },
+                    );
+                }
+                None => log::warn!(
+                    "Dropped {} spooled rows of {}, no tracked program is stored there",
+                    rows.len(),
+                    table
+                ),
+            }
+        }
+        batch
+    }
+}
+
+// statuses are kept as `SlotStatus::as_str`
+fn slot_statuses<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SlotStatuses, D::Error> {
+    let statuses = [
+        SlotStatus::Processed,
+        SlotStatus::Confirmed,
+        SlotStatus::Rooted,
+    ];
+    HashMap::<u64, (Option<u64>, String)>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(slot, (parent, name))| {
+            let status = statuses
+                .iter()
+                .map(SlotStatus::as_str)
+                .find(|status| *status == name)
+                .ok_or_else(|| D::Error::custom(format!("Unknown slot status {}", name)))?;
+            Ok((slot, (parent, status)))
+        })
+        .collect()
+}
+
+// a listings row as JSON, u128 amounts as strings since they don't fit a JSON number
+fn listing_version(account: &str, listing: &Listing, slot: u64, write_version: u64) -> Value {
+    serde_json::json!({
+        "account": account,
+        "name": listing.name,
+        "seed": listing.seed,
+        "mint": listing.mint,
+        "funding_goal": listing.funding_goal,
+        "pool_mint_supply": listing.pool_mint_supply.to_string(),
+        "funding_raised": listing.funding_raised,
+        "available_tokens": listing.available_tokens.to_string(),
+        "base_price": listing.base_price,
+        "tokens_sold": listing.tokens_sold.to_string(),
+        "bump": listing.bump,
+        "vault_bump": listing.vault_bump,
+        "mint_bump": listing.mint_bump,
+        "slot": slot,
+        "write_version": write_version,
+    })
+}
+
+fn status_rank(status: &str) -> u8 {
+    match status {
+        "rooted" => 2,
+        "confirmed" => 1,
+        _ => 0,
+    }
+}
+
+impl Batch {
+    fn push(&mut self, op: WriteOp) {
+        match op {
+            WriteOp::Listing {
+                account,
+                listing,
+                slot,
+                write_version,
+            } => {
+                self.versions.push(AccountVersion {
+                    table: LISTINGS_TABLE.to_string(),
+                    account: account.clone(),
+                    slot,
+                    write_version,
+                    data: Some(listing_version(&account, &listing, slot, write_version)),
+                });
+                let version = (slot, write_version);
+                let newer = self
+                    .listings
+                    .get(&account)
+                    .is_none_or(|(_, old_slot, old_version)| version > (*old_slot, *old_version));
+                if newer && self.reopen(LISTINGS_TABLE, &account, version) {
+                    self.listings
+                        .insert(account, (listing, slot, write_version));
+                }
+            }
+            WriteOp::IdlAccount {
+                account,
+                program_id,
+                idl_account,
+                data,
+                slot,
+                write_version,
+            } => {
+                self.versions.push(AccountVersion {
+                    table: idl_account.table.clone(),
+                    account: account.clone(),
+                    slot,
+                    write_version,
+                    data: Some(serde_json::json!({ "program_id": program_id, "data": data })),
+                });
+                let version = (slot, write_version);
+                if !self.reopen(&idl_account.table, &account, version) {
+                    return;
+                }
+                let rows = &mut self
+                    .idl_accounts
+                    .entry(idl_account.table.clone())
+                    .or_insert_with(|| IdlBatch {
+                        idl_account,
+                        rows: HashMap::new(),
+                    })
+                    .rows;
+                let newer = rows
+                    .get(&account)
+                    .is_none_or(|(_, _, old_slot, old_version)| {
+                        version > (*old_slot, *old_version)
+                    });
+                if newer {
+                    rows.insert(account, (program_id, data, slot, write_version));
+                }
+            }
+            WriteOp::SolBalance {
+                user,
+                lamports,
+                slot,
+                write_version,
+            } => self
+                .users
+                .entry(user)
+                .or_default()
+                .set_lamports(lamports, slot, write_version),
+            WriteOp::TokenHolding {
+                user,
+                token_account,
+                mint,
+                balance,
+            } => self
+                .users
+                .entry(user)
+                .or_default()
+                .set_token(token_account, mint, *balance),
+            WriteOp::Mint { mint, info } => insert_newest(&mut self.mints, mint, info),
+            WriteOp::NftMetadata { mint, metadata } => {
+                insert_newest(&mut self.nft_metadata, mint, *metadata)
+            }
+            WriteOp::MasterEdition { mint, edition } => {
+                insert_newest(&mut self.master_editions, mint, edition)
+            }
+            WriteOp::NativeAccount { account, native } => {
+                if self.reopen(native.state.table(), &account, native.version()) {
+                    insert_newest(&mut self.native_accounts, account, *native);
+                }
+            }
+            WriteOp::Epoch { epoch, slot } => {
+                let first = self.epochs.entry(epoch).or_insert(slot);
+                *first = (*first).min(slot);
+            }
+            WriteOp::SlotStatus {
+                slot,
+                parent,
+                status,
+            } => {
+                let entry = self.slots.entry(slot).or_insert((parent, status.as_str()));
+                if status_rank(status.as_str()) >= status_rank(entry.1) {
+                    *entry = (parent.or(entry.0), status.as_str());
+                }
+            }
+            WriteOp::AccountClosed {
+                account,
+                table,
+                slot,
+                write_version,
+            } => {
+                // native accounts are only served as they are now
+                if ![
+                    STAKE_ACCOUNTS_TABLE,
+                    VOTE_ACCOUNTS_TABLE,
+                    NONCE_ACCOUNTS_TABLE,
+                ]
+                .contains(&table.as_str())
+                {
+                    self.versions.push(AccountVersion {
+                        table: table.clone(),
+                        account: account.clone(),
+                        slot,
+                        write_version,
+                        data: None,
+                    });
+                }
+
+                // a close drops any older write still pending for the account, and loses
+                // to a newer one (the account was re-created)
+                let version = (slot, write_version);
+                let native = self
+                    .native_accounts
+                    .get(&account)
+                    .filter(|native| native.state.table() == table);
+                let pending = if table == LISTINGS_TABLE {
+                    self.listings
+                        .get(&account)
+                        .map(|(_, slot, write_version)| (*slot, *write_version))
+                } else if let Some(native) = native {
+                    Some(native.version())
+                } else {
+                    self.idl_accounts
+                        .get(&table)
+                        .and_then(|batch| batch.rows.get(&account))
+                        .map(|(_, _, slot, write_version)| (*slot, *write_version))
+                };
+                if pending.is_some_and(|pending| pending > version) {
+                    return;
+                }
+
+                if table == LISTINGS_TABLE {
+                    self.listings.remove(&account);
+                } else if native.is_some() {
+                    self.native_accounts.remove(&account);
+                } else if let Some(batch) = self.idl_accounts.get_mut(&table) {
+                    batch.rows.remove(&account);
+                }
+                let closed = self.closed.entry(table).or_default();
+                if closed.get(&account).is_none_or(|closed| version > *closed) {
+                    closed.insert(account, version);
+                }
+            }
+            WriteOp::Transaction(transaction) => self.transactions.push(transaction),
+            WriteOp::Event(event) => self.events.push(event),
+            WriteOp::Block(block) => {
+                self.blocks.insert(block.slot, block);
+            }
+        }
+    }
+
+    // a write newer than a pending close means the account was re-created: the close is
+    // dropped and the write goes through; false when the close is newer
+    fn reopen(&mut self, table: &str, account: &str, version: (u64, u64)) -> bool {
+        let Some(accounts) = self.closed.get_mut(table) else {
+            return true;
+        };
+        match accounts.get(account) {
+            Some(closed) if *closed > version => false,
+            Some(_) => {
+                accounts.remove(account);
+                true
+            }
+            None => true,
+        }
+    }
+
+    // rows the flush writes: the coalesced keys, plus the versions and history entries
+    // kept for every write, so a hot account still fills the batch
+    fn len(&self) -> usize {
+        self.listings.len()
+            + self
+                .idl_accounts
+                .values()
+                .map(|batch| batch.rows.len())
+                .sum::<usize>()
+            + self.users.len()
+            + self.mints.len()
+            + self.nft_metadata.len()
+            + self.master_editions.len()
+            + self.native_accounts.len()
+            + self.epochs.len()
+            + self.slots.len()
+            + self.transactions.len()
+            + self.events.len()
+            + self.blocks.len()
+            + self.closed.values().map(HashMap::len).sum::<usize>()
+            + self.versions.len()
+            + self
+                .users
+                .values()
+                .map(|update| update.lamports_changes.len() + update.holding_changes.len())
+                .sum::<usize>()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // highest slot of anything in the batch, logged with its errors
+    fn slot(&self) -> u64 {
+        let listings = self.listings.values().map(|(_, slot, _)| *slot);
+        let idl_accounts = self
+            .idl_accounts
+            .values()
+            .flat_map(|batch| batch.rows.values().map(|(_, _, slot, _)| *slot));
+        let users = self.users.values().map(|update| update.slot);
+        let mints = self.mints.values().map(|mint| mint.slot);
+        let nft_metadata = self.nft_metadata.values().map(|metadata| metadata.slot);
+        let master_editions = self.master_editions.values().map(|edition| edition.slot);
+        let native_accounts = self.native_accounts.values().map(|native| native.slot);
+        let transactions = self.transactions.iter().map(|transaction| transaction.slot);
+        let events = self.events.iter().map(|event| event.slot);
+        let closed = self
+            .closed
+            .values()
+            .flat_map(|accounts| accounts.values().map(|(slot, _)| *slot));
+
+        listings
+            .chain(idl_accounts)
+            .chain(users)
+            .chain(mints)
+            .chain(nft_metadata)
+            .chain(master_editions)
+            .chain(native_accounts)
+            .chain(self.epochs.values().copied())
+            .chain(self.slots.keys().copied())
+            .chain(transactions)
+            .chain(events)
+            .chain(self.blocks.keys().copied())
+            .chain(closed)
+            .max()
+            .unwrap_or_default()
+    }
+
+    // failed writes are logged and dropped, except when the database is unreachable:
+    // then the flush stops and the whole batch is retried later. Everything in it is
+    // versioned or idempotent, so writing what already went through again is harmless
+    async fn flush(&self, pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+        let mut notifications = Vec::with_capacity(self.len());
+        let slot = self.slot();
+
+        if !self.versions.is_empty() {
+            if let Err(e) = available(
+                time_write(
+                    "account_versions",
+                    db::insert_account_versions(pool, &self.versions),
+                )
+                .await,
+            )? {
+                limited!(
+                    error,
+                    accounts = sample(self.versions.iter().map(|version| &version.account))
+                        .as_str(),
+                    slot;
+                    "Error inserting {} account versions: {:?}",
+                    self.versions.len(),
+                    e
+                );
+            }
+        }
+
+        if !self.listings.is_empty() {
+            match available(
+                time_write("listings", db::upsert_listings(pool, &self.listings)).await,
+            )? {
+                Ok(()) => {
+                    notifications.extend(self.listings.iter().map(|(account, (_, slot, _))| {
+                        let payload = serde_json::json!({
+                            "account": account,
+                            "action": "account_update",
+                            "slot": slot,
+                        });
+                        ("account_updates", payload.to_string())
+                    }))
+                }
+                Err(e) => limited!(
+                    error,
+                    accounts = sample(self.listings.keys()).as_str(),
+                    slot;
+                    "Error inserting/updating {} listings: {:?}",
+                    self.listings.len(),
+                    e
+                ),
+            }
+        }
+
+        // from every version rather than `listings`, which only keeps the newest per account
+        let listing_versions: Vec<&AccountVersion> = self
+            .versions
+            .iter()
+            .filter(|version| version.table == LISTINGS_TABLE && version.data.is_some())
+            .collect();
+        if !listing_versions.is_empty() {
+            if let Err(e) = available(
+                time_write(
+                    "listing_history",
+                    db::insert_listing_history(pool, &listing_versions),
+                )
+                .await,
+            )? {
+                limited!(
+                    error,
+                    accounts = sample(listing_versions.iter().map(|version| &version.account))
+                        .as_str(),
+                    slot;
+                    "Error inserting {} listing history rows: {:?}",
+                    listing_versions.len(),
+                    e
+                );
+            }
+        }
+
+        for batch in self.idl_accounts.values() {
+            let idl_account = &batch.idl_account;
+            match available(
+                time_write(
+                    "idl_accounts",
+                    db::upsert_idl_accounts(pool, idl_account, &batch.rows),
+                )
+                .await,
+            )? {
+                Ok(()) => {
+                    notifications.extend(batch.rows.iter().map(|(account, (_, _, slot, _))| {
+                        let payload = serde_json::json!({
+                            "account": account,
+                            "action": "idl_account_update",
+                            "account_type": idl_account.name,
+                            "table": idl_account.table,
+                            "slot": slot,
+                        });
+                        ("account_updates", payload.to_string())
+                    }))
+                }
+                Err(e) => limited!(
+                    error,
+                    accounts = sample(batch.rows.keys()).as_str(),
+                    slot;
+                    "Error inserting/updating {} {} accounts: {:?}",
+                    batch.rows.len(),
+                    idl_account.name,
+                    e
+                ),
+            }
+        }
+
+        for (table, accounts) in &self.closed {
+            if accounts.is_empty() {
+                continue;
+            }
+
+            match available(
+                time_write(
+                    "closed_accounts",
+                    db::delete_accounts(pool, table, accounts),
+                )
+                .await,
+            )? {
+                Ok(deleted) => notifications.extend(deleted.into_iter().map(|account| {
+                    let payload = serde_json::json!({
+                        "account": account,
+                        "action": "account_closed",
+                        "table": table,
+                        "slot": accounts.get(&account).map(|(slot, _)| slot),
+                    });
+                    ("account_updates", payload.to_string())
+                })),
+                Err(e) => limited!(
+                    error,
+                    accounts = sample(accounts.keys()).as_str(),
+                    slot;
+                    "Error deleting {} closed accounts from {}: {:?}",
+                    accounts.len(),
+                    table,
+                    e
+                ),
+            }
+        }
+
+        if !self.users.is_empty() {
+            match available(time_write("users", write_user_updates(pool, &self.users)).await)? {
+                Ok(events) => {
+                    notifications.extend(self.users.iter().map(|(user, update)| {
+                        let payload = serde_json::json!({
+                            "account": user,
+                            "action": "user_update",
+                            "slot": update.slot,
+                        });
+                        ("user_updates", payload.to_string())
+                    }));
+
+                    if !events.is_empty() {
+                        match available(
+                            time_write(
+                                "security_events",
+                                db::insert_security_events(pool, &events),
+                            )
+                            .await,
+                        )? {
+                            Ok(()) => notifications.extend(events.iter().map(|event| {
+                                let payload = serde_json::json!({
+                                    "wallet": event.wallet,
+                                    "mint": event.mint,
+                                    "event": event.event,
+                                    "details": event.details,
+                                    "slot": event.slot,
+                                });
+                                ("security_events", payload.to_string())
+                            })),
+                            Err(e) => limited!(
+                                error,
+                                wallets = sample(events.iter().map(|event| &event.wallet)).as_str(),
+                                slot;
+                                "Error inserting {} security events: {:?}",
+                                events.len(),
+                                e
+                            ),
+                        }
+                    }
+                }
+                Err(e) => limited!(
+                    error,
+                    wallets = sample(self.users.keys()).as_str(),
+                    slot;
+                    "Error updating {} users: {:?}",
+                    self.users.len(),
+                    e
+                ),
+            }
+        }
+
+        if !self.mints.is_empty() {
+            if let Err(e) =
+                available(time_write("mints", db::upsert_mints(pool, &self.mints)).await)?
+            {
+                limited!(
+                    error,
+                    mints = sample(self.mints.keys()).as_str(),
+                    slot;
+                    "Error updating {} mints: {:?}",
+                    self.mints.len(),
+                    e
+                );
+            }
+        }
+
+        if !self.nft_metadata.is_empty() {
+            if let Err(e) = available(
+                time_write(
+                    "nft_metadata",
+                    db::upsert_nft_metadata(pool, &self.nft_metadata),
+                )
+                .await,
+            )? {
+                limited!(
+                    error,
+                    mints = sample(self.nft_metadata.keys()).as_str(),
+                    slot;
+                    "Error updating {} NFT metadata accounts: {:?}",
+                    self.nft_metadata.len(),
+                    e
+                );
+            }
+        }
+
+        if !self.master_editions.is_empty() {
+            if let Err(e) = available(
+                time_write(
+                    "master_editions",
+                    db::upsert_master_editions(pool, &self.master_editions),
+                )
+                .await,
+            )? {
+                limited!(
+                    error,
+                    mints = sample(self.master_editions.keys()).as_str(),
+                    slot;
+                    "Error updating {} master editions: {:?}",
+                    self.master_editions.len(),
+                    e
+                );
+            }
+        }
+
+        if !self.native_accounts.is_empty() {
+            match available(
+                time_write(
+                    "native_accounts",
+                    db::upsert_native_accounts(pool, &self.native_accounts),
+                )
+                .await,
+            )? {
+                Ok(()) => {
+                    // one notification per wallet, with the newest slot of its accounts
+                    let mut wallets = HashMap::new();
+                    for native in self.native_accounts.values() {
+                        for wallet in &native.wallets {
+                            let slot = wallets.entry(wallet).or_insert(native.slot);
+                            *slot = native.slot.max(*slot);
+                        }
+                    }
+                    notifications.extend(wallets.into_iter().map(|(wallet, slot)| {
+                        let payload = serde_json::json!({
+                            "account": wallet,
+                            "action": "user_update",
+                            "slot": slot,
+                        });
+                        ("user_updates", payload.to_string())
+                    }));
+                }
+                Err(e) => limited!(
+                    error,
+                    accounts = sample(self.native_accounts.keys()).as_str(),
+                    slot;
+                    "Error updating {} native accounts: {:?}",
+                    self.native_accounts.len(),
+                    e
+                ),
+            }
+        }
+
+        if !self.epochs.is_empty() {
+            if let Err(e) =
+                available(time_write("epochs", db::upsert_epochs(pool, &self.epochs)).await)?
+            {
+                limited!(
+                    error,
+                    slot;
+                    "Error updating {} epochs: {:?}",
+                    self.epochs.len(),
+                    e
+                );
+            }
+        }
+
+        if !self.slots.is_empty() {
+            match available(time_write("slots", db::upsert_slots(pool, &self.slots)).await)? {
+                Ok(()) => {
+                    if let Some(slot) = self.slots.keys().max() {
+                        set_max(&metrics().written_slot, *slot);
+                    }
+                    notifications.extend(self.slots.iter().map(|(slot, (_, status))| {
+                        let payload = serde_json::json!({
+                            "slot": slot,
+                            "status": status,
+                        });
+                        ("slot_updates", payload.to_string())
+                    }));
+
+                    let rooted: Vec<i64> = self
+                        .slots
+                        .iter()
+                        .filter(|(_, (_, status))| *status == "rooted")
+                        .map(|(slot, _)| *slot as i64)
+                        .collect();
+                    if !rooted.is_empty() {
+                        if let Err(e) = available(
+                            time_write(
+                                "account_versions",
+                                db::prune_account_versions(pool, &rooted),
+                            )
+                            .await,
+                        )? {
+                            limited!(
+                                error,
+                                slot;
+                                "Error pruning account versions: {:?}",
+                                e
+                            );
+                        }
+                    }
+                }
+                Err(e) => limited!(
+                    error,
+                    slot;
+                    "Error updating {} slots: {:?}",
+                    self.slots.len(),
+                    e
+                ),
+            }
+        }
+
+        if !self.transactions.is_empty() {
+            if let Err(e) = available(
+                time_write(
+                    "transactions",
+                    db::insert_transactions(pool, &self.transactions),
+                )
+                .await,
+            )? {
+                limited!(
+                    error,
+                    signatures =
+                        sample(self.transactions.iter().map(|transaction| &transaction.signature))
+                            .as_str(),
+                    slot;
+                    "Error inserting {} transactions: {:?}",
+                    self.transactions.len(),
+                    e
+                );
+            }
+        }
+
+        if !self.events.is_empty() {
+            match available(time_write("events", db::insert_events(pool, &self.events)).await)? {
+                Ok(()) => {
+                    // one notification per transaction, the stream reads all of its events
+                    let transactions: HashSet<(&str, u64)> = self
+                        .events
+                        .iter()
+                        .map(|event| (event.signature.as_str(), event.slot))
+                        .collect();
+                    notifications.extend(transactions.into_iter().map(|(signature, slot)| {
+                        let payload = serde_json::json!({
+                            "account": signature,
+                            "action": "program_events",
+                            "slot": slot,
+                        });
+                        ("account_updates", payload.to_string())
+                    }));
+                }
+                Err(e) => limited!(
+                    error,
+                    signatures = sample(self.events.iter().map(|event| &event.signature)).as_str(),
+                    slot;
+                    "Error inserting {} events: {:?}",
+                    self.events.len(),
+                    e
+                ),
+            }
+        }
+
+        if !self.blocks.is_empty() {
+            if let Err(e) =
+                available(time_write("blocks", db::upsert_blocks(pool, &self.blocks)).await)?
+            {
+                limited!(
+                    error,
+                    slot;
+                    "Error updating {} blocks: {:?}",
+                    self.blocks.len(),
+                    e
+                );
+            }
+        }
+
+        if let Err(e) = time_write("notify", db::notify(pool, &notifications)).await {
+            metrics().notify_failures.inc();
+            limited!(
+                error,
+                slot;
+                "Failed to send {} update notifications: {:?}",
+                notifications.len(),
+                e
+            );
+        }
+        Ok(())
+    }
+}
+
+// the error back as a failed write, unless it means the database is unreachable
+fn available<T>(result: Result<T, sqlx::Error>) -> Result<Result<T, sqlx::Error>, sqlx::Error> {
+    match result {
+        Err(e) if db::is_unavailable(&e) => Err(e),
+        result => Ok(result),
+    }
+}
+
+// writes one worker's batches, or holds them back while the database is unreachable
+#[derive(Debug)]
+struct Writer {
+    index: usize,
+    pool: Pool<Postgres>,
+    spool: Option<Spool>,
+    tracked: SharedTracked,
+    backoff: Backoff,
+    // set while the database is unreachable, when to try it again
+    retry_at: Option<Instant>,
+}
+
+impl Writer {
+    // batches queue up behind the ones held back, so updates are written in order
+    async fn write(&mut self, batch: Batch) {
+        if self.retry_at.is_none() {
+            let Err(e) = batch.flush(&self.pool).await else {
+                return;
+            };
+            let delay = self.backoff.next();
+            log::warn!(
+                worker = self.index;
+                "Database unreachable, holding back writes and retrying in {:?}: {}",
+                delay,
+                e
+            );
+            self.retry_at = Some(Instant::now() + delay);
+        }
+        self.hold(batch);
+    }
+
+    fn hold(&mut self, batch: Batch) {
+        let updates = batch.len();
+        let slot = batch.slot();
+        let Some(spool) = self.spool.as_mut() else {
+            metrics().spool_dropped.inc();
+            limited!(
+                error,
+                slot;
+                "Database unreachable and no spool configured, dropped {} updates",
+                updates
+            );
+            return;
+        };
+
+        match spool.push(&SpooledBatch::from(batch)) {
+            Ok(true) => metrics().spool_batches.inc(),
+            Ok(false) => {
+                metrics().spool_dropped.inc();
+                limited!(error, slot; "Spool is full, dropped {} updates", updates);
+            }
+            Err(e) => {
+                metrics().spool_dropped.inc();
+                limited!(error, slot; "Error spooling {} updates: {:?}", updates, e);
+            }
+        }
+    }
+
+    // once the backoff has passed, checks the database and replays the spool oldest
+    // first, stopping at the first batch that fails again
+    async fn retry(&mut self) {
+        if self
+            .retry_at
+            .is_none_or(|retry_at| Instant::now() < retry_at)
+        {
+            return;
+        }
+
+        let mut replayed = 0;
+        let result = match sqlx::query("SELECT 1").execute(&self.pool).await {
+            Ok(_) => self.replay(&mut replayed).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            let delay = self.backoff.next();
+            limited!(
+                warn,
+                worker = self.index;
+                "Database still unreachable, retrying in {:?}: {}",
+                delay,
+                e
+            );
+            self.retry_at = Some(Instant::now() + delay);
+            return;
+        }
+
+        log::info!(
+            worker = self.index;
+            "Database reachable again, replayed {} spooled batches",
+            replayed
+        );
+        self.backoff.reset();
+        self.retry_at = None;
+    }
+
+    async fn replay(&mut self, replayed: &mut usize) -> Result<(), sqlx::Error> {
+        let Some(spool) = self.spool.as_mut() else {
+            return Ok(());
+        };
+        loop {
+            let batch = match spool.peek::<SpooledBatch>() {
+                Ok(Some(batch)) => batch.into_batch(&self.tracked.get().registry),
+                Ok(None) => return Ok(()),
+                Err(e) => {
+                    log::error!("Dropped unreadable spooled batch: {:?}", e);
+                    Batch::default()
+                }
+            };
+            batch.flush(&self.pool).await?;
+
+            if let Err(e) = spool.pop() {
+                log::error!("Error removing replayed batch from the spool: {:?}", e);
+            }
+            metrics().spool_batches.dec();
+            *replayed += 1;
+        }
+    }
+}
+
+async fn run_worker(
+    mut rx: mpsc::Receiver<WriteOp>,
+    mut writer: Writer,
+    batch_size: usize,
+    flush_interval: Duration,
+) {
+    let mut batch = Batch::default();
+    let mut ticker = tokio::time::interval(flush_interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let queue_depth = metrics()
+        .queue_depth
+        .with_label_values(&[&writer.index.to_string()]);
+
+    loop {
+        tokio::select! {
+            op = rx.recv() => match op {
+                Some(op) => {
+                    batch.push(op);
+                    if batch.len() >= batch_size {
+                        writer.write(std::mem::take(&mut batch)).await;
+                        queue_depth.set(rx.len() as i64);
+                    }
+                }
+                None => break,
+            },
+            _ = ticker.tick() => {
+                writer.retry().await;
+                if !batch.is_empty() {
+                    writer.write(std::mem::take(&mut batch)).await;
+                }
+                queue_depth.set(rx.len() as i64);
+            }
+        }
+    }
+
+    // whatever is still held back stays in the spool for the next run
+    if !batch.is_empty() {
+        writer.write(batch).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::postgres::PgPoolOptions;
+    use std::{env, fs, process};
+    use tokio::runtime::Handle;
+
+    use crate::{backfill::Backfill, config::RpcConfig};
+
+    fn listing(account: &str, funding_raised: u64, slot: u64, write_version: u64) -> WriteOp {
+        WriteOp::Listing {
+            account: account.to_string(),
+            listing: Listing {
+                name: "listing".to_string(),
+                seed: 1,
+                mint: "mint".to_string(),
+                funding_goal: 100,
+                pool_mint_supply: 1,
+                funding_raised,
+                available_tokens: 1,
+                base_price: 1.0,
+                tokens_sold: 0,
+                bump: 0,
+                vault_bump: 0,
+                mint_bump: 0,
+            },
+            slot,
+            write_version,
+        }
+    }
+
+    fn closed(account: &str, slot: u64, write_version: u64) -> WriteOp {
+        WriteOp::AccountClosed {
+            account: account.to_string(),
+            table: LISTINGS_TABLE.to_string(),
+            slot,
+            write_version,
+        }
+    }
+
+    fn pending(batch: &Batch, account: &str) -> Option<(u64, u64, u64)> {
+        batch
+            .listings
+            .get(account)
+            .map(|(listing, slot, write_version)| (listing.funding_raised, *slot, *write_version))
+    }
+
+    #[test]
+    fn keeps_the_newest_write() {
+        let mut batch = Batch::default();
+        batch.push(listing("a", 1, 5, 1));
+        batch.push(listing("a", 2, 5, 3));
+        batch.push(listing("a", 3, 5, 2));
+        batch.push(listing("a", 4, 4, 9));
+        assert_eq!(pending(&batch, "a"), Some((2, 5, 3)));
+        // every write is still kept as a version, and counts toward the batch size
+        assert_eq!(batch.versions.len(), 4);
+        assert_eq!(batch.len(), 5);
+    }
+
+    #[test]
+    fn reopens_closed_accounts() {
+        let mut batch = Batch::default();
+        batch.push(listing("a", 1, 5, 0));
+        batch.push(closed("a", 6, 0));
+        assert_eq!(pending(&batch, "a"), None);
+        assert_eq!(batch.closed[LISTINGS_TABLE]["a"], (6, 0));
+
+        // re-created after the close
+        batch.push(listing("a", 2, 7, 0));
+        assert_eq!(pending(&batch, "a"), Some((2, 7, 0)));
+        assert!(batch.closed[LISTINGS_TABLE].is_empty());
+    }
+
+    #[test]
+    fn ignores_older_writes_that_arrive_late() {
+        let mut batch = Batch::default();
+        batch.push(listing("a", 2, 7, 0));
+        batch.push(listing("a", 1, 6, 0));
+        assert_eq!(pending(&batch, "a"), Some((2, 7, 0)));
+
+        // neither reopens a closed account nor overrides a newer write
+        batch.push(closed("b", 6, 0));
+        batch.push(listing("b", 1, 5, 0));
+        assert_eq!(pending(&batch, "b"), None);
+        assert_eq!(batch.closed[LISTINGS_TABLE]["b"], (6, 0));
+
+        batch.push(closed("a", 6, 5));
+        assert_eq!(pending(&batch, "a"), Some((2, 7, 0)));
+        assert!(!batch.closed[LISTINGS_TABLE].contains_key("a"));
+    }
+
+    fn holding(slot: u64, authorities: Option<TokenAuthorities>) -> TokenBalance {
+        TokenBalance {
+            amount: 1,
+            token_program: token::SPL_TOKEN,
+            extensions: serde_json::json!({}),
+            authorities,
+            slot,
+            write_version: 0,
+        }
+    }
+
+    fn delegated(delegate: Option<&str>, delegated_amount: u64) -> Option<TokenAuthorities> {
+        Some(TokenAuthorities {
+            delegate: delegate.map(str::to_string),
+            delegated_amount,
+            ..TokenAuthorities::default()
+        })
+    }
+
+    fn events_of(
+        previous: Option<TokenAuthorities>,
+        stored: Option<(u64, u64)>,
+        changes: &[TokenBalance],
+    ) -> Vec<(&'static str, u64)> {
+        let mint = "mint".to_string();
+        holding_events(
+            "wallet",
+            previous.unwrap_or_default(),
+            stored,
+            changes.iter().map(|balance| (&mint, balance)).collect(),
+        )
+        .into_iter()
+        .map(|event| (event.event, event.slot))
+        .collect()
+    }
+
+    #[test]
+    fn raises_events_for_changes_undone_within_a_batch() {
+        // approved, then revoked before the flush, arriving out of order
+        let changes = [
+            holding(11, delegated(None, 0)),
+            holding(10, delegated(Some("d"), 5)),
+        ];
+        assert_eq!(events_of(None, None, &changes), [("delegate_approved", 10)]);
+
+        let frozen = TokenAuthorities {
+            frozen: true,
+            ..TokenAuthorities::default()
+        };
+        let changes = [
+            holding(10, Some(frozen)),
+            holding(11, Some(TokenAuthorities::default())),
+        ];
+        assert_eq!(events_of(None, None, &changes), [("account_frozen", 10)]);
+
+        let changes = [
+            holding(10, delegated(Some("d"), 5)),
+            holding(11, delegated(Some("d"), 500)),
+            holding(12, delegated(Some("d"), 5)),
+        ];
+        assert_eq!(
+            events_of(None, None, &changes),
+            [
+                ("delegate_approved", 10),
+                ("delegated_amount_increased", 11)
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_changes_the_stored_row_has_seen() {
+        let changes = [
+            holding(10, delegated(Some("d"), 5)),
+            holding(12, delegated(Some("e"), 5)),
+        ];
+        // the stored authorities are those of slot 10, e.g. a replayed batch
+        assert_eq!(
+            events_of(delegated(Some("d"), 5), Some((10, 0)), &changes),
+            [("delegate_approved", 12)]
+        );
+
+        // a close in between is compared against nothing, like a new holding
+        let changes = [
+            holding(10, delegated(Some("d"), 5)),
+            holding(11, None),
+            holding(12, delegated(Some("d"), 5)),
+        ];
+        assert_eq!(
+            events_of(None, None, &changes),
+            [("delegate_approved", 10), ("delegate_approved", 12)]
+        );
+    }
+
+    #[test]
+    fn replays_spooled_batches() {
+        let mut batch = Batch::default();
+        for (lamports, slot) in [(5, 10), (7, 11)] {
+            batch.push(WriteOp::SolBalance {
+                user: "wallet".to_string(),
+                lamports,
+                slot,
+                write_version: 0,
+            });
+        }
+        batch.push(WriteOp::TokenHolding {
+            user: "wallet".to_string(),
+            token_account: "token account".to_string(),
+            mint: "mint".to_string(),
+            balance: Box::new(TokenBalance {
+                amount: 3,
+                token_program: token::SPL_TOKEN,
+                extensions: serde_json::json!({}),
+                authorities: None,
+                slot: 11,
+                write_version: 0,
+            }),
+        });
+        batch.push(WriteOp::SlotStatus {
+            slot: 11,
+            parent: Some(10),
+            status: SlotStatus::Confirmed,
+        });
+
+        let dir = env::temp_dir().join(format!("heimdall-pipeline-spool-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut spool = Spool::open(&dir, 0, 1, None).unwrap();
+        assert!(spool.push(&SpooledBatch::from(batch)).unwrap());
+        let replayed = Spool::open(&dir, 0, 1, None)
+            .unwrap()
+            .peek::<SpooledBatch>()
+            .unwrap()
+            .unwrap()
+            .into_batch(&AccountRegistry::default());
+        fs::remove_dir_all(dir).unwrap();
+
+        let user = &replayed.users["wallet"];
+        assert_eq!(user.lamports, Some((7, 11, 0)));
+        assert_eq!(user.lamports_changes, [(5, 10, 0), (7, 11, 0)]);
+        let (mint, balance) = &user.holdings["token account"];
+        assert_eq!((mint.as_str(), balance.amount), ("mint", 3));
+        assert_eq!(user.holding_changes.len(), 1);
+        assert_eq!(replayed.slots[&11], (Some(10), "confirmed"));
+    }
+
+    #[tokio::test]
+    async fn moves_the_queue_flush_and_backfill_metrics() {
+        let metrics = metrics();
+
+        // queue depth follows what's waiting, a full queue drops under the drop policy
+        let (pipeline, mut queue) = WritePipeline::unstarted(QueueFullPolicy::Drop, 2);
+        let dropped = metrics.queue_dropped.get();
+        pipeline.send(WriteOp::Epoch { epoch: 1, slot: 1 });
+        pipeline.send(WriteOp::Epoch { epoch: 2, slot: 2 });
+        assert_eq!(metrics.queue_depth.with_label_values(&["0"]).get(), 2);
+        pipeline.send(WriteOp::Epoch { epoch: 3, slot: 3 });
+        assert!(metrics.queue_dropped.get() > dropped);
+
+        // a flush times its writes and counts the ones that failed
+        let mut batch = Batch::default();
+        batch.push(queue.recv().await.unwrap());
+        let pool = PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(100))
+            .connect_lazy("postgres://heimdall@127.0.0.1:1/heimdall")
+            .unwrap();
+        let timed = metrics
+            .db_write_seconds
+            .with_label_values(&["epochs"])
+            .get_sample_count();
+        let failed = metrics.db_write_errors.with_label_values(&["epochs"]).get();
+        assert!(batch.flush(&pool).await.is_err());
+        assert!(
+            metrics
+                .db_write_seconds
+                .with_label_values(&["epochs"])
+                .get_sample_count()
+                > timed
+        );
+        assert!(metrics.db_write_errors.with_label_values(&["epochs"]).get() > failed);
+
+        // fetches through an RPC that isn't up are counted on every retry
+        let failures = metrics.backfill_failures.get();
+        let backfill = Backfill::start(
+            &Handle::current(),
+            "http://127.0.0.1:1",
+            &RpcConfig {
+                url: None,
+                retry_ms: 1,
+            },
+            |_| {},
+        );
+        backfill.fetch_mint([1; 32]);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while metrics.backfill_failures.get() <= failures {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("the failed fetch wasn't counted");
+        backfill.stop().await;
+    }
+}
@@ -0,0 +1,382 @@
+use anchor_lang::AnchorDeserialize;
+use serde_json::Value;
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use crate::{
+    config::{AccountFilter, FilterEntry, ProgramConfig},
+    idl::{account_discriminator, IdlAccount, IdlError, ProgramIdl},
+    metrics::metrics,
+    migrations::PLUGIN_TABLES,
+    models::AnchorListing,
+};
+
+pub const LISTINGS_TABLE: &str = "listings";
+
+#[derive(Debug)]
+pub enum AccountDecoder {
+    // built-in `AnchorListing` layout, used for programs without an IDL
+    Listing,
+    Idl(Arc<IdlAccount>),
+}
+
+impl AccountDecoder {
+    pub fn name(&self) -> &str {
+        match self {
+            AccountDecoder::Listing => "Listing",
+            AccountDecoder::Idl(account) => &account.name,
+        }
+    }
+
+    pub fn table(&self) -> &str {
+        match self {
+            AccountDecoder::Listing => LISTINGS_TABLE,
+            AccountDecoder::Idl(account) => &account.table,
+        }
+    }
+}
+
+pub enum DecodedAccount<'a> {
+    Listing(AnchorListing),
+    Idl(&'a Arc<IdlAccount>, Value),
+}
+
+pub type DecodeResult<'a> = Result<DecodedAccount<'a>, Box<dyn Error>>;
+
+#[derive(Debug)]
+pub struct ProgramAccounts {
+    pub program_id: String,
+    pub decoders: HashMap<[u8; 8], AccountDecoder>,
+    filters: Vec<AccountFilter>,
+    idl: Option<ProgramIdl>,
+    unknown_discriminators: AtomicU64,
+}
+
+impl ProgramAccounts {
+    pub fn unknown_discriminators(&self) -> u64 {
+        self.unknown_discriminators.load(Ordering::Relaxed)
+    }
+
+    // None for programs without an IDL or bytes that aren't one of its events
+    pub fn decode_event(&self, data: &[u8]) -> Option<Result<(&str, Value), IdlError>> {
+        self.idl.as_ref()?.decode_event(data)
+    }
+
+    // true without filters
+    pub fn matches(&self, lamports: u64, data: &[u8]) -> bool {
+        self.filters
+            .iter()
+            .all(|filter| filter.matches(lamports, data))
+    }
+}
+
+// routes account data to a decoder and target table by owner program and discriminator
+#[derive(Debug, Default)]
+pub struct AccountRegistry {
+    programs: HashMap<[u8; 32], ProgramAccounts>,
+}
+
+impl AccountRegistry {
+    pub fn from_config(programs: &[ProgramConfig]) -> Result<Self, Box<dyn Error>> {
+        let mut registry = AccountRegistry::default();
+        let mut tables = HashSet::new();
+
+        for program in programs {
+            let program_bytes = bs58::decode(&program.program_id)
+                .into_vec()
+                .ok()
+                .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+                .ok_or_else(|| format!("Invalid program id {}", program.program_id))?;
+
+            if registry.programs.contains_key(&program_bytes) {
+                return Err(format!("Program {} is configured twice", program.program_id).into());
+            }
+
+            let mut decoders: HashMap<[u8; 8], AccountDecoder> = HashMap::new();
+            let idl = match program.idl_path.as_deref() {
+                Some(idl_path) => {
+                    let table_prefix = program.table_prefix.as_deref().unwrap_or_default();
+                    let mut idl = ProgramIdl::load(idl_path, table_prefix).map_err(|e| {
+                        format!("Error loading IDL for {}: {}", program.program_id, e)
+                    })?;
+                    for account in idl.accounts.drain(..) {
+                        if let Some(existing) = decoders.get(&account.discriminator) {
+                            return Err(format!(
+                                "Accounts {} and {} of {} share a discriminator",
+                                existing.name(),
+                                account.name,
+                                program.program_id
+                            )
+                            .into());
+                        }
+                        decoders.insert(
+                            account.discriminator,
+                            AccountDecoder::Idl(Arc::new(account)),
+                        );
+                    }
+                    Some(idl)
+                }
+                None => {
+                    decoders.insert(account_discriminator("Listing"), AccountDecoder::Listing);
+                    None
+                }
+            };
+
+            for decoder in decoders.values() {
+                // programs without an IDL all share the built-in listings table
+                if !matches!(decoder, AccountDecoder::Idl(_)) {
+                    continue;
+                }
+                // `CREATE TABLE IF NOT EXISTS` would quietly reuse a plugin table, and every
+                // table gets a `_staging` twin during startup
+                if PLUGIN_TABLES.contains(&decoder.table()) || decoder.table().ends_with("_staging")
+                {
+                    return Err(format!(
+                        "Table {} of account {} clashes with a plugin table, set a table_prefix for {}",
+                        decoder.table(),
+                        decoder.name(),
+                        program.program_id
+                    )
+                    .into());
+                }
+                if !tables.insert(decoder.table().to_string()) {
+                    return Err(format!(
+                        "Table {} is used by more than one account type, set a table_prefix for {}",
+                        decoder.table(),
+                        program.program_id
+                    )
+                    .into());
+                }
+            }
+
+            let filters = program
+                .filters
+                .iter()
+                .map(|filter| resolve_filter(filter, &decoders, &program.program_id))
+                .collect::<Result<_, _>>()?;
+
+            registry.programs.insert(
+                program_bytes,
+                ProgramAccounts {
+                    program_id: program.program_id.clone(),
+                    decoders,
+                    filters,
+                    idl,
+                    unknown_discriminators: AtomicU64::new(0),
+                },
+            );
+        }
+
+        Ok(registry)
+    }
+
+    pub fn programs(&self) -> impl Iterator<Item = &ProgramAccounts> {
+        self.programs.values()
+    }
+
+    // carries the counters of programs that are still configured over from the registry
+    // this one replaces, so a reload doesn't reset them
+    pub fn keep_counters(&self, previous: &AccountRegistry) {
+        for (program_bytes, program) in &self.programs {
+            if let Some(old) = previous.programs.get(program_bytes) {
+                program
+                    .unknown_discriminators
+                    .fetch_add(old.unknown_discriminators(), Ordering::Relaxed);
+            }
+        }
+    }
+
+    // the IDL account type currently stored in `table`
+    pub fn idl_account(&self, table: &str) -> Option<&Arc<IdlAccount>> {
+        self.programs
+            .values()
+            .flat_map(|program| program.decoders.values())
+            .find_map(|decoder| match decoder {
+                AccountDecoder::Idl(account) if account.table == table => Some(account),
+                _ => None,
+            })
+    }
+
+    pub fn get(&self, owner: &[u8]) -> Option<&ProgramAccounts> {
+        <[u8; 32]>::try_from(owner)
+            .ok()
+            .and_then(|owner| self.programs.get(&owner))
+    }
+
+    // None when the owner isn't configured or the discriminator is unknown (counted)
+    pub fn decode<'a>(
+        &'a self,
+        owner: &[u8],
+        data: &[u8],
+    ) -> Option<(&'a ProgramAccounts, DecodeResult<'a>)> {
+        let program = self.get(owner)?;
+
+        let decoder = data
+            .get(..8)
+            .and_then(|bytes| <[u8; 8]>::try_from(bytes).ok())
+            .and_then(|discriminator| program.decoders.get(&discriminator));
+        let Some(decoder) = decoder else {
+            program
+                .unknown_discriminators
+                .fetch_add(1, Ordering::Relaxed);
+            metrics()
+                .decode_failures
+                .with_label_values(&[&program.program_id, "unknown_discriminator"])
+                .inc();
+            return None;
+        };
+
+        let mut account_data = &data[8..];
+        let decoded = match decoder {
+            AccountDecoder::Listing => AnchorListing::deserialize(&mut account_data)
+                .map(DecodedAccount::Listing)
+                .map_err(Into::into),
+            AccountDecoder::Idl(account) => program
+                .idl
+                .as_ref()
+                .expect("IDL decoders always come with their program IDL")
+                .decode_account(account, account_data)
+                .map(|value| DecodedAccount::Idl(account, value))
+                .map_err(Into::into),
+        };
+
+        Some((program, decoded))
+    }
+}
+
+// a discriminator filter takes the discriminator its account has in the IDL, which
+// 0.30+ IDLs list explicitly instead of deriving it from the name
+fn resolve_filter(
+    filter: &FilterEntry,
+    decoders: &HashMap<[u8; 8], AccountDecoder>,
+    program_id: &str,
+) -> Result<AccountFilter, Box<dyn Error>> {
+    let name = match filter {
+        FilterEntry::Account(filter) => return Ok(filter.clone()),
+        FilterEntry::Discriminator(name) => name,
+    };
+    decoders
+        .iter()
+        .find(|(_, decoder)| decoder.name() == name)
+        .map(|(discriminator, _)| AccountFilter::Memcmp {
+            offset: 0,
+            bytes: discriminator.to_vec(),
+        })
+        .ok_or_else(|| {
+            format!(
+                "Discriminator filter {} isn't an account of {}",
+                name, program_id
+            )
+            .into()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, process};
+
+    const PROGRAM_ID: &str = "11111111111111111111111111111111";
+
+    fn program(name: &str, idl: &str, table_prefix: Option<&str>) -> ProgramConfig {
+        let path = env::temp_dir().join(format!("heimdall-{}-{}.json", name, process::id()));
+        fs::write(&path, idl).unwrap();
+        ProgramConfig {
+            program_id: PROGRAM_ID.to_string(),
+            idl_path: Some(path.to_string_lossy().into_owned()),
+            table_prefix: table_prefix.map(str::to_string),
+            filters: Vec::new(),
+        }
+    }
+
+    const NFT_METADATA_IDL: &str = r#"{
+        "accounts": [
+            {"name": "NftMetadata", "type": {"kind": "struct", "fields": [{"name": "uri", "type": "string"}]}}
+        ]
+    }"#;
+
+    #[test]
+    fn rejects_plugin_table_names() {
+        let err = AccountRegistry::from_config(&[program("clash", NFT_METADATA_IDL, None)])
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("nft_metadata"), "{}", err);
+
+        let registry =
+            AccountRegistry::from_config(&[program("prefixed", NFT_METADATA_IDL, Some("app_"))])
+                .unwrap();
+        assert!(registry.idl_account("app_nft_metadata").is_some());
+    }
+
+    #[test]
+    fn rejects_staging_table_names() {
+        let idl = r#"{"accounts": [{"name": "PoolStaging", "type": {"kind": "struct"}}]}"#;
+        assert!(AccountRegistry::from_config(&[program("staging", idl, None)]).is_err());
+    }
+
+    #[test]
+    fn keeps_counters_across_rebuilds() {
+        let config = [ProgramConfig {
+            program_id: PROGRAM_ID.to_string(),
+            idl_path: None,
+            table_prefix: None,
+            filters: Vec::new(),
+        }];
+        let owner = bs58::decode(PROGRAM_ID).into_vec().unwrap();
+        let previous = AccountRegistry::from_config(&config).unwrap();
+        assert!(previous.decode(&owner, &[0; 16]).is_none());
+        assert!(previous.decode(&owner, &[1; 16]).is_none());
+
+        let registry = AccountRegistry::from_config(&config).unwrap();
+        registry.keep_counters(&previous);
+        assert!(registry.decode(&owner, &[2; 16]).is_none());
+        let program = registry.get(&owner).unwrap();
+        assert_eq!(program.unknown_discriminators(), 3);
+
+        // programs dropped from the config take their counters with them
+        let emptied = AccountRegistry::from_config(&[]).unwrap();
+        emptied.keep_counters(&registry);
+        assert_eq!(emptied.programs().count(), 0);
+    }
+
+    #[test]
+    fn resolves_discriminator_filters_from_the_idl() {
+        let idl = r#"{
+            "accounts": [{"name": "Pool", "discriminator": [1, 2, 3, 4, 5, 6, 7, 8]}],
+            "types": [{"name": "Pool", "type": {"kind": "struct", "fields": []}}]
+        }"#;
+        let mut config = program("filters", idl, None);
+        config.filters = serde_json::from_value(serde_json::json!([
+            {"discriminator": "Pool"},
+            {"dataSize": 12},
+            {"memcmp": {"offset": 8, "bytes": "AQI=", "encoding": "base64"}},
+            {"lamports": {"min": 10}}
+        ]))
+        .unwrap();
+        let registry = AccountRegistry::from_config(&[config.clone()]).unwrap();
+        let program = registry.programs().next().unwrap();
+
+        let data = [1, 2, 3, 4, 5, 6, 7, 8, 1, 2, 0, 0];
+        assert!(program.matches(10, &data));
+        assert!(!program.matches(9, &data));
+        assert!(!program.matches(10, &data[..11]));
+        let mut derived = account_discriminator("Pool").to_vec();
+        derived.extend_from_slice(&[1, 2, 0, 0]);
+        assert!(!program.matches(10, &derived));
+
+        config.filters = serde_json::from_value(serde_json::json!([
+            {"discriminator": "Vault"}
+        ]))
+        .unwrap();
+        let err = AccountRegistry::from_config(&[config])
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Vault"), "{}", err);
+    }
+}
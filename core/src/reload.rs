@@ -0,0 +1,339 @@
+use sqlx::{postgres::PgListener, Pool, Postgres};
+use std::{
+    collections::HashSet,
+    error::Error,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+use tokio::{runtime::Runtime, task::JoinHandle, time::MissedTickBehavior};
+
+use crate::{
+    config::{resolve_config_path, Config, ProgramConfig, ReloadConfig},
+    db,
+    idl::IdlAccount,
+    logging::{limited, setup_logger},
+    registry::{AccountDecoder, AccountRegistry},
+};
+
+const TRACKED_ENTITIES_CHANNEL: &str = "tracked_entities";
+
+// programs and users the plugin currently tracks
+#[derive(Debug, Default)]
+pub struct Tracked {
+    pub registry: AccountRegistry,
+    pub users: HashSet<[u8; 32]>,
+}
+
+impl Tracked {
+    pub fn is_user(&self, pubkey: &[u8]) -> bool {
+        <[u8; 32]>::try_from(pubkey)
+            .map(|pubkey| self.users.contains(&pubkey))
+            .unwrap_or(false)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.registry.programs().next().is_none() && self.users.is_empty()
+    }
+}
+
+// replaced as a whole on reload, callers keep the snapshot they started with
+#[derive(Debug, Clone, Default)]
+pub struct SharedTracked(Arc<RwLock<Arc<Tracked>>>);
+
+impl SharedTracked {
+    pub fn get(&self) -> Arc<Tracked> {
+        self.0.read().unwrap().clone()
+    }
+
+    pub fn set(&self, tracked: Tracked) {
+        *self.0.write().unwrap() = Arc::new(tracked);
+    }
+}
+
+// users and programs from the config plus, if enabled, the `tracked_entities` table.
+// Config entries have to be valid, bad table rows are skipped. With a database, i.e. for
+// the postgres sink, creates the IDL tables and wallet rows the result needs. On reload
+// `previous` is the registry being replaced, whose tables are already set up
+pub async fn load_tracked(
+    pool: Option<&Pool<Postgres>>,
+    config: &Config,
+    config_path: &str,
+    previous: Option<&AccountRegistry>,
+) -> Result<Tracked, Box<dyn Error + Send + Sync>> {
+    let mut programs = config.programs.clone().unwrap_or_default();
+    let mut users = HashSet::new();
+
+    for user in config.tracked_users.iter().flatten() {
+        let user_bytes =
+            parse_pubkey(user).ok_or_else(|| format!("Invalid tracked user {}", user))?;
+        users.insert(user_bytes);
+    }
+
+    if let (true, Some(pool)) = (config.reload.tracked_entities, pool) {
+        for (kind, address, idl_path, table_prefix, filters) in db::tracked_entities(pool).await? {
+            match kind.as_str() {
+                "user" => match parse_pubkey(&address) {
+                    Some(user_bytes) => {
+                        users.insert(user_bytes);
+                    }
+                    None => log::warn!("Skipping invalid tracked entity user {}", address),
+                },
+                _ if programs.iter().any(|program| program.program_id == address) => {}
+                _ => match filters.map(serde_json::from_value).transpose() {
+                    Ok(filters) => programs.push(ProgramConfig {
+                        program_id: address,
+                        idl_path: idl_path.map(|path| resolve_config_path(config_path, &path)),
+                        table_prefix,
+                        filters: filters.unwrap_or_default(),
+                    }),
+                    Err(e) => log::warn!("Skipping tracked entity program {}: {}", address, e),
+                },
+            }
+        }
+    }
+
+    let registry = AccountRegistry::from_config(&programs).map_err(|e| e.to_string())?;
+    let Some(pool) = pool else {
+        return Ok(Tracked { registry, users });
+    };
+
+    for (account, statements) in table_changes(&registry, previous) {
+        for statement in statements {
+            if let Err(e) = sqlx::query(&statement).execute(pool).await {
+                log::error!("Error creating table {}: {:?}", account.table, e);
+            }
+        }
+    }
+
+    let wallets: Vec<String> = users
+        .iter()
+        .map(|user| bs58::encode(user).into_string())
+        .collect();
+    if let Err(e) = db::register_wallets(pool, &wallets).await {
+        log::error!("Error registering tracked users: {:?}", e);
+    }
+
+    Ok(Tracked { registry, users })
+}
+
+// the table statements of every IDL account that's new or changed since `previous`.
+// Running them again for the rest would rescan each table for its account versions
+fn table_changes<'a>(
+    registry: &'a AccountRegistry,
+    previous: Option<&AccountRegistry>,
+) -> Vec<(&'a IdlAccount, Vec<String>)> {
+    registry
+        .programs()
+        .flat_map(|program| program.decoders.values())
+        .filter_map(|decoder| match decoder {
+            AccountDecoder::Idl(account) => Some(account.as_ref()),
+            _ => None,
+        })
+        .filter_map(|account| {
+            let statements = account.create_table_sql();
+            let unchanged = previous
+                .and_then(|previous| previous.idl_account(&account.table))
+                .is_some_and(|previous| previous.create_table_sql() == statements);
+            (!unchanged).then_some((account, statements))
+        })
+        .collect()
+}
+
+fn parse_pubkey(address: &str) -> Option<[u8; 32]> {
+    bs58::decode(address)
+        .into_vec()
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+}
+
+// re-reads the config file and `tracked_entities`, keeps the current set on any error
+pub async fn reload(pool: Option<&Pool<Postgres>>, config_path: &str, tracked: &SharedTracked) {
+    let config = match Config::load(config_path) {
+        Ok(config) => config,
+        Err(e) => {
+            log::error!("Error reloading config {}: {}", config_path, e);
+            return;
+        }
+    };
+    setup_logger(&config.log_level);
+
+    let current = tracked.get();
+    match load_tracked(pool, &config, config_path, Some(&current.registry)).await {
+        Ok(reloaded) => {
+            reloaded.registry.keep_counters(&current.registry);
+            log::info!(
+                "Reloaded {} programs and {} tracked users",
+                reloaded.registry.programs().count(),
+                reloaded.users.len()
+            );
+            tracked.set(reloaded);
+        }
+        Err(e) => log::error!("Error reloading tracked users and programs: {}", e),
+    }
+}
+
+// background tasks that reload the tracked set when its sources change
+#[derive(Debug, Default)]
+pub struct Reloader {
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Reloader {
+    pub fn start(
+        runtime: &Runtime,
+        pool: Option<Pool<Postgres>>,
+        config_path: &str,
+        config: &ReloadConfig,
+        tracked: SharedTracked,
+    ) -> Self {
+        let interval = Duration::from_millis(config.poll_interval_ms.max(1));
+        let mut tasks = Vec::new();
+
+        if config.watch_config {
+            tasks.push(runtime.spawn(watch_config(
+                pool.clone(),
+                config_path.to_string(),
+                interval,
+                tracked.clone(),
+            )));
+        }
+        if let (true, Some(pool)) = (config.tracked_entities, pool) {
+            tasks.push(runtime.spawn(listen_tracked_entities(
+                pool,
+                config_path.to_string(),
+                interval,
+                tracked,
+            )));
+        }
+
+        Reloader { tasks }
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.tasks.is_empty()
+    }
+
+    pub fn stop(&mut self) {
+        for task in self.tasks.drain(..) {
+            task.abort();
+        }
+    }
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+async fn watch_config(
+    pool: Option<Pool<Postgres>>,
+    config_path: String,
+    interval: Duration,
+    tracked: SharedTracked,
+) {
+    let mut last_modified = modified(&config_path);
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    loop {
+        ticker.tick().await;
+        let current = modified(&config_path);
+        if current != last_modified {
+            last_modified = current;
+            reload(pool.as_ref(), &config_path, &tracked).await;
+        }
+    }
+}
+
+async fn listen_tracked_entities(
+    pool: Pool<Postgres>,
+    config_path: String,
+    retry_interval: Duration,
+    tracked: SharedTracked,
+) {
+    let mut listener = loop {
+        let listener = match PgListener::connect_with(&pool).await {
+            Ok(mut listener) => listener
+                .listen(TRACKED_ENTITIES_CHANNEL)
+                .await
+                .map(|()| listener),
+            Err(e) => Err(e),
+        };
+        match listener {
+            Ok(listener) => break listener,
+            Err(e) => {
+                limited!(error, "Error listening for tracked entity changes: {:?}", e);
+                tokio::time::sleep(retry_interval).await;
+            }
+        }
+    };
+
+    loop {
+        match listener.recv().await {
+            Ok(_) => reload(Some(&pool), &config_path, &tracked).await,
+            // the listener reconnects on the next recv, changes made meanwhile were missed
+            Err(e) => {
+                limited!(error, "Lost tracked entity notifications: {:?}", e);
+                tokio::time::sleep(retry_interval).await;
+                reload(Some(&pool), &config_path, &tracked).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, process};
+
+    const PROGRAM_ID: &str = "11111111111111111111111111111111";
+
+    fn registry(name: &str, idl: &str) -> AccountRegistry {
+        let path = env::temp_dir().join(format!("heimdall-reload-{}-{}.json", name, process::id()));
+        fs::write(&path, idl).unwrap();
+        let registry = AccountRegistry::from_config(&[ProgramConfig {
+            program_id: PROGRAM_ID.to_string(),
+            idl_path: Some(path.to_string_lossy().into_owned()),
+            table_prefix: None,
+            filters: Vec::new(),
+        }])
+        .unwrap();
+        fs::remove_file(path).unwrap();
+        registry
+    }
+
+    fn changed_tables(
+        registry: &AccountRegistry,
+        previous: Option<&AccountRegistry>,
+    ) -> Vec<String> {
+        let mut tables: Vec<_> = table_changes(registry, previous)
+            .into_iter()
+            .map(|(account, _)| account.table.clone())
+            .collect();
+        tables.sort();
+        tables
+    }
+
+    #[test]
+    fn only_sets_up_new_or_changed_tables() {
+        let pool = r#"{"accounts": [{"name": "Pool", "type": {"kind": "struct", "fields": [
+            {"name": "reserve", "type": "u64"}
+        ]}}]}"#;
+        let first = registry("first", pool);
+        assert_eq!(changed_tables(&first, None), ["pool"]);
+        // reloading the same IDL leaves the table alone
+        assert!(changed_tables(&registry("same", pool), Some(&first)).is_empty());
+
+        let extended = r#"{"accounts": [
+            {"name": "Pool", "type": {"kind": "struct", "fields": [
+                {"name": "reserve", "type": "u64"}, {"name": "fee", "type": "u16"}
+            ]}},
+            {"name": "Vault", "type": {"kind": "struct", "fields": []}}
+        ]}"#;
+        assert_eq!(
+            changed_tables(&registry("extended", extended), Some(&first)),
+            ["pool", "vault"]
+        );
+    }
+}
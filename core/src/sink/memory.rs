@@ -0,0 +1,38 @@
+use std::{collections::VecDeque, sync::Mutex};
+
+use super::{AccountSink, SinkEvent};
+
+// keeps the most recent events in memory, meant for tests
+#[derive(Debug)]
+pub struct MemorySink {
+    capacity: usize,
+    events: Mutex<VecDeque<SinkEvent>>,
+}
+
+impl MemorySink {
+    pub fn new(capacity: usize) -> Self {
+        MemorySink {
+            capacity: capacity.max(1),
+            events: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    // oldest first
+    pub fn events(&self) -> Vec<SinkEvent> {
+        self.events.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.events.lock().unwrap().clear();
+    }
+}
+
+impl AccountSink for MemorySink {
+    fn send(&self, event: &SinkEvent, _is_startup: bool) {
+        let mut events = self.events.lock().unwrap();
+        if events.len() == self.capacity {
+            events.pop_front();
+        }
+        events.push_back(event.clone());
+    }
+}
@@ -0,0 +1,120 @@
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+use solana_geyser_plugin_interface::geyser_plugin_interface::SlotStatus;
+use std::{fmt::Debug, sync::Arc};
+
+use crate::{
+    idl::IdlAccount,
+    models::{
+        Block, Listing, MasterEdition, MintInfo, NativeAccount, NftMetadata, ProgramEvent,
+        TokenBalance, Transaction,
+    },
+};
+
+mod memory;
+mod ndjson;
+pub(crate) mod postgres;
+
+pub use memory::MemorySink;
+pub use ndjson::NdjsonSink;
+
+// everything the plugin stores, every configured sink receives each event
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SinkEvent {
+    ListingUpdated {
+        account: String,
+        listing: Listing,
+        slot: u64,
+        write_version: u64,
+    },
+    // account of a program with an IDL, `data` holds the decoded fields
+    IdlAccountUpdated {
+        account: String,
+        program_id: String,
+        #[serde(rename = "account_type", serialize_with = "idl_account_name")]
+        idl_account: Arc<IdlAccount>,
+        data: Value,
+        slot: u64,
+        write_version: u64,
+    },
+    WalletBalanceChanged {
+        wallet: String,
+        lamports: u64,
+        slot: u64,
+        write_version: u64,
+    },
+    // also sent when the token account is closed, with an amount of 0 and no authorities
+    TokenHoldingChanged {
+        wallet: String,
+        token_account: String,
+        mint: String,
+        balance: TokenBalance,
+    },
+    MintUpdated {
+        mint: String,
+        info: MintInfo,
+    },
+    // boxed, it's several times larger than any other event
+    NftMetadataUpdated {
+        mint: String,
+        metadata: Box<NftMetadata>,
+    },
+    MasterEditionUpdated {
+        mint: String,
+        edition: MasterEdition,
+    },
+    // stake, vote or nonce account, boxed like the metadata
+    NativeAccountUpdated {
+        account: String,
+        native: Box<NativeAccount>,
+    },
+    // a program, stake, vote or nonce account was closed or stopped matching, `table` is
+    // where it was stored
+    AccountClosed {
+        account: String,
+        table: String,
+        slot: u64,
+        write_version: u64,
+    },
+    // first time the clock reported `epoch`
+    EpochStarted {
+        epoch: u64,
+        slot: u64,
+    },
+    SlotStatusChanged {
+        slot: u64,
+        parent: Option<u64>,
+        #[serde(serialize_with = "slot_status")]
+        status: SlotStatus,
+    },
+    TransactionProcessed(Transaction),
+    EventEmitted(ProgramEvent),
+    BlockProduced(Block),
+}
+
+fn idl_account_name<S: Serializer>(
+    idl_account: &Arc<IdlAccount>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&idl_account.name)
+}
+
+fn slot_status<S: Serializer>(status: &SlotStatus, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(status.as_str())
+}
+
+// destination for the plugin's events. `send` is called on the validator's geyser threads,
+// so it must not wait on anything slow
+pub trait AccountSink: Debug + Send + Sync {
+    // `is_startup` marks accounts from the snapshot the validator loads at boot
+    fn send(&self, event: &SinkEvent, is_startup: bool);
+
+    // called on every slot status update, mustn't wait either
+    fn flush(&self) {}
+
+    // called on unload, returns once everything sent before is written
+    fn close(&self) {
+        self.flush()
+    }
+}
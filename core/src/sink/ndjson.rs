@@ -0,0 +1,308 @@
+use serde::Serialize;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+    },
+    thread,
+};
+
+use super::{AccountSink, SinkEvent};
+use crate::{logging::limited, metrics::metrics};
+
+// one JSON object per line
+#[derive(Serialize)]
+struct Line<'a> {
+    #[serde(flatten)]
+    event: &'a SinkEvent,
+    is_startup: bool,
+}
+
+#[derive(Debug)]
+enum Message {
+    Line(Vec<u8>),
+    Flush,
+    // flushes, answers and stops the writer
+    Close(mpsc::Sender<()>),
+}
+
+#[derive(Debug)]
+struct OpenFile {
+    writer: BufWriter<File>,
+    len: u64,
+}
+
+impl OpenFile {
+    fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let len = file.metadata()?.len();
+        Ok(OpenFile {
+            writer: BufWriter::new(file),
+            len,
+        })
+    }
+}
+
+// owned by the writer thread, the only one touching the files
+#[derive(Debug)]
+struct Writer {
+    path: PathBuf,
+    max_bytes: Option<u64>,
+    max_files: usize,
+    file: OpenFile,
+}
+
+impl Writer {
+    fn run(mut self, receiver: Receiver<Message>) {
+        for message in receiver {
+            match message {
+                Message::Line(line) => {
+                    if let Err(e) = self.write(&line) {
+                        limited!(error, "Error writing to {}: {:?}", self.path.display(), e);
+                    }
+                }
+                Message::Flush => self.flush(),
+                Message::Close(done) => {
+                    self.flush();
+                    let _ = done.send(());
+                    return;
+                }
+            }
+        }
+        // every sender is gone
+        self.flush();
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.writer.flush()?;
+
+        for index in (1..self.max_files).rev() {
+            match fs::rename(self.rotated_path(index), self.rotated_path(index + 1)) {
+                Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        if self.max_files > 0 {
+            fs::rename(&self.path, self.rotated_path(1))?;
+        } else {
+            fs::remove_file(&self.path)?;
+        }
+
+        self.file = OpenFile::open(&self.path)?;
+        Ok(())
+    }
+
+    fn write(&mut self, line: &[u8]) -> io::Result<()> {
+        let len = line.len() as u64;
+        if self
+            .max_bytes
+            .is_some_and(|max_bytes| self.file.len > 0 && self.file.len + len > max_bytes)
+        {
+            self.rotate()?;
+        }
+
+        self.file.writer.write_all(line)?;
+        self.file.len += len;
+        Ok(())
+    }
+
+    fn flush(&mut self) {
+        if let Err(e) = self.file.writer.flush() {
+            limited!(error, "Error flushing {}: {:?}", self.path.display(), e);
+        }
+    }
+}
+
+// appends every event to a file. Once the file would grow past `max_bytes` it's renamed
+// to `<path>.1`, older files shift up to `<path>.<max_files>` and the oldest is dropped.
+// The file is written by a thread of its own, `send` only queues the line and drops it
+// when `queue_capacity` lines are already waiting
+#[derive(Debug)]
+pub struct NdjsonSink {
+    path: PathBuf,
+    sender: SyncSender<Message>,
+    dropped: AtomicU64,
+}
+
+impl NdjsonSink {
+    pub fn open(
+        path: impl Into<PathBuf>,
+        max_bytes: Option<u64>,
+        max_files: usize,
+        queue_capacity: usize,
+    ) -> io::Result<Self> {
+        let path = path.into();
+        let writer = Writer {
+            file: OpenFile::open(&path)?,
+            path: path.clone(),
+            max_bytes,
+            max_files,
+        };
+        let (sender, receiver) = mpsc::sync_channel(queue_capacity);
+        thread::Builder::new()
+            .name("heimdall-ndjson".to_string())
+            .spawn(move || writer.run(receiver))?;
+        Ok(NdjsonSink {
+            path,
+            sender,
+            dropped: AtomicU64::new(0),
+        })
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl AccountSink for NdjsonSink {
+    fn send(&self, event: &SinkEvent, is_startup: bool) {
+        let mut line = match serde_json::to_vec(&Line { event, is_startup }) {
+            Ok(line) => line,
+            Err(e) => {
+                limited!(error, "Error serializing sink event: {:?}", e);
+                return;
+            }
+        };
+        line.push(b'\n');
+
+        match self.sender.try_send(Message::Line(line)) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                metrics().ndjson_dropped.inc();
+                let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                if dropped % 10_000 == 1 {
+                    log::warn!(
+                        "Queue of {} full, {} events dropped so far",
+                        self.path.display(),
+                        dropped
+                    );
+                }
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                limited!(
+                    warn,
+                    "Writer of {} stopped, event dropped",
+                    self.path.display()
+                )
+            }
+        }
+    }
+
+    // a full queue means the writer is busy, a later slot flushes instead
+    fn flush(&self) {
+        let _ = self.sender.try_send(Message::Flush);
+    }
+
+    fn close(&self) {
+        let (done, wait) = mpsc::channel();
+        if self.sender.send(Message::Close(done)).is_ok() {
+            let _ = wait.recv();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    fn event(slot: u64) -> SinkEvent {
+        SinkEvent::EpochStarted { epoch: 1, slot }
+    }
+
+    fn lines(path: &Path) -> Vec<String> {
+        fs::read_to_string(path)
+            .unwrap_or_default()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("heimdall-ndjson-{}-{}.ndjson", name, process::id()));
+        for index in 0..3 {
+            let mut rotated = path.clone().into_os_string();
+            if index > 0 {
+                rotated.push(format!(".{}", index));
+            }
+            let _ = fs::remove_file(rotated);
+        }
+        path
+    }
+
+    #[test]
+    fn writes_and_rotates_on_the_writer_thread() {
+        let path = temp_path("rotate");
+        let line_len = serde_json::to_vec(&Line {
+            event: &event(10),
+            is_startup: false,
+        })
+        .unwrap()
+        .len() as u64
+            + 1;
+        let sink = NdjsonSink::open(&path, Some(line_len * 2), 1, 100).unwrap();
+        for slot in 10..15 {
+            sink.send(&event(slot), false);
+        }
+        sink.close();
+
+        // the oldest file was dropped, one rotated file is kept
+        assert_eq!(lines(&path).len(), 1);
+        assert!(
+            lines(&path)[0].contains(r#""slot":14"#),
+            "{:?}",
+            lines(&path)
+        );
+        let rotated = lines(&PathBuf::from(format!("{}.1", path.display())));
+        assert_eq!(rotated.len(), 2);
+        assert!(rotated[0].contains(r#""slot":12"#), "{:?}", rotated);
+        assert!(!PathBuf::from(format!("{}.2", path.display())).exists());
+
+        // sends after close are dropped without waiting
+        sink.send(&event(15), false);
+        sink.flush();
+        sink.close();
+        assert_eq!(lines(&path).len(), 1);
+    }
+
+    #[test]
+    fn drops_events_once_the_queue_is_full() {
+        let path = temp_path("full");
+        let (sender, receiver) = mpsc::sync_channel(2);
+        let sink = NdjsonSink {
+            path: path.clone(),
+            sender,
+            dropped: AtomicU64::new(0),
+        };
+        let dropped = metrics().ndjson_dropped.get();
+
+        // the writer isn't running yet, so only the first two fit
+        for slot in 0..5 {
+            sink.send(&event(slot), false);
+        }
+        assert_eq!(sink.dropped(), 3);
+        assert!(metrics().ndjson_dropped.get() >= dropped + 3);
+
+        let writer = Writer {
+            file: OpenFile::open(&path).unwrap(),
+            path: path.clone(),
+            max_bytes: None,
+            max_files: 0,
+        };
+        thread::spawn(move || writer.run(receiver));
+        sink.close();
+        let written = lines(&path);
+        assert_eq!(written.len(), 2);
+        assert!(written[1].contains(r#""slot":1"#), "{:?}", written);
+    }
+}
@@ -0,0 +1,167 @@
+use sqlx::{Pool, Postgres};
+use std::io;
+use tokio::runtime::Runtime;
+
+use super::{AccountSink, SinkEvent};
+use crate::{
+    config::{DatabaseConfig, PipelineConfig},
+    metrics::metrics,
+    pipeline::{WriteOp, WritePipeline},
+    reload::SharedTracked,
+    startup::StartupLoader,
+};
+
+// the plugin's own tables. Live updates go through the write pipeline and snapshot
+// accounts through the startup loader
+#[derive(Debug)]
+pub struct PostgresSink {
+    pipeline: WritePipeline,
+    startup: Option<StartupLoader>,
+}
+
+impl PostgresSink {
+    // a reloaded plugin gets no snapshot accounts and no end of startup
+    pub fn start(
+        runtime: &Runtime,
+        pool: Pool<Postgres>,
+        config: &PipelineConfig,
+        database: &DatabaseConfig,
+        tracked: SharedTracked,
+        is_reload: bool,
+    ) -> io::Result<Self> {
+        let pipeline = WritePipeline::start(runtime, pool.clone(), config, database, tracked)?;
+        if is_reload {
+            metrics().startup_complete.set(1);
+        }
+        Ok(PostgresSink {
+            pipeline,
+            startup: (!is_reload).then(|| StartupLoader::start(runtime, pool)),
+        })
+    }
+
+    fn enqueue(&self, op: WriteOp) {
+        self.pipeline.send(op);
+    }
+
+    // snapshot accounts go to the startup loader instead of the write pipeline
+    fn enqueue_account(&self, op: WriteOp, is_startup: bool) {
+        match &self.startup {
+            Some(startup) if is_startup && startup.is_active() => startup.send(op),
+            _ => self.enqueue(op),
+        }
+    }
+
+    // see `StartupLoader::finish`
+    pub async fn finish_startup(&self) -> Option<u64> {
+        self.startup.as_ref()?.finish().await
+    }
+
+    pub async fn shutdown(self) {
+        let dropped = self.pipeline.dropped();
+        if dropped > 0 {
+            log::warn!(
+                "Dropped {} updates because the write queue was full",
+                dropped
+            );
+        }
+        self.pipeline.shutdown().await;
+    }
+}
+
+impl AccountSink for PostgresSink {
+    fn send(&self, event: &SinkEvent, is_startup: bool) {
+        let op = match event.clone() {
+            SinkEvent::ListingUpdated {
+                account,
+                listing,
+                slot,
+                write_version,
+            } => WriteOp::Listing {
+                account,
+                listing,
+                slot,
+                write_version,
+            },
+            SinkEvent::IdlAccountUpdated {
+                account,
+                program_id,
+                idl_account,
+                data,
+                slot,
+                write_version,
+            } => WriteOp::IdlAccount {
+                account,
+                program_id,
+                idl_account,
+                data,
+                slot,
+                write_version,
+            },
+            SinkEvent::WalletBalanceChanged {
+                wallet,
+                lamports,
+                slot,
+                write_version,
+            } => WriteOp::SolBalance {
+                user: wallet,
+                lamports,
+                slot,
+                write_version,
+            },
+            SinkEvent::TokenHoldingChanged {
+                wallet,
+                token_account,
+                mint,
+                balance,
+            } => WriteOp::TokenHolding {
+                user: wallet,
+                token_account,
+                mint,
+                balance: Box::new(balance),
+            },
+            SinkEvent::MintUpdated { mint, info } => WriteOp::Mint { mint, info },
+            SinkEvent::NftMetadataUpdated { mint, metadata } => {
+                WriteOp::NftMetadata { mint, metadata }
+            }
+            SinkEvent::MasterEditionUpdated { mint, edition } => {
+                WriteOp::MasterEdition { mint, edition }
+            }
+            SinkEvent::NativeAccountUpdated { account, native } => {
+                WriteOp::NativeAccount { account, native }
+            }
+            SinkEvent::AccountClosed {
+                account,
+                table,
+                slot,
+                write_version,
+            } => WriteOp::AccountClosed {
+                account,
+                table,
+                slot,
+                write_version,
+            },
+            SinkEvent::EpochStarted { epoch, slot } => WriteOp::Epoch { epoch, slot },
+            SinkEvent::SlotStatusChanged {
+                slot,
+                parent,
+                status,
+            } => WriteOp::SlotStatus {
+                slot,
+                parent,
+                status,
+            },
+            SinkEvent::TransactionProcessed(transaction) => WriteOp::Transaction(transaction),
+            SinkEvent::EventEmitted(event) => WriteOp::Event(event),
+            SinkEvent::BlockProduced(block) => WriteOp::Block(block),
+        };
+        match op {
+            WriteOp::Listing { .. }
+            | WriteOp::IdlAccount { .. }
+            | WriteOp::SolBalance { .. }
+            | WriteOp::TokenHolding { .. } => self.enqueue_account(op, is_startup),
+            // mints, metadata and native accounts are few, the rest isn't part of the
+            // snapshot, so they skip the startup loader
+            _ => self.enqueue(op),
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::VecDeque,
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+// one write pipeline worker's batches that couldn't be written while the database was
+// down. Each batch is a `<worker>-<sequence>.json` file, replayed oldest first. A worker
+// also takes over the files of workers that no longer exist, e.g. after `workers` was
+// lowered, so nothing left from a previous run is lost
+#[derive(Debug)]
+pub struct Spool {
+    dir: PathBuf,
+    worker: usize,
+    max_bytes: Option<u64>,
+    // (file, size) of every spooled batch, oldest first
+    files: VecDeque<(PathBuf, u64)>,
+    bytes: u64,
+    next_sequence: u64,
+}
+
+impl Spool {
+    pub fn open(
+        dir: &Path,
+        worker: usize,
+        workers: usize,
+        max_bytes: Option<u64>,
+    ) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+
+        let mut found = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let Some((owner, sequence)) = parse_name(&path) else {
+                continue;
+            };
+            if owner % workers == worker {
+                found.push((sequence, owner, path));
+            }
+        }
+        found.sort();
+
+        let mut spool = Spool {
+            dir: dir.to_path_buf(),
+            worker,
+            max_bytes,
+            files: VecDeque::new(),
+            bytes: 0,
+            next_sequence: found.last().map_or(0, |(sequence, _, _)| sequence + 1),
+        };
+        for (_, _, path) in found {
+            let size = fs::metadata(&path)?.len();
+            spool.bytes += size;
+            spool.files.push_back((path, size));
+        }
+        Ok(spool)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    // false if the spool is full and the batch was not written
+    pub fn push<T: Serialize>(&mut self, batch: &T) -> io::Result<bool> {
+        let data = serde_json::to_vec(batch)?;
+        let size = data.len() as u64;
+        if self
+            .max_bytes
+            .is_some_and(|max_bytes| self.bytes + size > max_bytes)
+        {
+            return Ok(false);
+        }
+
+        // written under a temporary name first, so a crash never leaves half a batch
+        let path = self
+            .dir
+            .join(format!("{}-{:020}.json", self.worker, self.next_sequence));
+        let partial = path.with_extension("json.partial");
+        fs::write(&partial, data)?;
+        fs::rename(&partial, &path)?;
+
+        self.next_sequence += 1;
+        self.bytes += size;
+        self.files.push_back((path, size));
+        Ok(true)
+    }
+
+    // the oldest batch, left in place until it's popped
+    pub fn peek<T: DeserializeOwned>(&self) -> io::Result<Option<T>> {
+        let Some((path, _)) = self.files.front() else {
+            return Ok(None);
+        };
+        let data = fs::read(path)?;
+        serde_json::from_slice(&data)
+            .map(Some)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+    }
+
+    pub fn pop(&mut self) -> io::Result<()> {
+        let Some((path, size)) = self.files.pop_front() else {
+            return Ok(());
+        };
+        self.bytes -= size;
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+// `<worker>-<sequence>.json`
+fn parse_name(path: &Path) -> Option<(usize, u64)> {
+    if path.extension()? != "json" {
+        return None;
+    }
+    let (worker, sequence) = path.file_stem()?.to_str()?.split_once('-')?;
+    Some((worker.parse().ok()?, sequence.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    fn spool_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("heimdall-spool-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn replays_batches_oldest_first_across_restarts() {
+        let dir = spool_dir("replay");
+        let mut spool = Spool::open(&dir, 0, 1, None).unwrap();
+        for batch in ["first", "second", "third"] {
+            assert!(spool.push(&batch).unwrap());
+        }
+        assert_eq!(spool.peek::<String>().unwrap().as_deref(), Some("first"));
+        spool.pop().unwrap();
+
+        // a half written batch from a crash is ignored
+        fs::write(dir.join("0-00000000000000000009.json.partial"), "\"half").unwrap();
+        let mut spool = Spool::open(&dir, 0, 1, None).unwrap();
+        assert_eq!(spool.len(), 2);
+        assert!(spool.push(&"fourth").unwrap());
+        let mut replayed = Vec::new();
+        while let Some(batch) = spool.peek::<String>().unwrap() {
+            replayed.push(batch);
+            spool.pop().unwrap();
+        }
+        assert_eq!(replayed, ["second", "third", "fourth"]);
+        assert!(spool.is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn stops_at_max_bytes() {
+        let dir = spool_dir("full");
+        // `"batch"` is 7 bytes
+        let mut spool = Spool::open(&dir, 0, 1, Some(14)).unwrap();
+        assert!(spool.push(&"batch").unwrap());
+        assert!(spool.push(&"batch").unwrap());
+        assert!(!spool.push(&"batch").unwrap());
+        assert_eq!(spool.len(), 2);
+
+        spool.pop().unwrap();
+        assert!(spool.push(&"batch").unwrap());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn takes_over_the_files_of_removed_workers() {
+        let dir = spool_dir("workers");
+        for worker in 0..3 {
+            let mut spool = Spool::open(&dir, worker, 3, None).unwrap();
+            spool.push(&format!("worker {}", worker)).unwrap();
+        }
+
+        // down to two workers, the third one's batch goes to the first
+        let mut first = Spool::open(&dir, 0, 2, None).unwrap();
+        let second = Spool::open(&dir, 1, 2, None).unwrap();
+        assert_eq!((first.len(), second.len()), (2, 1));
+        assert_eq!(first.peek::<String>().unwrap().as_deref(), Some("worker 0"));
+        first.pop().unwrap();
+        assert_eq!(first.peek::<String>().unwrap().as_deref(), Some("worker 2"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
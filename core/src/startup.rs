@@ -0,0 +1,332 @@
+use sqlx::{Pool, Postgres};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+use tokio::{runtime::Runtime, sync::mpsc, task::JoinHandle};
+
+use crate::{
+    db,
+    idl::IdlAccount,
+    logging::limited,
+    metrics::metrics,
+    pipeline::{write_user_updates, UserUpdate, WriteOp},
+    registry::LISTINGS_TABLE,
+};
+
+const QUEUE_CAPACITY: usize = 100_000;
+// a staging buffer is sent with COPY once it holds this much CSV
+const COPY_CHUNK_BYTES: usize = 8 << 20;
+
+#[derive(Debug)]
+enum StartupMsg {
+    Op(WriteOp),
+    Finish,
+}
+
+// bulk-loads snapshot accounts seen while the validator boots: rows are COPYed into
+// unlogged staging tables and merged into the real tables once startup ends, without
+// any per-row notification
+#[derive(Debug)]
+pub struct StartupLoader {
+    sender: mpsc::Sender<StartupMsg>,
+    task: Mutex<Option<JoinHandle<u64>>>,
+    finished: AtomicBool,
+}
+
+impl StartupLoader {
+    pub fn start(runtime: &Runtime, pool: Pool<Postgres>) -> Self {
+        let (sender, rx) = mpsc::channel(QUEUE_CAPACITY);
+        let task = runtime.spawn(run_loader(rx, pool));
+
+        StartupLoader {
+            sender,
+            task: Mutex::new(Some(task)),
+            finished: AtomicBool::new(false),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.finished.load(Ordering::Relaxed)
+    }
+
+    // every snapshot account has to land, so this waits for room instead of dropping
+    pub fn send(&self, op: WriteOp) {
+        metrics().startup_accounts.inc();
+        if self.sender.blocking_send(StartupMsg::Op(op)).is_err() {
+            limited!(warn, "Startup loader is shut down, update dropped");
+        }
+    }
+
+    // merges everything staged into the real tables, returns the number of accounts
+    // loaded or None if startup was already finished
+    pub async fn finish(&self) -> Option<u64> {
+        if self.finished.swap(true, Ordering::SeqCst) {
+            return None;
+        }
+
+        if self.sender.send(StartupMsg::Finish).await.is_err() {
+            log::warn!("Startup loader stopped before startup finished");
+        }
+
+        let task = self.task.lock().unwrap().take()?;
+        match task.await {
+            Ok(accounts) => {
+                metrics().startup_complete.set(1);
+                Some(accounts)
+            }
+            Err(e) => {
+                log::error!("Startup loader failed: {:?}", e);
+                None
+            }
+        }
+    }
+}
+
+// unlogged, index-free copy of one target table, filled during startup
+#[derive(Debug)]
+struct Staging {
+    table: String,
+    create_sql: String,
+    columns: &'static str,
+    merge_sql: String,
+    // appends the merged rows to a history table, listings only
+    history_sql: Option<String>,
+    // records the merged rows as the accounts' first versions
+    versions_sql: String,
+    csv: String,
+    created: bool,
+    rows: u64,
+}
+
+impl Staging {
+    fn listings() -> Self {
+        let table = format!("{}_staging", LISTINGS_TABLE);
+        Staging {
+            create_sql: format!(
+                "CREATE UNLOGGED TABLE \"{}\" (LIKE {} INCLUDING DEFAULTS)",
+                table, LISTINGS_TABLE
+            ),
+            columns: db::LISTINGS_COPY_COLUMNS,
+            merge_sql: db::merge_listings_sql(&table),
+            history_sql: Some(db::stage_listing_history_sql(&table)),
+            versions_sql: db::stage_versions_sql(
+                &table,
+                LISTINGS_TABLE,
+                "to_jsonb(r) - 'updated_at'",
+            ),
+            table,
+            csv: String::new(),
+            created: false,
+            rows: 0,
+        }
+    }
+
+    fn idl(idl_account: &IdlAccount) -> Self {
+        let table = format!("{}_staging", idl_account.table);
+        Staging {
+            create_sql: format!(
+                "CREATE UNLOGGED TABLE \"{}\" \
+                (account TEXT, program_id TEXT, data JSONB, slot BIGINT, write_version BIGINT)",
+                table
+            ),
+            columns: "account, program_id, data, slot, write_version",
+            merge_sql: idl_account.merge_sql(&table),
+            history_sql: None,
+            versions_sql: db::stage_versions_sql(
+                &table,
+                &idl_account.table,
+                "jsonb_build_object('program_id', r.program_id, 'data', r.data)",
+            ),
+            table,
+            csv: String::new(),
+            created: false,
+            rows: 0,
+        }
+    }
+
+    // every field is quoted, so there are no NULLs. NUL bytes are dropped: Postgres text
+    // can't hold them, and one would fail the whole COPY chunk
+    fn push_row(&mut self, fields: &[&dyn Display]) {
+        for (i, field) in fields.iter().enumerate() {
+            if i > 0 {
+                self.csv.push(',');
+            }
+            self.csv.push('"');
+            self.csv
+                .push_str(&field.to_string().replace('\0', "").replace('"', "\"\""));
+            self.csv.push('"');
+        }
+        self.csv.push('\n');
+    }
+
+    async fn flush(&mut self, pool: &Pool<Postgres>) {
+        if self.csv.is_empty() {
+            return;
+        }
+
+        // leftovers from an interrupted startup are stale, start from an empty table
+        if !self.created {
+            let drop_sql = format!("DROP TABLE IF EXISTS \"{}\"", self.table);
+            let result = match sqlx::query(&drop_sql).execute(pool).await {
+                Ok(_) => sqlx::query(&self.create_sql).execute(pool).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                limited!(
+                    error,
+                    "Error creating staging table {}: {:?}",
+                    self.table,
+                    e
+                );
+                self.csv.clear();
+                return;
+            }
+            self.created = true;
+        }
+
+        match db::copy_csv(pool, &self.table, self.columns, &self.csv).await {
+            Ok(rows) => self.rows += rows,
+            Err(e) => limited!(error, "Error copying into {}: {:?}", self.table, e),
+        }
+        self.csv.clear();
+    }
+
+    // indexes are only built once every row is in, then the newest row per account is merged
+    async fn merge(&mut self, pool: &Pool<Postgres>) {
+        self.flush(pool).await;
+        if !self.created {
+            return;
+        }
+
+        let statements = [
+            Some(format!(
+                "CREATE INDEX ON \"{}\" (account, slot DESC, write_version DESC)",
+                self.table
+            )),
+            Some(format!("ANALYZE \"{}\"", self.table)),
+            Some(self.merge_sql.clone()),
+            self.history_sql.clone(),
+            Some(self.versions_sql.clone()),
+            Some(format!("DROP TABLE \"{}\"", self.table)),
+        ];
+        for statement in statements.iter().flatten() {
+            if let Err(e) = sqlx::query(statement).execute(pool).await {
+                log::error!("Error merging staging table {}: {:?}", self.table, e);
+                return;
+            }
+        }
+    }
+}
+
+async fn run_loader(mut rx: mpsc::Receiver<StartupMsg>, pool: Pool<Postgres>) -> u64 {
+    // keyed by target table
+    let mut staging: HashMap<String, Staging> = HashMap::new();
+    let mut users: HashMap<String, UserUpdate> = HashMap::new();
+
+    while let Some(StartupMsg::Op(op)) = rx.recv().await {
+        let table = match op {
+            WriteOp::Listing {
+                account,
+                listing,
+                slot,
+                write_version,
+            } => {
+                let table = staging
+                    .entry(LISTINGS_TABLE.to_string())
+                    .or_insert_with(Staging::listings);
+                table.push_row(&[
+                    &account,
+                    &listing.name,
+                    &listing.seed,
+                    &listing.mint,
+                    &listing.funding_goal,
+                    &listing.pool_mint_supply,
+                    &listing.funding_raised,
+                    &listing.available_tokens,
+                    &listing.base_price,
+                    &listing.tokens_sold,
+                    &listing.bump,
+                    &listing.vault_bump,
+                    &listing.mint_bump,
+                    &slot,
+                    &write_version,
+                ]);
+                table
+            }
+            WriteOp::IdlAccount {
+                account,
+                program_id,
+                idl_account,
+                data,
+                slot,
+                write_version,
+            } => {
+                let table = staging
+                    .entry(idl_account.table.clone())
+                    .or_insert_with(|| Staging::idl(&idl_account));
+                table.push_row(&[&account, &program_id, &data, &slot, &write_version]);
+                table
+            }
+            WriteOp::SolBalance {
+                user,
+                lamports,
+                slot,
+                write_version,
+            } => {
+                users
+                    .entry(user)
+                    .or_default()
+                    .set_lamports(lamports, slot, write_version);
+                continue;
+            }
+            WriteOp::TokenHolding {
+                user,
+                token_account,
+                mint,
+                balance,
+            } => {
+                users
+                    .entry(user)
+                    .or_default()
+                    .set_token(token_account, mint, *balance);
+                continue;
+            }
+            // everything else always goes through the write pipeline
+            _ => continue,
+        };
+
+        if table.csv.len() >= COPY_CHUNK_BYTES {
+            table.flush(&pool).await;
+        }
+    }
+
+    let mut accounts = 0;
+    for table in staging.values_mut() {
+        table.merge(&pool).await;
+        accounts += table.rows;
+    }
+
+    // the snapshot is the baseline, its differences to the stored holdings raise no events
+    if let Err(e) = write_user_updates(&pool, &users).await {
+        log::error!("Error updating {} users: {:?}", users.len(), e);
+    }
+
+    accounts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_rows_drop_nul_bytes() {
+        let mut staging = Staging::listings();
+        staging.push_row(&[&"Lis\0ting\0\0", &"say \"hi\"", &42]);
+        assert_eq!(staging.csv, "\"Listing\",\"say \"\"hi\"\"\",\"42\"\n");
+    }
+}
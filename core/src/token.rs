@@ -0,0 +1,539 @@
+use serde_json::{json, Map, Value};
+use spl_token::solana_program::program_pack::Pack;
+use spl_token_2022::{
+    extension::{
+        confidential_transfer::{ConfidentialTransferAccount, ConfidentialTransferMint},
+        cpi_guard::CpiGuard,
+        immutable_owner::ImmutableOwner,
+        interest_bearing_mint::InterestBearingConfig,
+        memo_transfer::MemoTransfer,
+        non_transferable::{NonTransferable, NonTransferableAccount},
+        permanent_delegate::PermanentDelegate,
+        transfer_fee::{TransferFee, TransferFeeAmount, TransferFeeConfig},
+        BaseStateWithExtensions, StateWithExtensions,
+    },
+    solana_program::pubkey::Pubkey,
+    state::{Account as Token2022Account, Mint as Token2022Mint},
+};
+
+use crate::models::{MintInfo, TokenAuthorities};
+
+// labels stored in `token_program`, same as the RPC's jsonParsed `program`
+pub const SPL_TOKEN: &str = "spl-token";
+pub const SPL_TOKEN_2022: &str = "spl-token-2022";
+
+// token account of either token program
+#[derive(Debug)]
+pub struct TokenAccount {
+    pub owner: [u8; 32],
+    pub mint: [u8; 32],
+    pub amount: u64,
+    pub token_program: &'static str,
+    // Token-2022 account extensions, an empty object otherwise
+    pub extensions: Value,
+    pub authorities: TokenAuthorities,
+}
+
+// mint of either token program
+#[derive(Debug)]
+pub struct TokenMint {
+    pub decimals: u8,
+    pub supply: u64,
+    pub mint_authority: Option<String>,
+    pub freeze_authority: Option<String>,
+    pub token_program: &'static str,
+    // Token-2022 mint extensions, an empty object otherwise
+    pub extensions: Value,
+}
+
+pub fn token_program(owner: &[u8]) -> Option<&'static str> {
+    if owner == spl_token::ID.as_ref() {
+        Some(SPL_TOKEN)
+    } else if owner == spl_token_2022::ID.as_ref() {
+        Some(SPL_TOKEN_2022)
+    } else {
+        None
+    }
+}
+
+// None for anything that isn't an initialized token account, including mints
+pub fn parse_token_account(owner: &[u8], data: &[u8]) -> Option<TokenAccount> {
+    match token_program(owner)? {
+        SPL_TOKEN => {
+            let account = spl_token::state::Account::unpack(data).ok()?;
+            Some(TokenAccount {
+                owner: account.owner.to_bytes(),
+                mint: account.mint.to_bytes(),
+                amount: account.amount,
+                token_program: SPL_TOKEN,
+                extensions: Value::Object(Map::new()),
+                authorities: TokenAuthorities {
+                    delegate: account.delegate.map(|delegate| delegate.to_string()).into(),
+                    delegated_amount: account.delegated_amount,
+                    frozen: account.is_frozen(),
+                    close_authority: account
+                        .close_authority
+                        .map(|authority| authority.to_string())
+                        .into(),
+                },
+            })
+        }
+        _ => {
+            // accounts with extensions are longer than 165 bytes, unpack handles both
+            let account = StateWithExtensions::<Token2022Account>::unpack(data).ok()?;
+            Some(TokenAccount {
+                owner: account.base.owner.to_bytes(),
+                mint: account.base.mint.to_bytes(),
+                amount: account.base.amount,
+                token_program: SPL_TOKEN_2022,
+                extensions: account_extensions(&account),
+                authorities: TokenAuthorities {
+                    delegate: account
+                        .base
+                        .delegate
+                        .map(|delegate| delegate.to_string())
+                        .into(),
+                    delegated_amount: account.base.delegated_amount,
+                    frozen: account.base.is_frozen(),
+                    close_authority: account
+                        .base
+                        .close_authority
+                        .map(|authority| authority.to_string())
+                        .into(),
+                },
+            })
+        }
+    }
+}
+
+// None for anything that isn't an initialized mint
+pub fn parse_mint(owner: &[u8], data: &[u8]) -> Option<TokenMint> {
+    match token_program(owner)? {
+        SPL_TOKEN => {
+            let mint = spl_token::state::Mint::unpack(data).ok()?;
+            Some(TokenMint {
+                decimals: mint.decimals,
+                supply: mint.supply,
+                mint_authority: mint
+                    .mint_authority
+                    .map(|authority| authority.to_string())
+                    .into(),
+                freeze_authority: mint
+                    .freeze_authority
+                    .map(|authority| authority.to_string())
+                    .into(),
+                token_program: SPL_TOKEN,
+                extensions: Value::Object(Map::new()),
+            })
+        }
+        _ => {
+            let mint = StateWithExtensions::<Token2022Mint>::unpack(data).ok()?;
+            Some(TokenMint {
+                decimals: mint.base.decimals,
+                supply: mint.base.supply,
+                mint_authority: mint
+                    .base
+                    .mint_authority
+                    .map(|authority| authority.to_string())
+                    .into(),
+                freeze_authority: mint
+                    .base
+                    .freeze_authority
+                    .map(|authority| authority.to_string())
+                    .into(),
+                token_program: SPL_TOKEN_2022,
+                extensions: mint_extensions(&mint),
+            })
+        }
+    }
+}
+
+// the `mints` row of a decoded mint
+pub fn mint_info(mint: TokenMint, slot: u64, write_version: u64) -> MintInfo {
+    MintInfo {
+        decimals: mint.decimals,
+        supply: mint.supply,
+        mint_authority: mint.mint_authority,
+        freeze_authority: mint.freeze_authority,
+        token_program: mint.token_program,
+        extensions: mint.extensions,
+        slot,
+        write_version,
+    }
+}
+
+// events for the changes from `old` to `new` on one token account, a holding seen for
+// the first time is compared against an account without any authorities
+pub fn security_events(
+    old: &TokenAuthorities,
+    new: &TokenAuthorities,
+) -> Vec<(&'static str, Value)> {
+    let mut events = Vec::new();
+
+    if new.delegate.is_some() && new.delegate != old.delegate {
+        events.push((
+            "delegate_approved",
+            json!({
+                "delegate": new.delegate,
+                "delegated_amount": new.delegated_amount,
+                "previous_delegate": old.delegate,
+            }),
+        ));
+    } else if new.delegate.is_some() && new.delegated_amount > old.delegated_amount {
+        events.push((
+            "delegated_amount_increased",
+            json!({
+                "delegate": new.delegate,
+                "delegated_amount": new.delegated_amount,
+                "previous_delegated_amount": old.delegated_amount,
+            }),
+        ));
+    }
+    if new.frozen && !old.frozen {
+        events.push(("account_frozen", json!({})));
+    }
+    if new.close_authority != old.close_authority {
+        events.push((
+            "close_authority_changed",
+            json!({
+                "close_authority": new.close_authority,
+                "previous_close_authority": old.close_authority,
+            }),
+        ));
+    }
+
+    events
+}
+
+fn account_extensions(account: &StateWithExtensions<Token2022Account>) -> Value {
+    let mut extensions = Map::new();
+
+    if let Ok(fee) = account.get_extension::<TransferFeeAmount>() {
+        extensions.insert(
+            "transfer_fee_amount".to_string(),
+            json!({ "withheld_amount": u64::from(fee.withheld_amount) }),
+        );
+    }
+    if account.get_extension::<NonTransferableAccount>().is_ok() {
+        extensions.insert("non_transferable".to_string(), Value::Bool(true));
+    }
+    if let Ok(confidential) = account.get_extension::<ConfidentialTransferAccount>() {
+        extensions.insert(
+            "confidential_transfer".to_string(),
+            json!({
+                "approved": bool::from(confidential.approved),
+                "allow_confidential_credits": bool::from(confidential.allow_confidential_credits),
+                "allow_non_confidential_credits": bool::from(confidential.allow_non_confidential_credits),
+                "pending_balance_credit_counter": u64::from(confidential.pending_balance_credit_counter),
+            }),
+        );
+    }
+    if let Ok(memo) = account.get_extension::<MemoTransfer>() {
+        extensions.insert(
+            "memo_transfer".to_string(),
+            json!({ "require_incoming_transfer_memos": bool::from(memo.require_incoming_transfer_memos) }),
+        );
+    }
+    if let Ok(guard) = account.get_extension::<CpiGuard>() {
+        extensions.insert(
+            "cpi_guard".to_string(),
+            json!({ "lock_cpi": bool::from(guard.lock_cpi) }),
+        );
+    }
+    if account.get_extension::<ImmutableOwner>().is_ok() {
+        extensions.insert("immutable_owner".to_string(), Value::Bool(true));
+    }
+
+    Value::Object(extensions)
+}
+
+fn mint_extensions(mint: &StateWithExtensions<Token2022Mint>) -> Value {
+    let mut extensions = Map::new();
+
+    if let Ok(config) = mint.get_extension::<TransferFeeConfig>() {
+        extensions.insert(
+            "transfer_fee_config".to_string(),
+            json!({
+                "transfer_fee_config_authority": optional_pubkey(config.transfer_fee_config_authority.into()),
+                "withdraw_withheld_authority": optional_pubkey(config.withdraw_withheld_authority.into()),
+                "withheld_amount": u64::from(config.withheld_amount),
+                "older_transfer_fee": transfer_fee(&config.older_transfer_fee),
+                "newer_transfer_fee": transfer_fee(&config.newer_transfer_fee),
+            }),
+        );
+    }
+    if let Ok(config) = mint.get_extension::<InterestBearingConfig>() {
+        extensions.insert(
+            "interest_bearing_config".to_string(),
+            json!({
+                "rate_authority": optional_pubkey(config.rate_authority.into()),
+                "initialization_timestamp": i64::from(config.initialization_timestamp),
+                "pre_update_average_rate": i16::from(config.pre_update_average_rate),
+                "last_update_timestamp": i64::from(config.last_update_timestamp),
+                "current_rate": i16::from(config.current_rate),
+            }),
+        );
+    }
+    if mint.get_extension::<NonTransferable>().is_ok() {
+        extensions.insert("non_transferable".to_string(), Value::Bool(true));
+    }
+    if let Ok(delegate) = mint.get_extension::<PermanentDelegate>() {
+        extensions.insert(
+            "permanent_delegate".to_string(),
+            json!({ "delegate": optional_pubkey(delegate.delegate.into()) }),
+        );
+    }
+    if let Ok(confidential) = mint.get_extension::<ConfidentialTransferMint>() {
+        extensions.insert(
+            "confidential_transfer_mint".to_string(),
+            json!({
+                "authority": optional_pubkey(confidential.authority.into()),
+                "auto_approve_new_accounts": bool::from(confidential.auto_approve_new_accounts),
+            }),
+        );
+    }
+
+    Value::Object(extensions)
+}
+
+fn transfer_fee(fee: &TransferFee) -> Value {
+    json!({
+        "epoch": u64::from(fee.epoch),
+        "maximum_fee": u64::from(fee.maximum_fee),
+        "transfer_fee_basis_points": u16::from(fee.transfer_fee_basis_points),
+    })
+}
+
+fn optional_pubkey(pubkey: Option<Pubkey>) -> Option<String> {
+    pubkey.map(|pubkey| pubkey.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spl_token_2022::solana_program::program_pack::Pack as Pack2022;
+
+    // extension type, its length and value, as Token-2022 lays them out after the base state
+    fn tlv(extension_type: u16, value: &[u8]) -> Vec<u8> {
+        let mut entry = extension_type.to_le_bytes().to_vec();
+        entry.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        entry.extend_from_slice(value);
+        entry
+    }
+
+    // a mint's base state is padded to the size of an account, then its account type
+    fn mint_with(extensions: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = vec![0; Token2022Mint::LEN];
+        Token2022Mint {
+            decimals: 6,
+            supply: 1_000,
+            is_initialized: true,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        data.resize(Token2022Account::LEN, 0);
+        data.push(1);
+        for extension in extensions {
+            data.extend_from_slice(extension);
+        }
+        data
+    }
+
+    fn account_with(extensions: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = vec![0; Token2022Account::LEN];
+        Token2022Account {
+            mint: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            amount: 5,
+            state: spl_token_2022::state::AccountState::Initialized,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        data.push(2);
+        for extension in extensions {
+            data.extend_from_slice(extension);
+        }
+        data
+    }
+
+    fn transfer_fee_bytes(epoch: u64, maximum_fee: u64, basis_points: u16) -> Vec<u8> {
+        let mut fee = epoch.to_le_bytes().to_vec();
+        fee.extend_from_slice(&maximum_fee.to_le_bytes());
+        fee.extend_from_slice(&basis_points.to_le_bytes());
+        fee
+    }
+
+    fn mint_extensions_of(data: &[u8]) -> Value {
+        parse_mint(spl_token_2022::ID.as_ref(), data)
+            .unwrap()
+            .extensions
+    }
+
+    fn authorities(delegate: Option<&str>, delegated_amount: u64) -> TokenAuthorities {
+        TokenAuthorities {
+            delegate: delegate.map(str::to_string),
+            delegated_amount,
+            ..TokenAuthorities::default()
+        }
+    }
+
+    fn event_names(old: &TokenAuthorities, new: &TokenAuthorities) -> Vec<&'static str> {
+        security_events(old, new)
+            .into_iter()
+            .map(|(event, _)| event)
+            .collect()
+    }
+
+    #[test]
+    fn raises_delegate_events() {
+        let none = TokenAuthorities::default();
+        let approved = authorities(Some("delegate"), 10);
+        let events = security_events(&none, &approved);
+        assert_eq!(
+            events,
+            [(
+                "delegate_approved",
+                json!({"delegate": "delegate", "delegated_amount": 10, "previous_delegate": null})
+            )]
+        );
+        // a new delegate is an approval, whatever the amount
+        assert_eq!(
+            event_names(&approved, &authorities(Some("other"), 5)),
+            ["delegate_approved"]
+        );
+
+        let events = security_events(&approved, &authorities(Some("delegate"), 25));
+        assert_eq!(
+            events,
+            [(
+                "delegated_amount_increased",
+                json!({
+                    "delegate": "delegate",
+                    "delegated_amount": 25,
+                    "previous_delegated_amount": 10,
+                })
+            )]
+        );
+        // spending the allowance or revoking it raises nothing
+        assert!(event_names(&approved, &authorities(Some("delegate"), 4)).is_empty());
+        assert!(event_names(&approved, &none).is_empty());
+    }
+
+    #[test]
+    fn raises_freeze_and_close_authority_events() {
+        let none = TokenAuthorities::default();
+        let frozen = TokenAuthorities {
+            frozen: true,
+            ..TokenAuthorities::default()
+        };
+        assert_eq!(
+            security_events(&none, &frozen),
+            [("account_frozen", json!({}))]
+        );
+        assert!(event_names(&frozen, &none).is_empty());
+
+        let closable = TokenAuthorities {
+            close_authority: Some("closer".to_string()),
+            ..TokenAuthorities::default()
+        };
+        assert_eq!(
+            security_events(&none, &closable),
+            [(
+                "close_authority_changed",
+                json!({"close_authority": "closer", "previous_close_authority": null})
+            )]
+        );
+        // removing it is a change too
+        assert_eq!(event_names(&closable, &none), ["close_authority_changed"]);
+    }
+
+    #[test]
+    fn raises_nothing_without_changes() {
+        let authorities = TokenAuthorities {
+            delegate: Some("delegate".to_string()),
+            delegated_amount: 10,
+            frozen: true,
+            close_authority: Some("closer".to_string()),
+        };
+        assert!(security_events(&authorities, &authorities).is_empty());
+        assert!(
+            security_events(&TokenAuthorities::default(), &TokenAuthorities::default()).is_empty()
+        );
+    }
+
+    #[test]
+    fn reads_transfer_fee_config() {
+        let authority = Pubkey::new_unique();
+        // an all-zero authority is none
+        let mut config = authority.to_bytes().to_vec();
+        config.extend_from_slice(&[0; 32]);
+        config.extend_from_slice(&25u64.to_le_bytes());
+        config.extend_from_slice(&transfer_fee_bytes(10, 5_000, 50));
+        config.extend_from_slice(&transfer_fee_bytes(12, 9_000, 75));
+
+        assert_eq!(
+            mint_extensions_of(&mint_with(&[tlv(1, &config)])),
+            json!({"transfer_fee_config": {
+                "transfer_fee_config_authority": authority.to_string(),
+                "withdraw_withheld_authority": null,
+                "withheld_amount": 25,
+                "older_transfer_fee": {"epoch": 10, "maximum_fee": 5_000, "transfer_fee_basis_points": 50},
+                "newer_transfer_fee": {"epoch": 12, "maximum_fee": 9_000, "transfer_fee_basis_points": 75},
+            }})
+        );
+    }
+
+    #[test]
+    fn reads_interest_bearing_config_and_flags() {
+        let delegate = Pubkey::new_unique();
+        let mut config = [0; 32].to_vec();
+        config.extend_from_slice(&1_700_000_000i64.to_le_bytes());
+        config.extend_from_slice(&(-20i16).to_le_bytes());
+        config.extend_from_slice(&1_700_000_500i64.to_le_bytes());
+        config.extend_from_slice(&350i16.to_le_bytes());
+
+        let data = mint_with(&[tlv(10, &config), tlv(9, &[]), tlv(12, delegate.as_ref())]);
+        assert_eq!(
+            mint_extensions_of(&data),
+            json!({
+                "interest_bearing_config": {
+                    "rate_authority": null,
+                    "initialization_timestamp": 1_700_000_000,
+                    "pre_update_average_rate": -20,
+                    "last_update_timestamp": 1_700_000_500,
+                    "current_rate": 350,
+                },
+                "non_transferable": true,
+                "permanent_delegate": {"delegate": delegate.to_string()},
+            })
+        );
+    }
+
+    #[test]
+    fn reads_account_extensions() {
+        let data = account_with(&[tlv(2, &7u64.to_le_bytes()), tlv(13, &[]), tlv(7, &[])]);
+        let account = parse_token_account(spl_token_2022::ID.as_ref(), &data).unwrap();
+        assert_eq!((account.amount, account.token_program), (5, SPL_TOKEN_2022));
+        assert_eq!(
+            account.extensions,
+            json!({
+                "transfer_fee_amount": {"withheld_amount": 7},
+                "non_transferable": true,
+                "immutable_owner": true,
+            })
+        );
+
+        // a plain 165-byte account has none
+        let account = parse_token_account(spl_token_2022::ID.as_ref(), &data[..165]).unwrap();
+        assert_eq!(account.extensions, json!({}));
+    }
+
+    #[test]
+    fn skips_unknown_extensions() {
+        // the mint still parses, extensions laid out before the unknown one are kept
+        let data = mint_with(&[tlv(9, &[]), tlv(0x7fff, &[1, 2, 3])]);
+        let mint = parse_mint(spl_token_2022::ID.as_ref(), &data).unwrap();
+        assert_eq!((mint.decimals, mint.supply), (6, 1_000));
+        assert_eq!(mint.extensions, json!({"non_transferable": true}));
+    }
+}
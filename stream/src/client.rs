@@ -13,6 +13,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     let request = tonic::Request::new(proto::StreamRequest {
         update_type: "all".to_string(), // or specify the type of updates you want
+        commitment: "processed".to_string(), // or "confirmed" / "finalized"
     });
 
     let mut stream = client.stream_listings(request).await?.into_inner();
@@ -29,6 +30,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
                     println!("  Mint: {}", listing.mint);
                     println!("  Funding Goal: {}", listing.funding_goal);
                     println!("  Funding Raised: {}", listing.funding_raised);
+                    println!("  Slot: {}", listing.slot);
                     println!("  Updated At: {}", listing.updated_at);
                     println!("-------------------");
                 }
@@ -36,9 +38,35 @@ async fn main() -> Result<(), Box<dyn Error>> {
                     println!("Received user assets update:");
                     println!("  Address: {}", assets.address);
                     println!("  SOL Balance: {}", assets.sol_balance);
+                    println!("  Slot: {}", assets.slot);
                     println!("  Updated At: {}", assets.updated_at);
                     println!("-------------------");
                 }
+                Some(proto::stream_response::Update::ProgramAccount(account)) => {
+                    println!("Received {} account update:", account.account_type);
+                    println!("  Account: {}", account.account);
+                    println!("  Program: {}", account.program_id);
+                    println!("  Data: {}", account.data);
+                    println!("  Slot: {}", account.slot);
+                    println!("  Updated At: {}", account.updated_at);
+                    println!("-------------------");
+                }
+                Some(proto::stream_response::Update::AccountClosed(closed)) => {
+                    println!("Received account closed:");
+                    println!("  Account: {}", closed.account);
+                    println!("  Table: {}", closed.table);
+                    println!("  Slot: {}", closed.slot);
+                    println!("-------------------");
+                }
+                Some(proto::stream_response::Update::ProgramEvent(event)) => {
+                    println!("Received {} event:", event.name);
+                    println!("  Signature: {}", event.signature);
+                    println!("  Program: {}", event.program_id);
+                    println!("  Instruction: {}", event.instruction_index);
+                    println!("  Data: {}", event.data);
+                    println!("  Slot: {}", event.slot);
+                    println!("-------------------");
+                }
                 None => println!("Received empty update"),
             },
             Err(e) => println!("Error receiving update: {:?}", e),
@@ -1,7 +1,7 @@
 use futures::Stream;
 use serde::Deserialize;
 use sqlx::{postgres::PgListener, Pool, Postgres, Row};
-use std::pin::Pin;
+use std::{collections::BTreeMap, pin::Pin};
 use tokio::sync::mpsc;
 use tonic::{transport::Server, Request, Response, Status};
 
@@ -16,6 +16,54 @@ use proto::listing_stream_server::{ListingStream, ListingStreamServer};
 struct NotifyPayload {
     account: String,
     action: String,
+    #[serde(default)]
+    account_type: Option<String>,
+    #[serde(default)]
+    table: Option<String>,
+    #[serde(default)]
+    slot: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SlotPayload {
+    slot: u64,
+    status: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Commitment {
+    Processed,
+    Confirmed,
+    Finalized,
+}
+
+impl Commitment {
+    fn parse(commitment: &str) -> Option<Self> {
+        match commitment {
+            "" | "processed" => Some(Commitment::Processed),
+            "confirmed" => Some(Commitment::Confirmed),
+            "finalized" => Some(Commitment::Finalized),
+            _ => None,
+        }
+    }
+
+    // as understood by the `slot_reaches` SQL function
+    fn as_str(&self) -> &'static str {
+        match self {
+            Commitment::Processed => "processed",
+            Commitment::Confirmed => "confirmed",
+            Commitment::Finalized => "finalized",
+        }
+    }
+
+    // slot statuses as reported by the plugin into the `slots` table
+    fn reached_by(&self, status: &str) -> bool {
+        match self {
+            Commitment::Processed => true,
+            Commitment::Confirmed => status == "confirmed" || status == "rooted",
+            Commitment::Finalized => status == "rooted",
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -29,7 +77,11 @@ impl ListingStreamService {
         Ok(Self { pool })
     }
 
-    async fn start_listener(&self, tx: mpsc::Sender<Result<proto::StreamResponse, Status>>) {
+    async fn start_listener(
+        &self,
+        tx: mpsc::Sender<Result<proto::StreamResponse, Status>>,
+        commitment: Commitment,
+    ) {
         let mut listener = match PgListener::connect_with(&self.pool).await {
             Ok(listener) => listener,
             Err(e) => {
@@ -38,7 +90,7 @@ impl ListingStreamService {
             }
         };
 
-        for channel in ["account_updates", "user_updates"] {
+        for channel in ["account_updates", "user_updates", "slot_updates"] {
             if let Err(e) = listener.listen(channel).await {
                 eprintln!("Failed to listen to channel {}: {:?}", channel, e);
                 return;
@@ -47,114 +99,471 @@ impl ListingStreamService {
 
         println!("Listening for updates...");
 
-        while let Some(notification) = listener.recv().await.ok() {
-            match serde_json::from_str::<NotifyPayload>(notification.payload()) {
-                Ok(payload) => {
-                    let result = match payload.action.as_str() {
-                        "account_update" => self.fetch_listing(&payload.account).await
-                            .map(|opt_listing| opt_listing.map(|l| 
-                                proto::StreamResponse {
-                                    update: Some(proto::stream_response::Update::Listing(l))
-                                }
-                            )),
-                        "user_update" => self.fetch_user_assets(&payload.account).await
-                            .map(|assets| Some(proto::StreamResponse {
-                                update: Some(proto::stream_response::Update::UserAssets(assets))
-                            })),
-                        _ => {
-                            eprintln!("Unknown action type: {}", payload.action);
-                            Ok(None)
-                        }
-                    };
+        // updates held back until their slot reaches the requested commitment
+        let mut pending: BTreeMap<u64, Vec<NotifyPayload>> = BTreeMap::new();
+
+        while let Ok(notification) = listener.recv().await {
+            if notification.channel() == "slot_updates" {
+                if commitment == Commitment::Processed {
+                    continue;
+                }
 
-                    match result {
-                        Ok(Some(response)) => {
-                            if let Err(e) = tx.send(Ok(response)).await {
-                                eprintln!("Failed to send update: {:?}", e);
-                            }
+                match serde_json::from_str::<SlotPayload>(notification.payload()) {
+                    Ok(slot_update) if commitment.reached_by(&slot_update.status) => {
+                        let newer = pending.split_off(&(slot_update.slot + 1));
+                        let ready = std::mem::replace(&mut pending, newer);
+
+                        // only the latest notification per account matters, the row is re-read anyway
+                        let mut latest = BTreeMap::new();
+                        for payload in ready.into_values().flatten() {
+                            latest.insert((payload.action.clone(), payload.account.clone()), payload);
                         }
-                        Ok(None) => {
-                            eprintln!("No data found for account: {}", payload.account);
+                        for payload in latest.into_values() {
+                            self.send_update(&tx, &payload, Some((commitment, slot_update.slot))).await;
                         }
-                        Err(e) => eprintln!("Failed to fetch data: {:?}", e),
                     }
+                    Ok(_) => {}
+                    Err(e) => eprintln!("Failed to parse slot notification payload: {:?}", e),
                 }
+                continue;
+            }
+
+            match serde_json::from_str::<NotifyPayload>(notification.payload()) {
+                Ok(payload) => match (commitment, payload.slot) {
+                    (Commitment::Processed, _) | (_, None) => {
+                        self.send_update(&tx, &payload, None).await
+                    }
+                    (_, Some(slot)) => pending.entry(slot).or_default().push(payload),
+                },
                 Err(e) => eprintln!("Failed to parse notification payload: {:?}", e),
             }
         }
     }
 
-    async fn fetch_user_assets(&self, account: &str) -> Result<proto::UserAssets, sqlx::Error> {
-        let table_name = format!("user_{}", account.replace(&['.' as char, '-' as char][..], "_"));
-        
-        let query = format!(
+    // `at` is the commitment being streamed and the slot that just reached it: rows are
+    // then read as of their newest version at or below that slot which reached the
+    // commitment, instead of as they are now
+    async fn send_update(
+        &self,
+        tx: &mpsc::Sender<Result<proto::StreamResponse, Status>>,
+        payload: &NotifyPayload,
+        at: Option<(Commitment, u64)>,
+    ) {
+        // one notification per transaction, sent on as one update per event
+        if payload.action == "program_events" {
+            match self.fetch_program_events(payload).await {
+                Ok(events) => {
+                    for event in events {
+                        let response = proto::StreamResponse {
+                            update: Some(proto::stream_response::Update::ProgramEvent(event)),
+                        };
+                        if let Err(e) = tx.send(Ok(response)).await {
+                            eprintln!("Failed to send update: {:?}", e);
+                        }
+                    }
+                }
+                Err(e) => eprintln!("Failed to fetch data: {:?}", e),
+            }
+            return;
+        }
+
+        let result = match payload.action.as_str() {
+            "account_update" => self.fetch_listing(&payload.account, at).await
+                .map(|opt_listing| opt_listing.map(|l|
+                    proto::StreamResponse {
+                        update: Some(proto::stream_response::Update::Listing(l))
+                    }
+                )),
+            "user_update" => self.fetch_user_assets(&payload.account, at).await
+                .map(|assets| Some(proto::StreamResponse {
+                    update: Some(proto::stream_response::Update::UserAssets(assets))
+                })),
+            "idl_account_update" => self.fetch_program_account(payload, at).await
+                .map(|opt_account| opt_account.map(|a|
+                    proto::StreamResponse {
+                        update: Some(proto::stream_response::Update::ProgramAccount(a))
+                    }
+                )),
+            // the row is gone, everything the client needs is in the notification
+            "account_closed" => Ok(Some(proto::StreamResponse {
+                update: Some(proto::stream_response::Update::AccountClosed(
+                    proto::AccountClosed {
+                        account: payload.account.clone(),
+                        table: payload.table.clone().unwrap_or_default(),
+                        slot: payload.slot.unwrap_or_default(),
+                    },
+                )),
+            })),
+            _ => {
+                eprintln!("Unknown action type: {}", payload.action);
+                Ok(None)
+            }
+        };
+
+        match result {
+            Ok(Some(response)) => {
+                if let Err(e) = tx.send(Ok(response)).await {
+                    eprintln!("Failed to send update: {:?}", e);
+                }
+            }
+            Ok(None) => {
+                if at.is_none() {
+                    eprintln!("No data found for account: {}", payload.account);
+                }
+            }
+            Err(e) => eprintln!("Failed to fetch data: {:?}", e),
+        }
+    }
+
+    // SOL and token balances honour `at` through their history, native accounts are the
+    // latest
+    async fn fetch_user_assets(
+        &self,
+        account: &str,
+        at: Option<(Commitment, u64)>,
+    ) -> Result<proto::UserAssets, sqlx::Error> {
+        let record = sqlx::query(
             r#"
-            SELECT 
-                CAST(sol_balance AS DOUBLE PRECISION) as sol_balance,
-                token_holdings::text as token_holdings,
-                nft_holdings::text as nft_holdings,
-                timestamp::text as updated_at
-            FROM {}
-            ORDER BY timestamp DESC
-            LIMIT 1
+            WITH a AS (
+                SELECT t.token_account, t.mint, t.amount, t.delegate, t.delegated_amount,
+                    t.state, t.close_authority, t.token_program, t.extensions, t.slot,
+                    t.updated_at
+                FROM token_balances t
+                WHERE t.wallet = $1 AND $2::bigint IS NULL
+                UNION ALL
+                (
+                    SELECT DISTINCT ON (h.mint, h.token_account) h.token_account, h.mint,
+                        h.amount, h.delegate, h.delegated_amount, h.state, h.close_authority,
+                        COALESCE(b.token_program, m.token_program), COALESCE(b.extensions, '{}'),
+                        h.slot, h.recorded_at
+                    FROM token_account_history h
+                    LEFT JOIN token_balances b
+                        ON b.wallet = h.wallet AND b.token_account = h.token_account
+                    LEFT JOIN mints m ON m.mint = h.mint
+                    WHERE h.wallet = $1 AND h.slot <= $2 AND slot_reaches(h.slot, $3)
+                    ORDER BY h.mint, h.token_account, h.slot DESC, h.write_version DESC
+                )
+            ), t AS (
+                -- one holding per mint, summed over the wallet's token accounts of it
+                SELECT a.mint, sum(a.amount) AS amount,
+                    ui_amount(sum(a.amount), min(m.decimals)) AS ui_amount,
+                    min(a.token_program) AS token_program,
+                    jsonb_agg(jsonb_build_object(
+                        'token_account', a.token_account,
+                        'amount', a.amount,
+                        'delegate', a.delegate,
+                        'delegated_amount', a.delegated_amount,
+                        'state', a.state,
+                        'close_authority', a.close_authority,
+                        'extensions', a.extensions
+                    ) ORDER BY a.token_account) FILTER (WHERE a.amount > 0) AS accounts,
+                    max(a.slot) AS slot, max(a.updated_at) AS updated_at
+                FROM a LEFT JOIN mints m ON m.mint = a.mint
+                GROUP BY a.mint
+            )
+            SELECT
+                CAST(COALESCE(sol.lamports, 0) AS DOUBLE PRECISION) / 1e9 AS sol_balance,
+                COALESCE(
+                    (SELECT jsonb_agg(jsonb_build_object(
+                        'mint', t.mint,
+                        'amount', t.amount,
+                        'ui_amount', t.ui_amount,
+                        'decimals', m.decimals,
+                        'token_program', t.token_program,
+                        'mint_extensions', COALESCE(m.extensions, '{}'::jsonb),
+                        'accounts', t.accounts
+                     ) ORDER BY t.mint)
+                     FROM t LEFT JOIN mints m ON m.mint = t.mint
+                     WHERE t.amount > 0),
+                    '[]'::jsonb
+                )::text AS token_holdings,
+                COALESCE(
+                    (SELECT jsonb_agg(jsonb_build_object(
+                        'mint', t.mint,
+                        'amount', t.amount,
+                        'ui_amount', t.ui_amount,
+                        'name', md.name,
+                        'symbol', md.symbol,
+                        'uri', md.uri,
+                        'token_standard', md.token_standard,
+                        'collection', md.collection,
+                        'collection_verified', md.collection_verified,
+                        'master_edition', e.mint IS NOT NULL
+                     ) ORDER BY t.mint)
+                     -- the `nft_holdings` view, over the holdings as of `at`
+                     FROM t
+                     LEFT JOIN mints m ON m.mint = t.mint
+                     LEFT JOIN nft_metadata md ON md.mint = t.mint
+                     LEFT JOIN master_editions e ON e.mint = t.mint
+                     WHERE t.amount > 0
+                        AND ((m.decimals = 0 AND m.supply = 1) OR e.mint IS NOT NULL)),
+                    '[]'::jsonb
+                )::text AS nft_holdings,
+                COALESCE(
+                    (SELECT jsonb_agg(jsonb_build_object(
+                        'account', s.account,
+                        'lamports', s.lamports,
+                        'staker', s.staker,
+                        'withdrawer', s.withdrawer,
+                        'voter', s.voter,
+                        'delegated_stake', s.delegated_stake,
+                        'activation_epoch', s.activation_epoch,
+                        'deactivation_epoch', s.deactivation_epoch,
+                        'activation', stake_activation(s.voter, s.activation_epoch, s.deactivation_epoch),
+                        'lockup_unix_timestamp', s.lockup_unix_timestamp,
+                        'lockup_epoch', s.lockup_epoch,
+                        'lockup_custodian', s.lockup_custodian
+                     ) ORDER BY s.account)
+                     FROM stake_accounts s
+                     WHERE s.staker = w.address OR s.withdrawer = w.address),
+                    '[]'::jsonb
+                )::text AS stake_accounts,
+                COALESCE(
+                    (SELECT jsonb_agg(jsonb_build_object(
+                        'account', v.account,
+                        'lamports', v.lamports,
+                        'node_pubkey', v.node_pubkey,
+                        'authorized_withdrawer', v.authorized_withdrawer,
+                        'authorized_voter', v.authorized_voter,
+                        'commission', v.commission,
+                        'credits', v.credits,
+                        'last_vote_slot', v.last_vote_slot,
+                        'root_slot', v.root_slot
+                     ) ORDER BY v.account)
+                     FROM vote_accounts v
+                     WHERE v.node_pubkey = w.address OR v.authorized_withdrawer = w.address),
+                    '[]'::jsonb
+                )::text AS vote_accounts,
+                COALESCE(
+                    (SELECT jsonb_agg(jsonb_build_object(
+                        'account', n.account,
+                        'lamports', n.lamports,
+                        'authority', n.authority,
+                        'blockhash', n.blockhash,
+                        'lamports_per_signature', n.lamports_per_signature
+                     ) ORDER BY n.account)
+                     FROM nonce_accounts n WHERE n.authority = w.address),
+                    '[]'::jsonb
+                )::text AS nonce_accounts,
+                GREATEST(
+                    w.updated_at,
+                    (SELECT max(t.updated_at) FROM t),
+                    (SELECT max(s.updated_at) FROM stake_accounts s
+                     WHERE s.staker = w.address OR s.withdrawer = w.address),
+                    (SELECT max(v.updated_at) FROM vote_accounts v
+                     WHERE v.node_pubkey = w.address OR v.authorized_withdrawer = w.address),
+                    (SELECT max(n.updated_at) FROM nonce_accounts n WHERE n.authority = w.address)
+                )::text AS updated_at,
+                GREATEST(
+                    sol.slot,
+                    (SELECT max(t.slot) FROM t),
+                    (SELECT max(s.slot) FROM stake_accounts s
+                     WHERE s.staker = w.address OR s.withdrawer = w.address),
+                    (SELECT max(v.slot) FROM vote_accounts v
+                     WHERE v.node_pubkey = w.address OR v.authorized_withdrawer = w.address),
+                    (SELECT max(n.slot) FROM nonce_accounts n WHERE n.authority = w.address)
+                ) AS slot
+            FROM wallets w
+            LEFT JOIN LATERAL (
+                SELECT h.lamports, h.slot FROM sol_balance_history h
+                WHERE h.wallet = w.address
+                    AND ($2::bigint IS NULL OR (h.slot <= $2 AND slot_reaches(h.slot, $3)))
+                ORDER BY h.slot DESC, h.write_version DESC
+                LIMIT 1
+            ) sol ON true
+            WHERE w.address = $1
             "#,
-            table_name
-        );
-
-        let record = sqlx::query(&query)
-            .fetch_one(&self.pool)
-            .await?;
+        )
+        .bind(account)
+        .bind(at.map(|(_, slot)| slot as i64))
+        .bind(at.map(|(commitment, _)| commitment.as_str()))
+        .fetch_one(&self.pool)
+        .await?;
 
         Ok(proto::UserAssets {
             address: account.to_string(),
             sol_balance: record.get("sol_balance"),
             token_holdings: record.get("token_holdings"),
             nft_holdings: record.get("nft_holdings"),
+            stake_accounts: record.get("stake_accounts"),
+            vote_accounts: record.get("vote_accounts"),
+            nonce_accounts: record.get("nonce_accounts"),
             updated_at: record.get("updated_at"),
+            slot: record.get::<Option<i64>, _>("slot").unwrap_or_default() as u64,
         })
     }
 
-    async fn fetch_listing(&self, account: &str) -> Result<Option<proto::Listing>, sqlx::Error> {
-        let record = sqlx::query!(
+    async fn fetch_program_account(
+        &self,
+        payload: &NotifyPayload,
+        at: Option<(Commitment, u64)>,
+    ) -> Result<Option<proto::ProgramAccount>, sqlx::Error> {
+        // table names come from the plugin's IDL config, but never trust them into SQL blindly
+        let table = match payload.table.as_deref() {
+            Some(table)
+                if !table.is_empty()
+                    && table
+                        .chars()
+                        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_') =>
+            {
+                table
+            }
+            _ => {
+                eprintln!("Invalid table in notification payload: {:?}", payload.table);
+                return Ok(None);
+            }
+        };
+
+        // a closed account's newest version has no data, and no program id
+        let query = format!(
+            r#"
+            SELECT
+                account,
+                program_id,
+                data::text as data,
+                updated_at::text as updated_at,
+                slot
+            FROM "{}"
+            WHERE account = $1 AND $2::bigint IS NULL
+            UNION ALL
+            SELECT * FROM (
+                SELECT
+                    account,
+                    data ->> 'program_id' AS program_id,
+                    (data -> 'data')::text AS data,
+                    recorded_at::text AS updated_at,
+                    slot
+                FROM account_versions
+                WHERE table_name = $4 AND account = $1 AND slot <= $2 AND slot_reaches(slot, $3)
+                ORDER BY slot DESC, write_version DESC
+                LIMIT 1
+            ) v
+            WHERE v.program_id IS NOT NULL
+            "#,
+            table
+        );
+
+        let record = sqlx::query(&query)
+            .bind(&payload.account)
+            .bind(at.map(|(_, slot)| slot as i64))
+            .bind(at.map(|(commitment, _)| commitment.as_str()))
+            .bind(table)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(record.map(|r| proto::ProgramAccount {
+            account: r.get("account"),
+            program_id: r.get("program_id"),
+            account_type: payload.account_type.clone().unwrap_or_default(),
+            data: r.get("data"),
+            updated_at: r.get::<Option<String>, _>("updated_at").unwrap_or_default(),
+            slot: r.get::<Option<i64>, _>("slot").unwrap_or_default() as u64,
+        }))
+    }
+
+    // `account` carries the transaction's signature
+    async fn fetch_program_events(
+        &self,
+        payload: &NotifyPayload,
+    ) -> Result<Vec<proto::ProgramEvent>, sqlx::Error> {
+        let records = sqlx::query(
+            r#"
+            SELECT
+                signature,
+                slot,
+                event_index,
+                instruction_index,
+                program_id,
+                name,
+                data::text as data
+            FROM events
+            WHERE signature = $1 AND slot = $2
+            ORDER BY event_index
+            "#,
+        )
+        .bind(&payload.account)
+        .bind(payload.slot.unwrap_or_default() as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(records
+            .into_iter()
+            .map(|r| proto::ProgramEvent {
+                signature: r.get("signature"),
+                slot: r.get::<i64, _>("slot") as u64,
+                event_index: r.get::<i32, _>("event_index") as u32,
+                instruction_index: r.get::<i32, _>("instruction_index") as u32,
+                program_id: r.get("program_id"),
+                name: r.get("name"),
+                data: r.get("data"),
+            })
+            .collect())
+    }
+
+    async fn fetch_listing(
+        &self,
+        account: &str,
+        at: Option<(Commitment, u64)>,
+    ) -> Result<Option<proto::Listing>, sqlx::Error> {
+        let record = sqlx::query(
             r#"
-            SELECT 
+            WITH l AS (
+                SELECT * FROM listings WHERE account = $1 AND $2::bigint IS NULL
+                UNION ALL
+                SELECT l.*
+                FROM (
+                    SELECT data, recorded_at FROM account_versions
+                    WHERE table_name = 'listings' AND account = $1 AND slot <= $2
+                        AND slot_reaches(slot, $3)
+                    ORDER BY slot DESC, write_version DESC
+                    LIMIT 1
+                ) v
+                CROSS JOIN LATERAL jsonb_populate_record(
+                    NULL::listings, v.data || jsonb_build_object('updated_at', v.recorded_at)
+                ) l
+                WHERE v.data IS NOT NULL
+            )
+            SELECT
                 account,
                 name,
-                seed as "seed!: i64",
+                seed,
                 mint,
-                funding_goal as "funding_goal!: i64",
+                funding_goal,
                 pool_mint_supply::text,
-                funding_raised as "funding_raised!: i64",
+                funding_raised,
                 available_tokens::text,
                 base_price,
                 tokens_sold::text,
-                bump as "bump!: i16",
-                vault_bump as "vault_bump!: i16",
-                mint_bump as "mint_bump!: i16",
-                updated_at::text
-            FROM listings 
-            WHERE account = $1
+                bump,
+                vault_bump,
+                mint_bump,
+                updated_at::text,
+                slot
+            FROM l
             "#,
-            account
         )
+        .bind(account)
+        .bind(at.map(|(_, slot)| slot as i64))
+        .bind(at.map(|(commitment, _)| commitment.as_str()))
         .fetch_optional(&self.pool)
         .await?;
 
         Ok(record.map(|r| proto::Listing {
-            account: r.account,
-            name: r.name,
-            seed: r.seed as u64,
-            mint: r.mint,
-            funding_goal: r.funding_goal as u64,
-            pool_mint_supply: r.pool_mint_supply.unwrap_or_default(),
-            funding_raised: r.funding_raised as u64,
-            available_tokens: r.available_tokens.unwrap_or_default(),
-            base_price: r.base_price,
-            tokens_sold: r.tokens_sold.unwrap_or_default(),
-            bump: r.bump as u32,
-            vault_bump: r.vault_bump as u32,
-            mint_bump: r.mint_bump as u32,
-            updated_at: r.updated_at.unwrap_or_default(),
+            account: r.get("account"),
+            name: r.get("name"),
+            seed: r.get::<i64, _>("seed") as u64,
+            mint: r.get("mint"),
+            funding_goal: r.get::<i64, _>("funding_goal") as u64,
+            pool_mint_supply: r.get("pool_mint_supply"),
+            funding_raised: r.get::<i64, _>("funding_raised") as u64,
+            available_tokens: r.get("available_tokens"),
+            base_price: r.get("base_price"),
+            tokens_sold: r.get("tokens_sold"),
+            bump: r.get::<i16, _>("bump") as u32,
+            vault_bump: r.get::<i16, _>("vault_bump") as u32,
+            mint_bump: r.get::<i16, _>("mint_bump") as u32,
+            updated_at: r.get::<Option<String>, _>("updated_at").unwrap_or_default(),
+            slot: r.get::<Option<i64>, _>("slot").unwrap_or_default() as u64,
         }))
     }
 }
@@ -166,14 +575,18 @@ impl ListingStream for ListingStreamService {
 
     async fn stream_listings(
         &self,
-        _request: Request<proto::StreamRequest>,
+        request: Request<proto::StreamRequest>,
     ) -> Result<Response<Self::StreamListingsStream>, Status> {
+        let commitment = Commitment::parse(&request.get_ref().commitment).ok_or_else(|| {
+            Status::invalid_argument("commitment must be processed, confirmed or finalized")
+        })?;
+
         let (tx, rx) = mpsc::channel(100);
 
         let service = self.clone();
 
         tokio::spawn(async move {
-            service.start_listener(tx).await;
+            service.start_listener(tx, commitment).await;
         });
 
         let output_stream = tokio_stream::wrappers::ReceiverStream::new(rx);
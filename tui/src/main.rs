@@ -1,3 +1,4 @@
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
 use ratatui::{style::Stylize, text::Line, widgets::Widget, DefaultTerminal, Frame};
 
 fn main() -> std::io::Result<()>{
@@ -19,6 +20,17 @@ impl App {
     fn run(&mut self, terminal: &mut DefaultTerminal) -> std::io::Result<()> {
         while !self.exit {
             terminal.draw(|frame| self.draw(frame))?;
+            self.handle_events()?;
+        }
+
+        Ok(())
+    }
+
+    fn handle_events(&mut self) -> std::io::Result<()> {
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press && key.code == KeyCode::Char('q') {
+                self.exit = true;
+            }
         }
 
         Ok(())